@@ -0,0 +1,5 @@
+fn main() {
+    // protoc embarqué : aucune installation système requise
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("protoc binary"));
+    tonic_prost_build::compile_protos("proto/events.proto").expect("failed to compile events.proto");
+}
@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Plage d'adresses annoncée par un système autonome
+struct AsnRange {
+    start: u128,
+    end: u128,
+    asn: u32,
+    name: String,
+}
+
+/// Base IP vers ASN chargée au démarrage (format TSV ip2asn : début, fin, ASN, pays, description)
+pub struct AsnDb {
+    v4: Vec<AsnRange>,
+    v6: Vec<AsnRange>,
+}
+
+fn ip_key(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+impl AsnDb {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open ASN database: {:?}", path))?;
+        let mut db = AsnDb { v4: Vec::new(), v6: Vec::new() };
+        
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let columns: Vec<&str> = line.split('\t').collect();
+            let parsed = match columns.as_slice() {
+                [start, end, asn, _country, rest @ ..] => {
+                    match (start.parse::<IpAddr>(), end.parse::<IpAddr>(), asn.parse::<u32>()) {
+                        (Ok(start), Ok(end), Ok(asn)) if start.is_ipv4() == end.is_ipv4() => {
+                            Some((start, end, asn, rest.first().copied().unwrap_or("")))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            let Some((start, end, asn, name)) = parsed else {
+                return Err(anyhow::anyhow!("Invalid ASN database line {} in {:?}", number + 1, path));
+            };
+            // ASN 0 : plage non annoncée
+            if asn == 0 {
+                continue;
+            }
+            let range = AsnRange { start: ip_key(start), end: ip_key(end), asn, name: name.to_string() };
+            if start.is_ipv4() { db.v4.push(range) } else { db.v6.push(range) }
+        }
+        
+        db.v4.sort_by_key(|range| range.start);
+        db.v6.sort_by_key(|range| range.start);
+        Ok(db)
+    }
+    
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+    
+    /// ASN et description de l'adresse, si elle appartient à une plage annoncée
+    pub fn lookup(&self, ip: IpAddr) -> Option<(u32, &str)> {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            ip => ip,
+        };
+        let ranges = if ip.is_ipv4() { &self.v4 } else { &self.v6 };
+        let key = ip_key(ip);
+        let index = ranges.partition_point(|range| range.start <= key).checked_sub(1)?;
+        let range = &ranges[index];
+        (key <= range.end).then_some((range.asn, range.name.as_str()))
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// IP bannies (--ban-score, commande de contrôle `ban`), levées après --ban-ttl
+///
+/// Au-delà de --max-banned, le plus ancien bannissement est oublié : un scan distribué
+/// ne fait pas croître la mémoire sans limite.
+pub struct BanList {
+    ttl: Duration,
+    max: usize,
+    banned: HashMap<IpAddr, Instant>,
+    /// Bannissements dans leur ordre ; une entrée levée ou renouvelée depuis est ignorée
+    order: VecDeque<(IpAddr, Instant)>,
+}
+
+impl BanList {
+    pub fn new(ttl: Duration, max: usize) -> Self {
+        Self { ttl, max, banned: HashMap::new(), order: VecDeque::new() }
+    }
+    
+    /// Bannit l'IP ; faux si elle l'était déjà
+    pub fn insert(&mut self, ip: IpAddr) -> bool {
+        self.insert_at(ip, Instant::now())
+    }
+    
+    fn insert_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if self.contains_at(ip, now) {
+            return false;
+        }
+        if self.banned.len() >= self.max {
+            self.evict_oldest();
+        }
+        self.banned.insert(ip, now);
+        self.order.push_back((ip, now));
+        // Bannir/lever en boucle laisse des entrées périmées : compactage occasionnel
+        if self.order.len() > 2 * self.max {
+            let banned = &self.banned;
+            self.order.retain(|(ip, at)| banned.get(ip) == Some(at));
+        }
+        true
+    }
+    
+    /// Lève le bannissement ; faux si l'IP n'était pas bannie
+    pub fn remove(&mut self, ip: &IpAddr) -> bool {
+        self.banned.remove(ip).is_some()
+    }
+    
+    pub fn contains(&mut self, ip: IpAddr) -> bool {
+        self.contains_at(ip, Instant::now())
+    }
+    
+    fn contains_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        match self.banned.get(&ip) {
+            Some(at) if now.duration_since(*at) < self.ttl => true,
+            Some(_) => {
+                self.banned.remove(&ip);
+                false
+            }
+            None => false,
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.banned.len()
+    }
+    
+    /// Oublie les bannissements expirés
+    pub fn prune(&mut self) {
+        self.prune_at(Instant::now());
+    }
+    
+    fn prune_at(&mut self, now: Instant) {
+        while let Some(&(ip, at)) = self.order.front() {
+            let current = self.banned.get(&ip) == Some(&at);
+            if current && now.duration_since(at) < self.ttl {
+                break;
+            }
+            if current {
+                self.banned.remove(&ip);
+            }
+            self.order.pop_front();
+        }
+    }
+    
+    fn evict_oldest(&mut self) {
+        while let Some((ip, at)) = self.order.pop_front() {
+            if self.banned.get(&ip) == Some(&at) {
+                self.banned.remove(&ip);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, last])
+    }
+    
+    #[test]
+    fn bans_expire_after_ttl() {
+        let start = Instant::now();
+        let mut bans = BanList::new(Duration::from_secs(60), 10);
+        assert!(bans.insert_at(ip(1), start));
+        assert!(!bans.insert_at(ip(1), start + Duration::from_secs(1)));
+        assert!(bans.contains_at(ip(1), start + Duration::from_secs(59)));
+        assert!(!bans.contains_at(ip(1), start + Duration::from_secs(60)));
+        assert_eq!(bans.len(), 0);
+        
+        bans.insert_at(ip(2), start);
+        bans.insert_at(ip(3), start + Duration::from_secs(30));
+        bans.prune_at(start + Duration::from_secs(61));
+        assert_eq!(bans.len(), 1);
+        assert!(bans.contains_at(ip(3), start + Duration::from_secs(61)));
+    }
+    
+    #[test]
+    fn oldest_ban_is_evicted_at_capacity() {
+        let start = Instant::now();
+        let mut bans = BanList::new(Duration::from_secs(3600), 3);
+        for last in 1..=3 {
+            bans.insert_at(ip(last), start + Duration::from_secs(last as u64));
+        }
+        // Levée puis nouveau bannissement : l'IP 1 devient la plus récente
+        assert!(bans.remove(&ip(1)));
+        bans.insert_at(ip(1), start + Duration::from_secs(10));
+        bans.insert_at(ip(4), start + Duration::from_secs(11));
+        
+        assert_eq!(bans.len(), 3);
+        assert!(!bans.contains_at(ip(2), start + Duration::from_secs(12)));
+        for last in [1, 3, 4] {
+            assert!(bans.contains_at(ip(last), start + Duration::from_secs(12)), "{} evicted", last);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::utils::FileModes;
+
+/// Index des captures (--index-file) : une ligne JSON par message enregistré, en ajout seul
+///
+/// Rotation quotidienne : au premier ajout d'un nouveau jour, le fichier de la veille est
+/// renommé en `<fichier>.AAAA-MM-JJ` (date de sa dernière écriture) et un fichier neuf est ouvert.
+pub struct CaptureIndex {
+    path: PathBuf,
+    modes: FileModes,
+    /// Fichier ouvert et jour de ses entrées
+    current: Mutex<Option<(NaiveDate, tokio::fs::File)>>,
+}
+
+impl CaptureIndex {
+    pub fn new(path: PathBuf, modes: FileModes) -> Self {
+        Self { path, modes, current: Mutex::new(None) }
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Ajoute une entrée (sans fin de ligne)
+    pub async fn append(&self, entry: &str) -> io::Result<()> {
+        let today = Local::now().date_naive();
+        let mut current = self.current.lock().await;
+        if current.as_ref().map(|(day, _)| *day) != Some(today) {
+            *current = None;
+            self.rotate(today).await?;
+            let file = tokio::fs::OpenOptions::from(self.modes.open_options())
+                .append(true)
+                .create(true)
+                .open(&self.path)
+                .await?;
+            *current = Some((today, file));
+        }
+        
+        let Some((_, file)) = current.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(format!("{}\n", entry).as_bytes()).await?;
+        file.flush().await
+    }
+    
+    /// Fichier existant écrit un autre jour : mis de côté sous sa date
+    async fn rotate(&self, today: NaiveDate) -> io::Result<()> {
+        let Ok(metadata) = tokio::fs::metadata(&self.path).await else {
+            return Ok(());
+        };
+        let day = DateTime::<Local>::from(metadata.modified()?).date_naive();
+        if day == today || metadata.len() == 0 {
+            return Ok(());
+        }
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", day.format("%Y-%m-%d")));
+        tokio::fs::rename(&self.path, &rotated).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    
+    fn index_in(name: &str) -> (PathBuf, CaptureIndex) {
+        let dir = std::env::temp_dir().join(format!("smtp-honeypot-index-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.jsonl");
+        (dir, CaptureIndex::new(path, FileModes { file: 0o600, dir: 0o700 }))
+    }
+    
+    #[tokio::test]
+    async fn entries_are_appended_one_per_line() {
+        let (dir, index) = index_in("append");
+        index.append("{\"n\":1}").await.unwrap();
+        index.append("{\"n\":2}").await.unwrap();
+        assert_eq!(std::fs::read_to_string(index.path()).unwrap(), "{\"n\":1}\n{\"n\":2}\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn file_from_an_earlier_day_is_rotated() {
+        let (dir, index) = index_in("rotate");
+        std::fs::write(index.path(), "{\"n\":0}\n").unwrap();
+        let earlier = SystemTime::now() - Duration::from_secs(3 * 24 * 3600);
+        std::fs::File::options().write(true).open(index.path()).unwrap().set_modified(earlier).unwrap();
+        
+        index.append("{\"n\":1}").await.unwrap();
+        let day = DateTime::<Local>::from(earlier).date_naive();
+        let mut rotated = index.path().as_os_str().to_owned();
+        rotated.push(format!(".{}", day.format("%Y-%m-%d")));
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap(), "{\"n\":0}\n");
+        assert_eq!(std::fs::read_to_string(index.path()).unwrap(), "{\"n\":1}\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
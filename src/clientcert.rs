@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use openssl::sha::Sha256;
+use openssl::x509::{X509, X509NameRef};
+use rustls::server::{ClientCertVerified, ClientCertVerifier};
+use rustls::{Certificate, DistinguishedName};
+
+use crate::utils;
+
+/// Vérificateur qui demande un certificat client sans l'exiger et accepte tout certificat présenté
+///
+/// `AllowAnyAnonymousOrAuthenticatedClient` rejetterait les certificats qui ne remontent pas
+/// à une racine de confiance, et n'envoie aucune demande sans racine : on veut au contraire
+/// recueillir ce que le client présente. La signature de la poignée reste vérifiée
+/// (implémentation par défaut), ce qui prouve la possession de la clé.
+struct AnyClientCert {
+    /// Autorités annoncées dans la demande : l'émetteur de notre propre certificat
+    subjects: Vec<DistinguishedName>,
+}
+
+impl ClientCertVerifier for AnyClientCert {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+    
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+    
+    fn client_auth_root_subjects(&self) -> &[DistinguishedName] {
+        &self.subjects
+    }
+    
+    fn verify_client_cert(&self, _end_entity: &Certificate, _intermediates: &[Certificate],
+                          _now: SystemTime) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+/// Vérificateur de --request-client-cert, construit à partir du certificat du serveur
+pub fn verifier(server_cert: &Certificate) -> Result<Arc<dyn ClientCertVerifier>> {
+    let x509 = X509::from_der(&server_cert.0).context("Failed to decode server certificate")?;
+    let issuer = x509.issuer_name().to_der().context("Failed to encode certificate issuer")?;
+    Ok(Arc::new(AnyClientCert { subjects: vec![DistinguishedName::from(issuer)] }))
+}
+
+/// Certificat présenté par le client lors de la poignée de main
+#[derive(Debug, Clone)]
+pub struct ClientCert {
+    pub subject: String,
+    pub issuer: String,
+    /// Empreinte SHA-256 du certificat DER, en hexadécimal
+    pub sha256: String,
+}
+
+impl ClientCert {
+    pub fn from_der(der: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(der);
+        let sha256 = utils::hex(&hasher.finish());
+        // Certificat illisible : seule l'empreinte est conservée
+        match X509::from_der(der) {
+            Ok(x509) => Self {
+                subject: format_name(x509.subject_name()),
+                issuer: format_name(x509.issuer_name()),
+                sha256,
+            },
+            Err(_) => Self { subject: "-".to_string(), issuer: "-".to_string(), sha256 },
+        }
+    }
+}
+
+impl std::fmt::Display for ClientCert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "subject=\"{}\" issuer=\"{}\" sha256={}", self.subject, self.issuer, self.sha256)
+    }
+}
+
+/// Nom distinctif au format `CN=..., O=...`, valeurs filtrées pour le journal
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string()
+                .unwrap_or_else(|_| utils::hex(entry.data().as_slice()));
+            format!("{}={}", key, utils::safe_log_string(&value))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::honeypot::SmtpHoneypot;
+use crate::utils::FileModes;
+
+/// Socket de contrôle local (--control-socket) : une commande texte par ligne
+/// (`stats`, `ban <ip>`, `unban <ip>`, `reload`, `drain`, `recent [n]`), une réponse `OK ...` ou `ERR ...` par ligne
+///
+/// L'accès est restreint par les permissions du fichier socket (--file-mode), posées avant
+/// que le socket n'apparaisse à son emplacement.
+#[cfg(unix)]
+pub fn spawn(path: &Path, modes: FileModes, honeypot: Arc<SmtpHoneypot>) -> Result<()> {
+    use anyhow::Context;
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+    
+    // Socket laissé par une exécution précédente ; tout autre fichier est conservé
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow::anyhow!("--control-socket {:?} exists and is not a socket", path));
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale control socket {:?}", path))?;
+    }
+    
+    // Bind dans un répertoire privé voisin, puis renommage une fois les permissions posées :
+    // le socket n'est jamais joignable avec les permissions issues de l'umask
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut private_name = std::ffi::OsString::from(".");
+    private_name.push(path.file_name().unwrap_or_default());
+    private_name.push(format!(".{}.tmp", std::process::id()));
+    let private = parent.join(private_name);
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)
+        .with_context(|| format!("Failed to create private directory {:?} for the control socket", private))?;
+    let staged = private.join("control.sock");
+    let bound = UnixListener::bind(&staged)
+        .with_context(|| format!("Failed to bind control socket {:?}", path))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(modes.file))
+                .with_context(|| format!("Failed to set permissions on control socket {:?}", path))?;
+            std::fs::rename(&staged, path)
+                .with_context(|| format!("Failed to move control socket to {:?}", path))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    let listener = bound?;
+    eprintln!("[INFO] Control socket listening on {:?}", path);
+    
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("[WARNING] Control socket accept failed: {}", e);
+                    continue;
+                }
+            };
+            let honeypot = honeypot.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let response = honeypot.control_command(line.trim()).await;
+                    if writer.write_all(format!("{}\n", response).as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn spawn(_path: &Path, _modes: FileModes, _honeypot: Arc<SmtpHoneypot>) -> Result<()> {
+    Err(anyhow::anyhow!("--control-socket is only supported on Unix"))
+}
@@ -0,0 +1,310 @@
+use chrono::{DateTime, Local};
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::utils::{self, Severity};
+
+/// Version du format des événements (--print-event-schema)
+pub const EVENT_SCHEMA_VERSION: u32 = 5;
+
+/// Types d'événements émis vers les sorties structurées
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Connection,
+    /// Connexion refusée avant ou pendant la session SMTP, avec sa raison
+    Rejection,
+    Auth,
+    Alert,
+    Capture,
+    Transaction,
+    /// Boîte aux lettres leurre (--honey-mailbox) visée par RCPT ou VRFY
+    Probe,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 7] = [
+        EventKind::Connection,
+        EventKind::Rejection,
+        EventKind::Auth,
+        EventKind::Alert,
+        EventKind::Capture,
+        EventKind::Transaction,
+        EventKind::Probe,
+    ];
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Connection => "connection",
+            EventKind::Rejection => "rejection",
+            EventKind::Auth => "auth",
+            EventKind::Alert => "alert",
+            EventKind::Capture => "capture",
+            EventKind::Transaction => "transaction",
+            EventKind::Probe => "probe",
+        }
+    }
+    
+    /// Champs nommés que peut porter ce type, tous des chaînes ; à tenir à jour avec
+    /// EVENT_SCHEMA_VERSION à chaque champ ajouté, renommé ou retiré
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            EventKind::Connection => &["port", "ttl", "mss", "tcp_options", "os_guess", "asn", "asn_name", "asn_flagged",
+                                       "rate_limit_delay_s", "helo", "mail_from", "rcpt_count", "data_bytes", "tls",
+                                       "auth_attempts", "disposition", "rate_limited_commands", "line_endings",
+                                       "score", "score_signals"],
+            EventKind::Rejection => &["reason", "port", "asn", "asn_name", "banned_response", "probe"],
+            EventKind::Auth => &["mechanism", "auth_line", "raw", "decoded", "enumeration"],
+            EventKind::Alert => &["alert", "severity", "mail_from", "mail_auth", "rcpt_to", "signatures", "line_endings",
+                                  "crlf_lines", "bare_lf_lines", "bare_cr_lines", "score", "score_signals", "count",
+                                  "coalesced", "window_s"],
+            EventKind::Capture => &["filename", "data_dir", "policy", "mode", "sha256", "size", "truncated"],
+            EventKind::Transaction => &["helo", "helo_class", "mail_from", "rcpt_to", "signals", "relay_attempted",
+                                        "would_reject", "size_declared", "size", "command_gaps", "gap_min_ms",
+                                        "gap_median_ms", "gap_max_ms", "score", "score_signals", "mode", "retry_of",
+                                        "retry_attempt", "retry_interval_s", "command_format", "line_endings",
+                                        "client_cert_subject", "client_cert_issuer", "client_cert_sha256",
+                                        "auth_advertised", "tls_source", "tls_version", "tls_cipher", "tls_sni",
+                                        "tls_handshake", "tls_resumed_id", "tls_issued_id", "header_section",
+                                        "header_count", "header_from", "header_to", "header_cc", "header_reply_to",
+                                        "header_subject", "header_date", "header_message_id", "header_content_type",
+                                        "auth_mechanisms", "auth_enumerations"],
+            EventKind::Probe => &["mailbox", "command", "hits", "mail_from"],
+        }
+    }
+    
+    pub fn severity(&self) -> Severity {
+        match self {
+            EventKind::Connection | EventKind::Rejection => Severity::Low,
+            _ => Severity::Normal,
+        }
+    }
+    
+    /// Identifiant de signature CEF
+    fn cef_signature(&self) -> u32 {
+        match self {
+            EventKind::Connection => 100,
+            EventKind::Rejection => 150,
+            EventKind::Auth => 200,
+            EventKind::Alert => 300,
+            EventKind::Capture => 400,
+            EventKind::Transaction => 500,
+            EventKind::Probe => 250,
+        }
+    }
+    
+    /// Gravité CEF (0-10)
+    fn cef_severity(&self) -> u8 {
+        match self {
+            EventKind::Connection => 1,
+            EventKind::Rejection => 2,
+            EventKind::Transaction => 3,
+            EventKind::Probe => 4,
+            EventKind::Capture => 5,
+            EventKind::Auth => 6,
+            EventKind::Alert => 8,
+        }
+    }
+    
+    fn cef_name(&self) -> &'static str {
+        match self {
+            EventKind::Connection => "SMTP connection",
+            EventKind::Rejection => "SMTP connection rejected",
+            EventKind::Auth => "SMTP authentication attempt",
+            EventKind::Alert => "SMTP honeypot alert",
+            EventKind::Capture => "SMTP message captured",
+            EventKind::Transaction => "SMTP transaction",
+            EventKind::Probe => "SMTP honey mailbox probed",
+        }
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventKind::ALL.iter()
+            .find(|kind| kind.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown event type '{}'", s))
+    }
+}
+
+/// Sorties vers lesquelles un type d'événement peut être routé (--route)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SinkName {
+    /// Ligne texte sur la sortie standard
+    Stdout,
+    /// Ligne texte dans le fichier journal (--log-file)
+    File,
+    /// Sortie CEF (--cef-url)
+    Cef,
+    /// Flux gRPC (--grpc-port)
+    Grpc,
+    /// Journal d'événements Windows (--windows-event-log)
+    EventLog,
+    /// Tampon des derniers événements (--recent-events)
+    Recent,
+}
+
+impl SinkName {
+    pub const ALL: [SinkName; 6] = [SinkName::Stdout, SinkName::File, SinkName::Cef, SinkName::Grpc,
+                                    SinkName::EventLog, SinkName::Recent];
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SinkName::Stdout => "stdout",
+            SinkName::File => "file",
+            SinkName::Cef => "cef",
+            SinkName::Grpc => "grpc",
+            SinkName::EventLog => "eventlog",
+            SinkName::Recent => "recent",
+        }
+    }
+}
+
+impl FromStr for SinkName {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SinkName::ALL.iter()
+            .find(|sink| sink.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown sink '{}' (expected stdout, file, cef, grpc, eventlog or recent)", s))
+    }
+}
+
+/// Route `type=sortie[,sortie...]` : sorties recevant un type d'événement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub kind: EventKind,
+    pub sinks: Vec<SinkName>,
+}
+
+impl FromStr for Route {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, sinks) = s.split_once('=')
+            .ok_or_else(|| format!("invalid route '{}' (expected type=sink[,sink...])", s))?;
+        Ok(Route {
+            kind: kind.trim().parse()?,
+            sinks: sinks.split(',')
+                .map(|sink| sink.trim().parse())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Événement structuré : un message lisible plus des champs nommés
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub kind: EventKind,
+    pub timestamp: DateTime<Local>,
+    pub client_addr: SocketAddr,
+    pub message: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, client_addr: SocketAddr, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            timestamp: Local::now(),
+            client_addr,
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+    
+    pub fn with(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.fields.push((key, value.to_string()));
+        self
+    }
+}
+
+/// Schéma JSON (draft 2020-12) d'une ligne NDJSON du journal
+///
+/// Les lignes issues d'un événement portent `type` et les champs de ce type ; les autres
+/// lignes du journal n'ont que `timestamp`, `client` et `message`.
+pub fn json_schema() -> String {
+    let string = "{ \"type\": \"string\" }";
+    let kinds: Vec<String> = EventKind::ALL.iter().map(|kind| utils::json_string(kind.as_str())).collect();
+    let rules: Vec<String> = EventKind::ALL.iter()
+        .map(|kind| {
+            let fields: Vec<String> = kind.fields().iter()
+                .map(|field| format!("          {}: {}", utils::json_string(field), string))
+                .collect();
+            format!("    {{\n      \"if\": {{ \"properties\": {{ \"type\": {{ \"const\": {} }} }}, \"required\": [\"type\"] }},\n      \
+                     \"then\": {{\n        \"properties\": {{\n{}\n        }}\n      }}\n    }}",
+                    utils::json_string(kind.as_str()), fields.join(",\n"))
+        })
+        .collect();
+    format!("{{\n  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",\n  \
+             \"$id\": \"urn:smtp-honeypot:event:v{version}\",\n  \
+             \"title\": \"smtp-honeypot event\",\n  \
+             \"description\": \"One NDJSON log line (--stdout-format/--file-format ndjson), event format version {version}\",\n  \
+             \"type\": \"object\",\n  \
+             \"required\": [\"timestamp\", \"client\", \"message\"],\n  \
+             \"properties\": {{\n    \
+             \"timestamp\": {{ \"type\": \"string\", \"description\": \"Local time, YYYY-MM-DD HH:MM:SS.mmm\" }},\n    \
+             \"client\": {{ \"type\": \"string\", \"description\": \"Client address:port, 0.0.0.0:0 for server lines\" }},\n    \
+             \"message\": {string},\n    \
+             \"type\": {{ \"enum\": [{kinds}] }}\n  \
+             }},\n  \
+             \"additionalProperties\": {string},\n  \
+             \"allOf\": [\n{rules}\n  ]\n}}",
+            version = EVENT_SCHEMA_VERSION, string = string, kinds = kinds.join(", "), rules = rules.join(",\n"))
+}
+
+/// Sortie recevant les événements ; `send` ne doit jamais bloquer la session
+pub trait EventSink: Send + Sync {
+    fn send(&self, event: &Event);
+}
+
+fn cef_escape_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_escape_extension(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Formate un événement en ligne CEF (ArcSight Common Event Format)
+pub fn format_cef(event: &Event) -> String {
+    let mut extensions = vec![
+        format!("rt={}", event.timestamp.timestamp_millis()),
+        format!("src={}", event.client_addr.ip()),
+        format!("spt={}", event.client_addr.port()),
+        format!("cat={}", event.kind.as_str()),
+        format!("msg={}", cef_escape_extension(&event.message)),
+    ];
+    
+    // Champs connus vers cs1-cs4, les autres vers cs5/cs6 dans l'ordre
+    let mut extra_slots = 5..=6;
+    for (key, value) in &event.fields {
+        let slot = match *key {
+            "helo" => Some(1),
+            "mail_from" => Some(2),
+            "rcpt_to" => Some(3),
+            "signals" => Some(4),
+            _ => extra_slots.next(),
+        };
+        if let Some(slot) = slot {
+            extensions.push(format!("cs{}={} cs{}Label={}", slot, cef_escape_extension(value), slot, key));
+        }
+    }
+    
+    format!(
+        "CEF:0|philtems|smtp-honeypot|{}|{}|{}|{}|{}",
+        cef_escape_header(env!("CARGO_PKG_VERSION")),
+        event.kind.cef_signature(),
+        cef_escape_header(event.kind.cef_name()),
+        event.kind.cef_severity(),
+        extensions.join(" ")
+    )
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::utils::FileModes;
+
+/// Concatène les captures .eml des répertoires de données en un fichier mbox (variante mboxrd)
+///
+/// Les fichiers sont lus ligne par ligne : seul le message courant est parcouru, jamais chargé.
+pub fn export_mbox(data_dirs: &[PathBuf], output: &Path, modes: FileModes) -> Result<usize> {
+    let mut files = Vec::new();
+    for data_dir in data_dirs {
+        files.extend(std::fs::read_dir(data_dir)
+            .with_context(|| format!("Failed to read data directory: {:?}", data_dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "eml")));
+    }
+    // Noms horodatés : tri chronologique, tous répertoires confondus
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    
+    let out = modes.open_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output)
+        .with_context(|| format!("Failed to create mbox file: {:?}", output))?;
+    let mut out = BufWriter::new(out);
+    
+    for path in &files {
+        append_message(&mut out, path)
+            .with_context(|| format!("Failed to export {:?}", path))?;
+    }
+    out.flush()?;
+    
+    Ok(files.len())
+}
+
+fn append_message(out: &mut impl Write, path: &Path) -> Result<()> {
+    let file = File::open(path)?;
+    let received: DateTime<Local> = file.metadata()?.modified()?.into();
+    let mut reader = BufReader::new(file);
+    // Octets bruts : la capture garde ceux du client, UTF-8 invalide compris
+    let mut line = Vec::new();
+    
+    // En-têtes X-Honeypot ajoutés à la capture, jusqu'à la ligne vide qui précède le message
+    let mut honeypot_headers = Vec::new();
+    let mut sender: Option<Vec<u8>> = None;
+    let mut in_mail_from = false;
+    while read_line(&mut reader, &mut line)? {
+        if line.is_empty() {
+            break;
+        }
+        // En-têtes pliés (RFC 5322) : une ligne commençant par un blanc prolonge le précédent
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            if let (true, Some(sender)) = (in_mail_from, sender.as_mut()) {
+                sender.extend_from_slice(&line);
+            }
+        } else if let Some(mail_from) = line.strip_prefix(b"X-Honeypot-MailFrom: ") {
+            sender = Some(mail_from.to_vec());
+            in_mail_from = true;
+        } else {
+            in_mail_from = false;
+        }
+        honeypot_headers.push(line.clone());
+    }
+    
+    let sender = sender.filter(|s| !s.is_empty() && !s.iter().any(u8::is_ascii_whitespace));
+    write!(out, "From ")?;
+    out.write_all(sender.as_deref().unwrap_or(b"MAILER-DAEMON"))?;
+    writeln!(out, " {}", received.format("%a %b %e %H:%M:%S %Y"))?;
+    for header in &honeypot_headers {
+        out.write_all(header)?;
+        writeln!(out)?;
+    }
+    
+    // Les en-têtes du message suivent directement ceux du honeypot
+    while read_line(&mut reader, &mut line)? {
+        let unquoted = line.iter().position(|b| *b != b'>').map_or(&[][..], |start| &line[start..]);
+        if unquoted.starts_with(b"From ") {
+            write!(out, ">")?;
+        }
+        out.write_all(&line)?;
+        writeln!(out)?;
+    }
+    writeln!(out)?;
+    
+    Ok(())
+}
+
+/// Ligne suivante sans sa fin de ligne (LF ou CRLF) ; faux en fin de fichier
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> std::io::Result<bool> {
+    line.clear();
+    if reader.read_until(b'\n', line)? == 0 {
+        return Ok(false);
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+    }
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn exports_capture_with_invalid_utf8() {
+        let path = std::env::temp_dir().join(format!("smtp-honeypot-export-{}.eml", std::process::id()));
+        std::fs::write(&path, b"X-Honeypot-MailFrom: a@b.c\r\n\r\nSubject: \xff\xfe\r\n\r\nFrom here\r\nbody \xc3(\r\n").unwrap();
+        let mut out = Vec::new();
+        let exported = append_message(&mut out, &path);
+        let _ = std::fs::remove_file(&path);
+        exported.unwrap();
+        
+        assert!(out.starts_with(b"From a@b.c "));
+        let body = &out[out.iter().position(|b| *b == b'\n').unwrap() + 1..];
+        assert_eq!(body, b"X-Honeypot-MailFrom: a@b.c\nSubject: \xff\xfe\n\n>From here\nbody \xc3(\n\n");
+    }
+}
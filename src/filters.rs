@@ -0,0 +1,68 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Décision d'un filtre fourni par un intégrateur
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Poursuivre normalement
+    Accept,
+    /// Répondre avec ce code et ce message
+    Reject(u16, String),
+    /// Fermer la connexion sans réponse
+    Drop,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Accept => "accept",
+            Decision::Reject(..) => "reject",
+            Decision::Drop => "drop",
+        }
+    }
+    
+    /// Rejet reprenant une réponse SMTP complète (`550 5.1.1 No such user`)
+    pub fn from_reply(reply: &str) -> Self {
+        let code = reply.get(..3).and_then(|code| code.parse().ok()).unwrap_or(550);
+        Decision::Reject(code, reply.get(3..).unwrap_or("").trim_start_matches([' ', '-']).to_string())
+    }
+    
+    /// Ligne de réponse SMTP d'un rejet
+    pub fn reject_line(&self) -> Option<String> {
+        match self {
+            Decision::Reject(code, message) => Some(format!("{} {}\r\n", code, message)),
+            _ => None,
+        }
+    }
+}
+
+/// Filtre consulté à l'acceptation de la connexion
+pub type ConnectionFilter = Arc<dyn Fn(IpAddr) -> Decision + Send + Sync>;
+/// Filtre consulté à chaque RCPT TO (IP du client, destinataire) ; remplace la logique intégrée
+/// (domaines, boîtes valides), qui sert de filtre par défaut
+pub type RecipientFilter = Arc<dyn Fn(IpAddr, &str) -> Decision + Send + Sync>;
+/// Filtre consulté à chaque AUTH (IP du client, mécanisme)
+pub type AuthFilter = Arc<dyn Fn(IpAddr, &str) -> Decision + Send + Sync>;
+
+/// Filtres installés ; sans filtre, la logique intégrée s'applique
+#[derive(Clone, Default)]
+pub struct Filters {
+    pub connection: Option<ConnectionFilter>,
+    pub recipient: Option<RecipientFilter>,
+    pub auth: Option<AuthFilter>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn reject_from_reply_keeps_code_and_text() {
+        let decision = Decision::from_reply("550 5.1.1 No such user");
+        assert_eq!(decision, Decision::Reject(550, "5.1.1 No such user".to_string()));
+        assert_eq!(decision.reject_line().as_deref(), Some("550 5.1.1 No such user\r\n"));
+        assert_eq!(Decision::from_reply("451"), Decision::Reject(451, String::new()));
+        assert_eq!(Decision::Drop.reject_line(), None);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::events::{Event, EventSink};
+
+use std::pin::Pin;
+
+use anyhow::{Context, Result};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+pub mod pb {
+    tonic::include_proto!("smtp_honeypot");
+}
+
+use pb::event_stream_server::{EventStream, EventStreamServer};
+
+// Retard maximal d'un abonné avant qu'il ne soit déconnecté
+const BROADCAST_CAPACITY: usize = 1024;
+
+impl From<&Event> for pb::HoneypotEvent {
+    fn from(event: &Event) -> Self {
+        pb::HoneypotEvent {
+            kind: event.kind.as_str().to_string(),
+            timestamp_ms: event.timestamp.timestamp_millis(),
+            client_ip: event.client_addr.ip().to_string(),
+            client_port: event.client_addr.port() as u32,
+            message: event.message.clone(),
+            fields: event.fields.iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Sortie alimentant le canal de diffusion lu par les abonnés gRPC
+pub struct GrpcSink {
+    tx: broadcast::Sender<pb::HoneypotEvent>,
+}
+
+impl EventSink for GrpcSink {
+    fn send(&self, event: &Event) {
+        // Sans abonné, l'envoi échoue : rien à faire
+        let _ = self.tx.send(event.into());
+    }
+}
+
+struct EventStreamService {
+    tx: broadcast::Sender<pb::HoneypotEvent>,
+}
+
+type EventResultStream = Pin<Box<dyn Stream<Item = Result<pb::HoneypotEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl EventStream for EventStreamService {
+    type SubscribeStream = EventResultStream;
+    
+    async fn subscribe(&self, request: Request<pb::SubscribeRequest>) -> Result<Response<Self::SubscribeStream>, Status> {
+        eprintln!("[INFO] gRPC subscriber connected: {:?}", request.remote_addr());
+        // Un abonné en retard (Lagged) termine son flux plutôt que de ralentir les sessions
+        let stream = BroadcastStream::new(self.tx.subscribe())
+            .map_while(|item| item.ok())
+            .map(Ok);
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Lie le port gRPC et lance le serveur ; retourne la sortie à brancher sur le Logger
+pub async fn start_server(address: &str, port: u16) -> Result<GrpcSink> {
+    let addr = format!("{}:{}", address, port);
+    let listener = TcpListener::bind(&addr).await
+        .with_context(|| format!("Failed to bind gRPC port {}", addr))?;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    
+    let service = EventStreamService { tx: tx.clone() };
+    tokio::spawn(async move {
+        let result = tonic::transport::Server::builder()
+            .add_service(EventStreamServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await;
+        if let Err(e) = result {
+            eprintln!("[ERROR] gRPC server failed: {}", e);
+        }
+    });
+    
+    eprintln!("[INFO] gRPC event stream listening on {}", addr);
+    Ok(GrpcSink { tx })
+}
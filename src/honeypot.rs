@@ -1,139 +1,1795 @@
-use crate::{Opt, ratelimiter, session};
-use crate::utils::Logger;
+use crate::{CommandRateAction, clientcert, control, proxyproto, sinks, DataDistribution, DataPolicy, Opt, RateLimitMode, StorageErrorPolicy, grpc, ratelimiter, scoring, session, tcpinfo, tlsresume};
+use crate::asn::AsnDb;
+use crate::bans::BanList;
+use crate::captureindex::CaptureIndex;
+use crate::signatures::BodySignatures;
+use crate::events::{Event, EventKind, SinkName};
+use crate::listener::{ListenerDef, TlsMode};
+use crate::mailheaders::{self, MessageHeaders};
+use crate::inject::{self, InjectPoint};
+use crate::filters::{AuthFilter, ConnectionFilter, Decision, Filters, RecipientFilter};
+use crate::persona::{self, DomainPersona};
+use crate::rawcapture::{RawRecorder, RawTap};
+use crate::retries::{NewDelivery, RetryTracker};
+use crate::sinks::{CefSink, EventLogSink, RecentEvents};
+use crate::session::{AuthExchange, Signal};
+use crate::utils::{self, HeloClass, Logger};
 
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io::{BufReader as StdBufReader};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::task::Poll;
 use std::time::Duration;
 
 use anyhow::{Result, Context};
 use chrono::Local;
 use rustls::{ServerConfig, Certificate, PrivateKey};
-use rustls_pemfile::{certs, pkcs8_private_keys};
+use rustls::server::Acceptor;
+use rustls_pemfile::{certs, ec_private_keys, pkcs8_private_keys, rsa_private_keys};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::sync::{Mutex, Semaphore};
 use tokio::time;
-use tokio_rustls::TlsAcceptor;
+use tokio_rustls::{LazyConfigAcceptor, TlsAcceptor};
+use tokio_rustls::server::TlsStream;
 
+// Longueur maximale d'une ligne reçue (commande ou DATA), CRLF compris
+const MAX_LINE_BYTES: usize = 8192;
+// Octets conservés dans le journal pour identifier un flux sans lignes
+const RAW_STREAM_SAMPLE: usize = 256;
+// Type d'enregistrement TLS d'une ClientHello
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+// Longueur maximale d'une ligne de commande, CRLF compris (RFC 5321 4.5.3.1.4)
+const MAX_COMMAND_LINE: usize = 512;
+
+// Écart toléré entre SIZE= annoncé et taille reçue : 10 %, au moins 1 Ko
+const SIZE_TOLERANCE_RATIO: f64 = 0.10;
+const SIZE_TOLERANCE_MIN: u64 = 1024;
+
+// Nom annoncé si --helo auto ne peut pas être résolu
+const DEFAULT_HELO: &str = "smtp.local";
+
+// Fenêtre d'observation après le QUIT (--observe-after-quit) et volume maximal lu
+const POST_QUIT_WINDOW: Duration = Duration::from_secs(2);
+const POST_QUIT_MAX_BYTES: usize = 4096;
+
+// Retard de bannière en mode --rate-limit-mode soft : par connexion au-delà de la limite, et plafond
+const SOFT_LIMIT_DELAY_STEP: Duration = Duration::from_secs(2);
+const SOFT_LIMIT_DELAY_MAX: Duration = Duration::from_secs(120);
+
+// Intervalle du contrôle d'écriture des répertoires --data
+const STORAGE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Intervalle de réévaluation du délestage adaptatif, hors de toute commande
+const THROTTLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Délai de réception de l'en-tête PROXY v2 (--proxy-protocol)
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Écritures de capture en attente des écrivains (--save-workers) ; au-delà, la session attend
+const SAVE_QUEUE_SIZE: usize = 256;
+// Attente maximale des écritures en cours à l'arrêt
+const SAVE_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Écriture d'une capture, préparée par la session puis exécutée en ligne ou par un écrivain
+struct SaveJob {
+    client_addr: SocketAddr,
+    data_dir: PathBuf,
+    filepath: PathBuf,
+    tmp_path: PathBuf,
+    /// En-têtes X-Honeypot et corps gardé en mémoire
+    content: Vec<u8>,
+    /// Suite du corps sur disque, supprimée une fois recopiée
+    spill: Option<session::SpillFile>,
+    /// Corps incomplet (écriture du débordement en échec)
+    truncated: bool,
+    policy: DataPolicy,
+    /// Ligne de --index-file, ajoutée une fois le fichier renommé
+    index_entry: Option<String>,
+    /// Livraison suivie par --dedup-retries, enregistrée une fois le fichier renommé
+    delivery: Option<NewDelivery>,
+}
+
+#[derive(Clone)]
 pub struct SmtpHoneypot {
     pub opt: Opt,
     logger: Logger,
     rate_limiter: Arc<Mutex<ratelimiter::RateLimiter>>,
     pub valid_mailboxes: Vec<String>,
     pub tls_acceptor: Option<Arc<TlsAcceptor>>,
+    /// Configuration TLS courante, remplacée par la commande de contrôle `reload`
+    tls_config: Option<Arc<std::sync::RwLock<Arc<ServerConfig>>>>,
+    /// Nombre de sessions en cours, tous ports confondus
+    active_sessions: Arc<AtomicUsize>,
+    throttling: Arc<AtomicBool>,
+    scorer: Arc<scoring::Scorer>,
+    /// IP bannies pour avoir atteint --ban-score
+    banned: Arc<Mutex<BanList>>,
+    filters: Filters,
+    /// Prochain répertoire --data en répartition round-robin
+    next_data_dir: Arc<AtomicUsize>,
+    body_signatures: Arc<BodySignatures>,
+    asn_db: Arc<std::sync::RwLock<Option<Arc<AsnDb>>>>,
+    /// Livraisons récentes (--dedup-retries)
+    retries: Option<Arc<Mutex<RetryTracker>>>,
+    /// Réponses émises par code à trois chiffres, depuis le démarrage
+    response_codes: Arc<std::sync::Mutex<BTreeMap<u16, u64>>>,
+    /// Commande de contrôle `drain` : nouvelles connexions refusées jusqu'à l'arrêt
+    draining: Arc<AtomicBool>,
+    /// Arrêt demandé (fin d'un `drain`, --on-storage-error exit) et sa raison
+    stop: Arc<tokio::sync::Notify>,
+    stop_reason: Arc<std::sync::Mutex<&'static str>>,
+    /// Répertoires --data accessibles en écriture au dernier contrôle ou à la dernière sauvegarde
+    storage_writable: Arc<AtomicBool>,
+    /// File des écritures de capture (--save-workers), vidée par les écrivains lancés dans `run`
+    save_queue: Option<tokio::sync::mpsc::Sender<SaveJob>>,
+    save_receiver: Arc<std::sync::Mutex<Option<tokio::sync::mpsc::Receiver<SaveJob>>>>,
+    /// Écritures confiées aux écrivains et pas encore terminées
+    pending_saves: Arc<AtomicUsize>,
+    /// Sessions mises en attente faute de place dans la file
+    save_queue_waits: Arc<AtomicU64>,
+    /// Débit global d'acceptation (--max-accepts-per-second)
+    accept_bucket: Option<Arc<std::sync::Mutex<ratelimiter::AcceptBucket>>>,
+    /// Connexions délestées par --max-accepts-per-second
+    shed_connections: Arc<AtomicU64>,
+    /// Places de fichiers de débordement (--max-open-spill-files)
+    spill_slots: Option<Arc<Semaphore>>,
+    /// Fichiers de débordement actuellement ouverts
+    open_spill_files: Arc<AtomicUsize>,
+    /// DATA gardés en mémoire faute de place pour un fichier de débordement
+    spill_fallbacks: Arc<AtomicU64>,
+    /// Sondages par boîte leurre (--honey-mailbox), depuis le démarrage
+    honey_hits: Arc<std::sync::Mutex<BTreeMap<String, u64>>>,
+    /// Connexions TLS établies puis fermées sans aucune commande SMTP
+    tls_probes: Arc<AtomicU64>,
+    /// Derniers événements (--recent-events), pour la commande de contrôle `recent`
+    recent_events: Option<Arc<RecentEvents>>,
+    /// Sortie CEF (--cef-url), pour le compteur d'envois perdus
+    cef_sink: Option<Arc<CefSink>>,
+    /// Identité tirée au démarrage (--randomize-persona)
+    random_persona: Option<persona::RandomPersona>,
+    /// Index JSON Lines des captures (--index-file)
+    capture_index: Option<Arc<CaptureIndex>>,
+}
+
+/// Tait les événements de routine d'une connexion --allowlist jusqu'à la fin de la session
+struct QuietGuard {
+    logger: Logger,
+    client_addr: SocketAddr,
+}
+
+impl QuietGuard {
+    fn new(logger: Logger, client_addr: SocketAddr) -> Self {
+        logger.set_quiet(client_addr, true);
+        Self { logger, client_addr }
+    }
+}
+
+impl Drop for QuietGuard {
+    fn drop(&mut self) {
+        self.logger.set_quiet(self.client_addr, false);
+    }
+}
+
+/// Décrémente le compteur de sessions actives à la fin de la session
+struct SessionGuard(Arc<AtomicUsize>);
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl SmtpHoneypot {
-    pub async fn new(opt: Opt) -> Result<Self> {
-        // Log de debug
-        eprintln!("[DEBUG] SmtpHoneypot::new() called");
-        eprintln!("[DEBUG] Current PID: {}", std::process::id());
-        eprintln!("[DEBUG] Current working dir: {:?}", std::env::current_dir().unwrap());
+    /// Arrêt propre : journalise l'arrêt et vide les journaux
+    pub async fn shutdown(&self, reason: &str) {
+        self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), &format!("Shutting down ({})", reason)).await;
+        self.wait_pending_saves().await;
+        if let Some(path) = &self.opt.control_socket {
+            let _ = std::fs::remove_file(path);
+        }
+        self.logger.flush().await;
+        let lost = self.logger.lost_lines();
+        if lost > 0 {
+            eprintln!("[WARNING] {} log line(s) were lost to write errors or a full queue", lost);
+        }
+    }
+    
+    pub async fn new(mut opt: Opt) -> Result<Self> {
+        Self::validate(&opt)?;
+        
+        let modes = opt.file_modes();
+        let mut logger = Logger::new(opt.log_file.clone(), opt.raw_display, opt.log_sample, modes)?;
+        logger.set_formats(opt.stdout_format, opt.file_format);
+        if let Some(window) = opt.alert_window {
+            logger.set_alert_window(window);
+        }
+        
+        let cef_sink = match &opt.cef_url {
+            Some(target) => {
+                let sink = Arc::new(CefSink::new(target, modes)?);
+                logger.add_sink(SinkName::Cef, sink.clone());
+                eprintln!("[INFO] CEF events sent to: {}", target);
+                Some(sink)
+            }
+            None => None,
+        };
+        
+        let recent_events = opt.recent_events.map(|capacity| {
+            let recent = Arc::new(RecentEvents::new(capacity));
+            logger.add_sink(SinkName::Recent, recent.clone());
+            recent
+        });
+        
+        if opt.windows_event_log {
+            match EventLogSink::new() {
+                Ok(sink) => {
+                    logger.add_sink(SinkName::EventLog, Arc::new(sink));
+                    eprintln!("[INFO] Events written to the Windows Event Log (source: {})", sinks::EVENT_SOURCE);
+                }
+                Err(e) => eprintln!("[WARNING] Windows Event Log unavailable, logging to stdout/file only: {}", e),
+            }
+        }
+        
+        if let Some(port) = opt.grpc_port {
+            logger.add_sink(SinkName::Grpc, Arc::new(grpc::start_server(&opt.address, port).await?));
+        }
+        logger.set_routes(&opt.routes)?;
+        for route in &opt.routes {
+            let sinks: Vec<&str> = route.sinks.iter().map(|sink| sink.as_str()).collect();
+            eprintln!("[INFO] Route: {} events to {}", route.kind.as_str(), sinks.join(","));
+        }
+        
+        // Créer le dossier data si spécifié
+        for data_dir in &opt.data_dirs {
+            if !data_dir.exists() {
+                modes.create_dir_all(data_dir)
+                    .with_context(|| format!("Failed to create data directory: {:?}", data_dir))?;
+                eprintln!("[INFO] Data directory created: {:?}", data_dir);
+            }
+        }
+        
+        // --helo auto : nom de la machine hôte, résolu une seule fois
+        if opt.helo == "auto" {
+            match tokio::task::spawn_blocking(utils::system_fqdn).await.ok().flatten() {
+                Some(name) => opt.helo = name,
+                None => {
+                    eprintln!("[WARNING] Could not resolve the system hostname, using {}", DEFAULT_HELO);
+                    opt.helo = DEFAULT_HELO.to_string();
+                }
+            }
+            eprintln!("[INFO] HELO name resolved to: {}", opt.helo);
+        }
+        
+        if let Some(raw_dir) = &opt.capture_raw {
+            modes.create_dir_all(raw_dir)
+                .with_context(|| format!("Failed to create raw capture directory: {:?}", raw_dir))?;
+            eprintln!("[INFO] Raw session capture to: {:?}", raw_dir);
+        }
+        
+        if !opt.test_inject.is_empty() {
+            let points: Vec<&str> = opt.test_inject.iter().map(|point| point.as_str()).collect();
+            eprintln!("[WARNING] Failure injection enabled: {}", points.join(","));
+        }
+        
+        // --sinkhole : préréglage appliqué une fois pour toutes aux options concernées
+        if opt.sinkhole {
+            opt.accept_all_rcpt = true;
+            opt.starttls = false;
+        }
+        
+        let body_signatures = BodySignatures::new(&opt.body_signatures)?;
+        let asn_db = match &opt.asn_db {
+            Some(path) => {
+                let db = AsnDb::load(path)?;
+                eprintln!("[INFO] ASN database loaded: {} ranges from {:?}", db.len(), path);
+                Some(db)
+            }
+            None => None,
+        };
+        
+        if let Some(quarantine) = &opt.quarantine {
+            modes.create_dir_all(quarantine)
+                .with_context(|| format!("Failed to create quarantine directory: {:?}", quarantine))?;
+            eprintln!("[INFO] Messages matching a body signature quarantined to: {:?}", quarantine);
+        }
+        
+        for persona in &opt.domain_personas {
+            if !opt.domains.iter().any(|d| d.eq_ignore_ascii_case(&persona.domain)) {
+                eprintln!("[WARNING] Persona domain {} is not in the accepted --domain list", persona.domain);
+            }
+        }
+        
+        // Configurer TLS avec RustLS
+        let tls = if let Some((cert_path, key_path)) = tls_files(&opt) {
+            let config = Arc::new(load_tls_config(cert_path, key_path, &opt)?);
+            let acceptor = TlsAcceptor::from(config.clone());
+            
+            eprintln!("[INFO] TLS enabled with certificate: {:?}", cert_path);
+            if opt.tls_weak_profile {
+                logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                           "TLS weak profile enabled: INSECURE BY DESIGN (TLS 1.2 only, weakest cipher suites preferred)").await;
+            }
+            Some((Arc::new(acceptor), config))
+        } else {
+            if opt.listeners().iter().any(|l| l.implicit_tls(&opt.implicit_tls_ports) || l.starttls(opt.starttls, &opt.starttls_ports)) {
+                eprintln!("[WARNING] TLS ports specified but no certificates provided");
+            }
+            None
+        };
+        
+        let random_persona = if opt.randomize_persona {
+            let seed = *opt.persona_seed.get_or_insert_with(|| {
+                std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or_default()
+            });
+            let domain = opt.domains.first().map(String::as_str).unwrap_or("localdomain");
+            let persona = persona::RandomPersona::draw(seed, domain);
+            if opt.helo == DEFAULT_HELO {
+                opt.helo = persona.hostname.clone();
+            }
+            eprintln!("[INFO] Randomized persona: {} (--persona-seed {})", persona, seed);
+            Some(persona)
+        } else {
+            None
+        };
+        
+        if let Some(rate) = opt.auth_advertise_rate {
+            let seed = *opt.auth_advertise_seed.get_or_insert_with(|| {
+                std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or_default()
+            });
+            eprintln!("[INFO] AUTH advertised to {:.0}% of connections (--auth-advertise-seed {})", rate * 100.0, seed);
+        }
+        
+        let (save_queue, save_receiver) = match opt.save_workers {
+            Some(_) => {
+                let (sender, receiver) = tokio::sync::mpsc::channel(SAVE_QUEUE_SIZE);
+                (Some(sender), Some(receiver))
+            }
+            None => (None, None),
+        };
+        
+        Ok(Self {
+            opt: opt.clone(),
+            logger,
+            rate_limiter: Arc::new(Mutex::new(ratelimiter::RateLimiter::new(opt.max_connections_per_minute, opt.burst_allowance))),
+            valid_mailboxes: opt.valid_mailboxes.clone(),
+            tls_acceptor: tls.as_ref().map(|(acceptor, _)| acceptor.clone()),
+            tls_config: tls.map(|(_, config)| Arc::new(std::sync::RwLock::new(config))),
+            active_sessions: Arc::new(AtomicUsize::new(0)),
+            throttling: Arc::new(AtomicBool::new(false)),
+            scorer: Arc::new(scoring::Scorer::new(&opt.signal_weights)),
+            banned: Arc::new(Mutex::new(BanList::new(opt.ban_ttl, opt.max_banned))),
+            filters: Filters::default(),
+            next_data_dir: Arc::new(AtomicUsize::new(0)),
+            body_signatures: Arc::new(body_signatures),
+            asn_db: Arc::new(std::sync::RwLock::new(asn_db.map(Arc::new))),
+            response_codes: Arc::new(std::sync::Mutex::new(BTreeMap::new())),
+            draining: Arc::new(AtomicBool::new(false)),
+            stop: Arc::new(tokio::sync::Notify::new()),
+            stop_reason: Arc::new(std::sync::Mutex::new("")),
+            storage_writable: Arc::new(AtomicBool::new(true)),
+            save_queue,
+            save_receiver: Arc::new(std::sync::Mutex::new(save_receiver)),
+            pending_saves: Arc::new(AtomicUsize::new(0)),
+            save_queue_waits: Arc::new(AtomicU64::new(0)),
+            accept_bucket: opt.max_accepts_per_second
+                .map(|rate| Arc::new(std::sync::Mutex::new(ratelimiter::AcceptBucket::new(rate)))),
+            shed_connections: Arc::new(AtomicU64::new(0)),
+            spill_slots: opt.max_open_spill_files.map(|max| Arc::new(Semaphore::new(max))),
+            open_spill_files: Arc::new(AtomicUsize::new(0)),
+            spill_fallbacks: Arc::new(AtomicU64::new(0)),
+            honey_hits: Arc::new(std::sync::Mutex::new(BTreeMap::new())),
+            tls_probes: Arc::new(AtomicU64::new(0)),
+            recent_events,
+            cef_sink,
+            random_persona,
+            capture_index: opt.index_file.clone().map(|path| Arc::new(CaptureIndex::new(path, modes))),
+            retries: opt.dedup_retries.map(|window| Arc::new(Mutex::new(RetryTracker::new(window)))),
+        })
+    }
+    
+    /// Contrôles des options, sans effet de bord : au démarrage et pour --check-config
+    pub fn validate(opt: &Opt) -> Result<()> {
+        if opt.recent_events == Some(0) {
+            return Err(anyhow::anyhow!("--recent-events must be at least 1"));
+        }
+        if opt.save_workers == Some(0) {
+            return Err(anyhow::anyhow!("--save-workers must be at least 1"));
+        }
+        if opt.max_commands_per_second == Some(0) {
+            return Err(anyhow::anyhow!("--max-commands-per-second must be at least 1"));
+        }
+        if opt.max_banned == 0 {
+            return Err(anyhow::anyhow!("--max-banned must be at least 1"));
+        }
+        if opt.max_accepts_per_second == Some(0) {
+            return Err(anyhow::anyhow!("--max-accepts-per-second must be at least 1"));
+        }
+        if let Some(code) = opt.reject_code {
+            if !(400..=599).contains(&code) {
+                return Err(anyhow::anyhow!("--reject-code must be a 4xx or 5xx code, got {}", code));
+            }
+        }
+        if let Some(variant) = persona::variant_without_code(&Self::default_reject_template(opt)) {
+            return Err(anyhow::anyhow!("--reject-message variants after '|' must start with a 4xx/5xx code: '{}'",
+                                       variant.trim()));
+        }
+        if !opt.test_inject.is_empty() && !cfg!(feature = "test-inject") {
+            return Err(anyhow::anyhow!("--test-inject requires a build with the test-inject feature"));
+        }
+        if opt.asn_db.is_none() && !opt.reject_asns.is_empty() {
+            return Err(anyhow::anyhow!("--reject-asn requires --asn-db"));
+        }
+        
+        for listener in &opt.listen {
+            if let Some(profile) = &listener.profile {
+                if !opt.domain_personas.iter().any(|p| p.domain.eq_ignore_ascii_case(profile)) {
+                    return Err(anyhow::anyhow!("--listen {}: no --domain-persona for profile {}", listener.bind_addr(), profile));
+                }
+            }
+        }
+        
+        // Fichier PEM combiné (certificats + clé) ou paire --tls-cert/--tls-key
+        if opt.tls_pem.is_some() && (opt.tls_cert.is_some() || opt.tls_key.is_some()) {
+            return Err(anyhow::anyhow!("--tls-pem cannot be combined with --tls-cert/--tls-key"));
+        }
+        
+        if opt.accept_loops == 0 {
+            return Err(anyhow::anyhow!("--accept-loops must be at least 1"));
+        }
+        if opt.accept_loops > 1 && !opt.reuseport {
+            return Err(anyhow::anyhow!("--accept-loops requires --reuseport"));
+        }
+        if opt.reuseport && cfg!(not(unix)) {
+            return Err(anyhow::anyhow!("--reuseport is only supported on Unix"));
+        }
+        
+        for route in &opt.routes {
+            for sink in &route.sinks {
+                let enabled = match sink {
+                    SinkName::Stdout => true,
+                    SinkName::File => opt.log_file.is_some(),
+                    SinkName::Cef => opt.cef_url.is_some(),
+                    SinkName::Grpc => opt.grpc_port.is_some(),
+                    SinkName::EventLog => opt.windows_event_log,
+                    SinkName::Recent => opt.recent_events.is_some(),
+                };
+                if !enabled {
+                    return Err(anyhow::anyhow!("--route {}: sink '{}' is not enabled",
+                                               route.kind.as_str(), sink.as_str()));
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Installe une décision programmatique à l'acceptation des connexions
+    #[allow(dead_code)]
+    pub fn with_connection_filter(mut self, filter: impl Fn(IpAddr) -> Decision + Send + Sync + 'static) -> Self {
+        self.filters.connection = Some(Arc::new(filter) as ConnectionFilter);
+        self
+    }
+    
+    /// Remplace la logique intégrée (domaines, boîtes valides) pour RCPT TO
+    #[allow(dead_code)]
+    pub fn with_recipient_filter(mut self, filter: impl Fn(IpAddr, &str) -> Decision + Send + Sync + 'static) -> Self {
+        self.filters.recipient = Some(Arc::new(filter) as RecipientFilter);
+        self
+    }
+    
+    /// Installe une décision programmatique sur les tentatives AUTH
+    #[allow(dead_code)]
+    pub fn with_auth_filter(mut self, filter: impl Fn(IpAddr, &str) -> Decision + Send + Sync + 'static) -> Self {
+        self.filters.auth = Some(Arc::new(filter) as AuthFilter);
+        self
+    }
+    
+    /// Journalise une décision de filtre et retourne la réponse à envoyer
+    async fn apply_decision(&self, session: &mut session::SmtpSession, stage: &str, decision: &Decision) -> Option<String> {
+        self.logger.log(&session.client_addr, &format!("Filter decision on {}: {}", stage, decision.as_str())).await;
+        if *decision == Decision::Drop {
+            session.dropped = true;
+        }
+        decision.reject_line()
+    }
+    
+    /// Journalise les IP les plus refusées puis purge les compteurs inactifs et les livraisons expirées
+    async fn log_rate_limit_summary(&self) {
+        if let Some(retries) = &self.retries {
+            retries.lock().await.prune();
+        }
+        self.banned.lock().await.prune();
+        let top = {
+            let mut limiter = self.rate_limiter.lock().await;
+            let top = limiter.top_rejections(10);
+            limiter.prune();
+            top
+        };
+        if top.is_empty() {
+            return;
+        }
+        let list: Vec<String> = top.iter().map(|(ip, count)| format!("{}={}", ip, count)).collect();
+        self.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                        &format!("Rate limit rejections (top {}): {}", top.len(), list.join(" "))).await;
+    }
+    
+    /// État courant journalisé sur SIGUSR1 : compteurs et IP les plus refusées
+    async fn dump_state(&self) {
+        let top = self.rate_limiter.lock().await.top_rejections(10);
+        let list: Vec<String> = top.iter().map(|(ip, count)| format!("{}={}", ip, count)).collect();
+        let line = format!("State dump: {} rate_limit_top={}", self.stats_line().await,
+                           if list.is_empty() { "-".to_string() } else { list.join(",") });
+        eprintln!("[INFO] {}", line);
+        self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), &line).await;
+    }
+    
+    /// Commande reçue sur le socket de contrôle (--control-socket), réponse sur une ligne
+    pub async fn control_command(&self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("").to_lowercase();
+        let argument = words.next();
+        let server = SocketAddr::from(([0,0,0,0], 0));
+        let usage = || format!("ERR unknown command '{}' (expected stats, ban <ip>, unban <ip>, reload, drain or recent [n])", line);
+        // Aucune commande ne prend plus d'un argument
+        if words.next().is_some() {
+            return usage();
+        }
+        
+        match (command.as_str(), argument) {
+            ("stats", None) => format!("OK {}", self.stats_line().await),
+            ("ban", Some(ip)) | ("unban", Some(ip)) => {
+                let Ok(ip) = ip.parse::<IpAddr>() else {
+                    return format!("ERR invalid IP address '{}'", ip);
+                };
+                let changed = if command == "ban" {
+                    self.banned.lock().await.insert(ip)
+                } else {
+                    self.banned.lock().await.remove(&ip)
+                };
+                if !changed {
+                    return format!("OK {} already {}", ip, if command == "ban" { "banned" } else { "not banned" });
+                }
+                self.logger.log(&server, &format!("Control: {} {}", command, ip)).await;
+                format!("OK {} {}", ip, if command == "ban" { "banned" } else { "unbanned" })
+            }
+            ("reload", None) => match self.reload() {
+                Ok(reloaded) => {
+                    self.logger.log(&server, &format!("Control: reloaded {}", reloaded)).await;
+                    format!("OK reloaded {}", reloaded)
+                }
+                Err(e) => {
+                    self.logger.log(&server, &format!("Control: reload failed: {:#}", e)).await;
+                    format!("ERR reload failed: {:#}", e)
+                }
+            },
+            ("drain", None) => {
+                self.start_drain("Control").await;
+                format!("OK draining, {} active sessions", self.active_sessions.load(Ordering::Relaxed))
+            }
+            ("recent", count) => {
+                let Some(recent) = &self.recent_events else {
+                    return "ERR --recent-events is not enabled".to_string();
+                };
+                match count.map(str::parse::<usize>).transpose() {
+                    Ok(count) => format!("OK {}", recent.dump(count)),
+                    Err(_) => format!("ERR invalid count '{}'", count.unwrap_or("")),
+                }
+            }
+            _ => usage(),
+        }
+    }
+    
+    /// Compteurs courants, au format clé=valeur
+    async fn stats_line(&self) -> String {
+        format!("active_sessions={} banned={} draining={} throttling={} response_codes={} shed_connections={} honey_hits={} tls_probes={} open_spill_files={} spill_fallbacks={} pending_saves={} save_queue_waits={} lost_log_lines={} quiet_suppressed={} cef_failures={}",
+                self.active_sessions.load(Ordering::Relaxed),
+                self.banned.lock().await.len(),
+                self.draining.load(Ordering::Relaxed),
+                self.throttling.load(Ordering::Relaxed),
+                self.response_code_summary().map(|codes| codes.replace(' ', ",")).unwrap_or_else(|| "-".to_string()),
+                self.shed_connections.load(Ordering::Relaxed),
+                self.honey_hit_summary().map(|hits| hits.replace(' ', ",")).unwrap_or_else(|| "-".to_string()),
+                self.tls_probes.load(Ordering::Relaxed),
+                self.open_spill_files.load(Ordering::Relaxed),
+                self.spill_fallbacks.load(Ordering::Relaxed),
+                self.pending_saves.load(Ordering::Relaxed),
+                self.save_queue_waits.load(Ordering::Relaxed),
+                self.logger.lost_lines(),
+                self.logger.quiet_suppressed(),
+                self.cef_sink.as_ref().map(|sink| sink.failures()).unwrap_or(0))
+    }
+    
+    /// Recharge le certificat TLS et la base ASN ; rien n'est remplacé si un chargement échoue
+    fn reload(&self) -> Result<String> {
+        let tls = match (&self.tls_config, tls_files(&self.opt)) {
+            (Some(_), Some((cert_path, key_path))) => Some((load_tls_config(cert_path, key_path, &self.opt)?, cert_path)),
+            _ => None,
+        };
+        let asn_db = match &self.opt.asn_db {
+            Some(path) => Some(AsnDb::load(path)?),
+            None => None,
+        };
+        
+        let mut reloaded = Vec::new();
+        if let (Some(current), Some((config, cert_path))) = (&self.tls_config, tls) {
+            *current.write().unwrap() = Arc::new(config);
+            reloaded.push(format!("TLS certificate {:?}", cert_path));
+        }
+        if let Some(db) = asn_db {
+            reloaded.push(format!("ASN database ({} ranges)", db.len()));
+            *self.asn_db.write().unwrap() = Some(Arc::new(db));
+        }
+        if reloaded.is_empty() {
+            return Err(anyhow::anyhow!("nothing to reload (no TLS certificate or --asn-db)"));
+        }
+        Ok(reloaded.join(", "))
+    }
+    
+    /// Refuse les nouvelles connexions et signale l'arrêt quand la dernière session se termine
+    async fn start_drain(&self, origin: &str) {
+        if self.draining.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        self.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                        &format!("{}: draining, refusing new connections ({} active sessions)",
+                                 origin, self.active_sessions.load(Ordering::Relaxed))).await;
+        let this = self.clone();
+        tokio::spawn(async move {
+            while this.active_sessions.load(Ordering::Relaxed) > 0 {
+                time::sleep(Duration::from_millis(500)).await;
+            }
+            this.request_stop("drain");
+        });
+    }
+    
+    /// Draine et attend la fin des sessions actives, au plus `timeout` ; renvoie le nombre de sessions restantes
+    pub async fn drain(&self, origin: &str, timeout: Duration) -> usize {
+        self.start_drain(origin).await;
+        let _ = time::timeout(timeout, async {
+            while self.active_sessions.load(Ordering::Relaxed) > 0 {
+                time::sleep(Duration::from_millis(500)).await;
+            }
+        }).await;
+        self.active_sessions.load(Ordering::Relaxed)
+    }
+    
+    fn request_stop(&self, reason: &'static str) {
+        *self.stop_reason.lock().unwrap() = reason;
+        self.stop.notify_one();
+    }
+    
+    /// Attend une demande d'arrêt (fin d'un `drain`, stockage inaccessible) et renvoie sa raison
+    pub async fn stopped(&self) -> &'static str {
+        self.stop.notified().await;
+        *self.stop_reason.lock().unwrap()
+    }
+    
+    /// Vérifie que chaque répertoire --data accepte l'écriture d'un fichier témoin
+    async fn probe_storage(&self) -> std::result::Result<(), String> {
+        if self.opt.data_policy != DataPolicy::Capture {
+            return Ok(());
+        }
+        for data_dir in &self.opt.data_dirs {
+            let probe = data_dir.join(format!(".smtp-honeypot-probe-{}", std::process::id()));
+            let written = async {
+                let mut file = self.opt.file_modes().create_file(&probe).await?;
+                file.write_all(b"probe").await?;
+                file.sync_all().await
+            }.await;
+            let _ = tokio::fs::remove_file(&probe).await;
+            written.map_err(|e| format!("{:?}: {}", data_dir, e))?;
+        }
+        Ok(())
+    }
+    
+    /// Enregistre l'état du stockage, journalise les changements et applique --on-storage-error
+    async fn set_storage_state(&self, state: std::result::Result<(), String>) {
+        let writable = state.is_ok();
+        if self.storage_writable.swap(writable, Ordering::Relaxed) == writable {
+            return;
+        }
+        let server = SocketAddr::from(([0,0,0,0], 0));
+        match state {
+            Ok(()) => self.logger.log(&server, "Storage writable again").await,
+            Err(e) => {
+                eprintln!("[WARNING] Storage unwritable: {} (on-storage-error: {})", e, self.opt.on_storage_error.as_str());
+                self.logger.log(&server, &format!("Storage unwritable: {} (on-storage-error: {})",
+                                                  e, self.opt.on_storage_error.as_str())).await;
+                if self.opt.on_storage_error == StorageErrorPolicy::Exit {
+                    self.request_stop("storage-error");
+                }
+            }
+        }
+    }
+    
+    /// Vérification à blanc (--check-config) : contrôles et fichiers lus comme au démarrage, puis bind
+    /// de test ; ni journal, ni répertoire, ni serveur gRPC ne sont créés
+    pub async fn check_config(opt: Opt) -> bool {
+        let mut failures = 0;
+        let mut report = |ok: bool, warn_only: bool, message: String| {
+            let status = match (ok, warn_only) {
+                (true, _) => "PASS",
+                (false, true) => "WARN",
+                (false, false) => {
+                    failures += 1;
+                    "FAIL"
+                }
+            };
+            println!("[{}] {}", status, message);
+        };
+        
+        match Self::validate(&opt) {
+            Ok(()) => report(true, false, "Options".to_string()),
+            Err(e) => report(false, false, format!("Options: {:#}", e)),
+        }
+        if !opt.body_signatures.is_empty() {
+            match BodySignatures::new(&opt.body_signatures) {
+                Ok(_) => report(true, false, "Body signatures compiled".to_string()),
+                Err(e) => report(false, false, format!("Body signatures: {:#}", e)),
+            }
+        }
+        if let Some(path) = &opt.asn_db {
+            match AsnDb::load(path) {
+                Ok(db) => report(true, false, format!("ASN database: {} ranges", db.len())),
+                Err(e) => report(false, false, format!("ASN database: {:#}", e)),
+            }
+        }
+        let tls_enabled = match tls_files(&opt) {
+            Some((cert_path, key_path)) => match load_tls_config(cert_path, key_path, &opt) {
+                Ok(_) => {
+                    report(true, false, "Certificate and key loaded".to_string());
+                    true
+                }
+                Err(e) => {
+                    report(false, false, format!("TLS: {:#}", e));
+                    false
+                }
+            },
+            None => false,
+        };
+        
+        // Options TLS incohérentes, ignorées silencieusement au démarrage
+        if opt.tls_pem.is_none() && opt.tls_cert.is_some() != opt.tls_key.is_some() {
+            report(false, false, "--tls-cert and --tls-key must be given together".to_string());
+        }
+        if opt.starttls && !tls_enabled {
+            report(false, false, "--starttls requires a usable --tls-pem or --tls-cert/--tls-key".to_string());
+        }
+        for listener in opt.listeners() {
+            let needs_tls = listener.implicit_tls(&opt.implicit_tls_ports) || listener.tls == TlsMode::Starttls;
+            if needs_tls && !tls_enabled {
+                report(false, false, format!("Listener {} requires a usable --tls-pem or --tls-cert/--tls-key", listener));
+            }
+        }
+        
+        for domain in &opt.domains {
+            report(domain.contains('.') && !domain.contains('@'), true, format!("Domain {}", domain));
+        }
+        for mailbox in &opt.valid_mailboxes {
+            match mailbox.rsplit_once('@') {
+                Some((local, domain)) if !local.is_empty() && !domain.is_empty() => {
+                    let known = opt.domains.iter().any(|d| d.eq_ignore_ascii_case(domain));
+                    report(known, true, format!("Mailbox {}{}", mailbox,
+                                                if known { "" } else { " (domain not in --domain)" }));
+                }
+                _ => report(false, false, format!("Mailbox {} is not a valid address", mailbox)),
+            }
+        }
+        
+        for listener in opt.listeners() {
+            let addr = listener.bind_addr();
+            // Avec --reuseport, le bind de test cohabite avec les instances déjà lancées
+            let bound = if opt.reuseport {
+                std::net::ToSocketAddrs::to_socket_addrs(&addr)
+                    .and_then(|addrs| reuseport_listeners(&addr, addrs, opt.accept_loops))
+                    .map(drop)
+            } else {
+                std::net::TcpListener::bind(&addr).map(drop)
+            };
+            match bound {
+                Ok(()) => report(true, false, format!("Bind {}", addr)),
+                Err(e) => {
+                    let hint = privileged_port_hint(listener.port, &e).map(|h| format!(" ({})", h)).unwrap_or_default();
+                    report(false, false, format!("Bind {}: {}{}", addr, e, hint));
+                }
+            }
+        }
+        
+        println!("Configuration check: {}", if failures == 0 { "OK".to_string() } else { format!("{} failure(s)", failures) });
+        failures == 0
+    }
+    
+    /// Ouvre le fichier de capture brute de la session (--capture-raw)
+    async fn start_raw_capture(&self, session: &session::SmtpSession) -> Option<RawRecorder> {
+        let dir = self.opt.capture_raw.as_ref()?;
+        let filename = format!("{}_{}_s{}.raw", Local::now().format("%Y%m%d_%H%M%S"),
+                               session.client_addr.ip().to_string().replace('.', "_"), session.id);
+        let path = dir.join(filename);
+        match self.opt.file_modes().create_file(&path).await {
+            Ok(file) => {
+                self.logger.log(&session.client_addr, &format!("Raw capture: {:?}", path)).await;
+                Some(RawRecorder::start(file, path))
+            }
+            Err(e) => {
+                self.logger.log(&session.client_addr, &format!("Failed to create raw capture {:?}: {}", path, e)).await;
+                None
+            }
+        }
+    }
+    
+    /// Répertoire --data recevant la prochaine capture de ce client
+    fn data_dir_for(&self, client_addr: &SocketAddr) -> Option<&PathBuf> {
+        let dirs = &self.opt.data_dirs;
+        if dirs.len() <= 1 {
+            return dirs.first();
+        }
+        let index = match self.opt.data_distribution {
+            DataDistribution::Hash => {
+                let mut hasher = DefaultHasher::new();
+                client_addr.ip().hash(&mut hasher);
+                hasher.finish() as usize
+            }
+            DataDistribution::RoundRobin => self.next_data_dir.fetch_add(1, Ordering::Relaxed),
+        };
+        dirs.get(index % dirs.len())
+    }
+    
+    /// Cherche les signatures de corps connues ; seule la partie gardée en mémoire est examinée
+    async fn check_body_signatures(&self, client_addr: &SocketAddr, session: &session::SmtpSession) -> bool {
+        let mut scan = self.body_signatures.scan();
+        scan.feed(&session.body());
+        // Partie débordée sur disque : lue par blocs, comme pour l'empreinte
+        if let Some(spill) = &session.spill {
+            match tokio::fs::File::open(&spill.path).await {
+                Ok(mut file) => {
+                    let mut chunk = vec![0u8; 64 * 1024];
+                    loop {
+                        match file.read(&mut chunk).await {
+                            Ok(0) => break,
+                            Ok(n) => scan.feed(&chunk[..n]),
+                            Err(e) => {
+                                self.logger.log(client_addr, &format!("Failed to scan spilled body: {}", e)).await;
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => self.logger.log(client_addr, &format!("Failed to scan spilled body: {}", e)).await,
+            }
+        }
+        let matched = scan.finish();
+        if matched.is_empty() {
+            return false;
+        }
+        self.logger.event(Event::new(EventKind::Alert, *client_addr,
+                                     format!("ALERT: body signature matched: {}", matched.join(",")))
+            .with("alert", "body-signature")
+            .with("signatures", matched.join(","))
+            .with("mail_from", session.mail_from.as_deref().unwrap_or(""))
+            .with("mail_auth", session.mail_auth.as_deref().unwrap_or(""))
+            .with("rcpt_to", session.rcpt_to.join(","))).await;
+        true
+    }
+    
+    /// Termine l'empreinte SHA-256 avec le corps reçu, mémoire puis débordement disque
+    async fn body_sha256(session: &session::SmtpSession, mut hasher: openssl::sha::Sha256) -> Result<[u8; 32]> {
+        hasher.update(&session.body());
+        if let Some(spill) = &session.spill {
+            let mut file = tokio::fs::File::open(&spill.path).await?;
+            let mut chunk = vec![0u8; 64 * 1024];
+            loop {
+                let n = file.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&chunk[..n]);
+            }
+        }
+        Ok(hasher.finish())
+    }
+    
+    /// Reconnaît une nouvelle tentative : même expéditeur, mêmes destinataires, même corps
+    ///
+    /// Pour une première livraison, retourne de quoi l'enregistrer une fois sauvegardée :
+    /// un échec de sauvegarde ne doit pas faire écarter le renvoi comme doublon.
+    async fn check_retry(&self, session: &mut session::SmtpSession) -> Option<NewDelivery> {
+        let retries = self.retries.as_ref()?;
+        let mut envelope = openssl::sha::Sha256::new();
+        envelope.update(session.mail_from.as_deref().unwrap_or("").as_bytes());
+        let mut recipients = session.rcpt_to.clone();
+        recipients.sort();
+        for rcpt in &recipients {
+            envelope.update(b"\0");
+            envelope.update(rcpt.as_bytes());
+        }
+        envelope.update(b"\0\0");
+        match Self::body_sha256(session, envelope).await {
+            Ok(key) => {
+                session.retry_of = retries.lock().await.retry_of(&key);
+                session.retry_of.is_none().then_some(NewDelivery { key, session_id: session.id, message_seq: session.message_seq })
+            }
+            Err(e) => {
+                self.logger.log(&session.client_addr, &format!("Failed to hash message for retry detection: {}", e)).await;
+                None
+            }
+        }
+    }
+    
+    async fn record_delivery(&self, delivery: Option<NewDelivery>) {
+        if let (Some(retries), Some(delivery)) = (&self.retries, delivery) {
+            retries.lock().await.record(delivery);
+        }
+    }
+    
+    async fn save_email_data(&self, client_addr: &SocketAddr, session: &mut session::SmtpSession,
+                             delivery: Option<NewDelivery>) -> Result<()> {
+        if inject::should_fail(&self.opt.test_inject, InjectPoint::Save) {
+            return Err(anyhow::anyhow!("injected failure: save"));
+        }
+        let matched = self.check_body_signatures(client_addr, session).await;
+        let policy = self.opt.data_policy;
+        match policy {
+            DataPolicy::Discard => {
+                let size = session.data_size;
+                self.logger.event(Event::new(EventKind::Capture, *client_addr,
+                                             format!("Email discarded ({} bytes, policy: {})", size, policy.as_str()))
+                    .with("size", size)
+                    .with("policy", policy.as_str())
+                    .with("mode", self.mode())).await;
+                self.record_delivery(delivery).await;
+                return Ok(());
+            }
+            DataPolicy::HashOnly => {
+                let hash = utils::hex(&Self::body_sha256(session, openssl::sha::Sha256::new()).await?);
+                self.logger.event(Event::new(EventKind::Capture, *client_addr,
+                                             format!("Email body sha256: {} ({} bytes, policy: {})", hash, session.data_size, policy.as_str()))
+                    .with("sha256", hash)
+                    .with("size", session.data_size)
+                    .with("policy", policy.as_str())
+                    .with("mode", self.mode())).await;
+                self.record_delivery(delivery).await;
+                return Ok(());
+            }
+            DataPolicy::Capture => {}
+        }
+        
+        let data_dir = match &self.opt.quarantine {
+            Some(quarantine) if matched => Some(quarantine),
+            _ => self.data_dir_for(client_addr),
+        };
+        if let Some(data_dir) = data_dir {
+            let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+            // Session et numéro de message : plusieurs messages par connexion et par seconde
+            let filename = format!("{}_{}_s{}_m{}.eml", timestamp, client_addr.ip().to_string().replace('.', "_"),
+                                   session.id, session.message_seq);
+            let filepath = data_dir.join(&filename);
+            // Écriture dans un fichier temporaire puis renommage atomique
+            let tmp_path = data_dir.join(format!("{}.tmp", filename));
+            
+            let mut content = String::new();
+            content.push_str(&utils::fold_header("X-Honeypot-Client", &client_addr.to_string()));
+            content.push_str(&utils::fold_header("X-Honeypot-Date", &Local::now().format("%Y-%m-%d %H:%M:%S").to_string()));
+            if self.opt.sinkhole {
+                content.push_str(&utils::fold_header("X-Honeypot-Mode", "sinkhole"));
+            }
+            if let Some(persona) = &self.random_persona {
+                content.push_str(&utils::fold_header("X-Honeypot-Persona", &format!("{} seed={}", persona.profile, persona.seed)));
+            }
+            if let Some(helo) = &session.helo {
+                content.push_str(&utils::fold_header("X-Honeypot-HELO", helo));
+            }
+            if let Some(mail_from) = &session.mail_from {
+                content.push_str(&utils::fold_header("X-Honeypot-MailFrom", mail_from));
+            }
+            for rcpt in &session.rcpt_to {
+                content.push_str(&utils::fold_header("X-Honeypot-RcptTo", rcpt));
+            }
+            if session.spill_failed {
+                content.push_str(&utils::fold_header("X-Honeypot-Truncated",
+                                                     &format!("spill file write failed, body incomplete ({} bytes received)",
+                                                              session.data_size)));
+            }
+            content.push_str("\r\n");
+            // Corps tel que reçu, octet pour octet
+            let mut content = content.into_bytes();
+            content.extend_from_slice(&session.body());
+            
+            // Une entrée d'index impossible à calculer ne coûte pas la capture elle-même
+            let index_entry = match &self.capture_index {
+                Some(_) => match self.index_entry(client_addr, session, &filepath).await {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        self.logger.log(client_addr, &format!("Capture index entry skipped for {}: {}",
+                                                              filepath.display(), e)).await;
+                        None
+                    }
+                },
+                None => None,
+            };
+            
+            let job = SaveJob {
+                client_addr: *client_addr,
+                data_dir: data_dir.clone(),
+                filepath,
+                tmp_path,
+                content,
+                spill: session.spill.take(),
+                truncated: session.spill_failed,
+                policy,
+                index_entry,
+                delivery,
+            };
+            match &self.save_queue {
+                Some(queue) => self.enqueue_save(queue, job).await,
+                None => self.write_capture(job).await?,
+            }
+        } else {
+            self.record_delivery(delivery).await;
+        }
+        Ok(())
+    }
+    
+    /// Confie l'écriture aux écrivains ; file pleine : la session attend une place
+    async fn enqueue_save(&self, queue: &tokio::sync::mpsc::Sender<SaveJob>, job: SaveJob) {
+        self.pending_saves.fetch_add(1, Ordering::Relaxed);
+        let job = match queue.try_send(job) {
+            Ok(()) => return,
+            Err(tokio::sync::mpsc::error::TrySendError::Full(job)) => {
+                let waits = self.save_queue_waits.fetch_add(1, Ordering::Relaxed) + 1;
+                self.logger.log(&job.client_addr,
+                                &format!("Save queue full ({} writes pending), waiting (total waits: {})",
+                                         self.pending_saves.load(Ordering::Relaxed), waits)).await;
+                job
+            }
+            Err(tokio::sync::mpsc::error::TrySendError::Closed(job)) => job,
+        };
+        // Écrivains arrêtés : l'écriture se fait dans la session
+        if let Err(tokio::sync::mpsc::error::SendError(job)) = queue.send(job).await {
+            let client_addr = job.client_addr;
+            if let Err(e) = self.write_capture(job).await {
+                self.capture_failed(&client_addr, e).await;
+            }
+            self.pending_saves.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+    
+    /// Écriture d'une capture dans un fichier temporaire puis renommage atomique
+    async fn write_capture(&self, mut job: SaveJob) -> Result<()> {
+        let spill = job.spill.take();
+        let written: Result<()> = async {
+            let mut file = self.opt.file_modes().create_file(&job.tmp_path).await?;
+            file.write_all(&job.content).await?;
+            if let Some(spill) = &spill {
+                let mut spilled = tokio::fs::File::open(&spill.path).await?;
+                tokio::io::copy(&mut spilled, &mut file).await?;
+            }
+            file.flush().await?;
+            file.sync_all().await?;
+            Ok(())
+        }.await;
+        if let Some(spill) = spill {
+            let _ = tokio::fs::remove_file(&spill.path).await;
+        }
+        
+        let renamed = match written {
+            Ok(()) => tokio::fs::rename(&job.tmp_path, &job.filepath).await.map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = renamed {
+            let _ = tokio::fs::remove_file(&job.tmp_path).await;
+            return Err(e);
+        }
+        // Une capture enregistrée vaut vérification : le stockage est de nouveau utilisable
+        self.set_storage_state(Ok(())).await;
+        self.record_delivery(job.delivery).await;
+        let mut event = Event::new(EventKind::Capture, job.client_addr,
+                                   format!("Email saved to: {:?} (policy: {}{})", job.filepath, job.policy.as_str(),
+                                           if job.truncated { ", truncated" } else { "" }))
+            .with("filename", job.filepath.display())
+            .with("data_dir", job.data_dir.display())
+            .with("policy", job.policy.as_str())
+            .with("mode", self.mode());
+        if job.truncated {
+            event = event.with("truncated", true);
+        }
+        self.logger.event(event).await;
+        if let (Some(index), Some(entry)) = (&self.capture_index, &job.index_entry) {
+            if let Err(e) = index.append(entry).await {
+                self.logger.log(&job.client_addr, &format!("Failed to write capture index {}: {}",
+                                                           index.path().display(), e)).await;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Ligne JSON résumant une capture pour --index-file
+    async fn index_entry(&self, client_addr: &SocketAddr, session: &session::SmtpSession, filepath: &Path) -> Result<String> {
+        let sha256 = utils::hex(&Self::body_sha256(session, openssl::sha::Sha256::new()).await?);
+        let rcpts: Vec<String> = session.rcpt_to.iter().map(|rcpt| utils::json_string(rcpt)).collect();
+        Ok(format!("{{\"timestamp\":{},\"ip\":{},\"helo\":{},\"mail_from\":{},\"rcpts\":[{}],\"sha256\":{},\"size\":{},\"filename\":{}}}",
+                   utils::json_string(&Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+                   utils::json_string(&client_addr.ip().to_string()),
+                   utils::json_string(session.helo.as_deref().unwrap_or("")),
+                   utils::json_string(session.mail_from.as_deref().unwrap_or("")),
+                   rcpts.join(","),
+                   utils::json_string(&sha256),
+                   session.data_size,
+                   utils::json_string(&filepath.display().to_string())))
+    }
+    
+    /// Échec d'une sauvegarde : journalisé, et le stockage est marqué inaccessible
+    async fn capture_failed(&self, client_addr: &SocketAddr, e: anyhow::Error) {
+        self.logger.log(client_addr, &format!("Failed to save email: {}", e)).await;
+        self.set_storage_state(Err(format!("{:#}", e))).await;
+    }
+    
+    /// À l'arrêt, laisse aux écrivains le temps de terminer les écritures en file
+    async fn wait_pending_saves(&self) {
+        let deadline = time::Instant::now() + SAVE_DRAIN_TIMEOUT;
+        let mut pending = self.pending_saves.load(Ordering::Relaxed);
+        if pending > 0 {
+            self.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                            &format!("Waiting for {} pending capture write(s)", pending)).await;
+        }
+        while pending > 0 && time::Instant::now() < deadline {
+            time::sleep(Duration::from_millis(50)).await;
+            pending = self.pending_saves.load(Ordering::Relaxed);
+        }
+        if pending > 0 {
+            self.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                            &format!("{} capture write(s) still pending at shutdown", pending)).await;
+        }
+    }
+    
+    fn protocol_name(&self) -> &'static str {
+        if self.opt.lmtp { "LMTP" } else { "SMTP" }
+    }
+    
+    /// Délestage adaptatif : actif tant que les sessions dépassent le seuil haut
+    async fn throttle_engaged(&self) -> bool {
+        let high_water = match self.opt.adaptive_throttle {
+            Some(high_water) => high_water,
+            None => return false,
+        };
+        
+        let active = self.active_sessions.load(Ordering::Relaxed);
+        let engaged = active > high_water;
+        if self.throttling.swap(engaged, Ordering::Relaxed) != engaged {
+            let state = if engaged { "engaged" } else { "disengaged" };
+            self.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                            &format!("Adaptive throttling {} ({} active sessions, high-water mark {})",
+                                     state, active, high_water)).await;
+        }
+        engaged
+    }
+    
+    /// Persona associée à un point d'écoute : son profil, sinon celle de son port
+    fn persona_for_listener(&self, listener: &ListenerDef) -> Option<&DomainPersona> {
+        match &listener.profile {
+            Some(profile) => self.persona_for_domain(profile),
+            None => self.opt.domain_personas.iter().find(|p| p.port == Some(listener.port)),
+        }
+    }
+    
+    /// Persona associée à un domaine de destination
+    fn persona_for_domain(&self, domain: &str) -> Option<&DomainPersona> {
+        self.opt.domain_personas.iter().find(|p| p.domain.eq_ignore_ascii_case(domain))
+    }
+    
+    /// Persona dont le domaine est celui annoncé en EHLO, ou l'un de ses parents
+    fn persona_for_helo(&self, helo: &str) -> Option<&DomainPersona> {
+        let helo = helo.trim_end_matches('.').to_lowercase();
+        self.opt.domain_personas.iter().find(|p| {
+            let domain = p.domain.to_lowercase();
+            helo == domain || helo.ends_with(&format!(".{}", domain))
+        })
+    }
+    
+    fn helo_name<'a>(&'a self, session: &'a session::SmtpSession) -> &'a str {
+        session.persona.as_ref()
+            .and_then(|p| p.helo.as_deref())
+            .unwrap_or(&self.opt.helo)
+    }
+    
+    fn banner_text<'a>(&'a self, session: &'a session::SmtpSession) -> &'a str {
+        session.persona.as_ref()
+            .and_then(|p| p.banner.as_deref())
+            .or_else(|| self.random_persona.as_ref().filter(|_| !self.opt.lmtp).map(|p| p.banner))
+            .unwrap_or_else(|| self.protocol_name())
+    }
+    
+    /// Bannière 220 ; en mode sinkhole, le nom seul
+    fn banner_line(&self, session: &session::SmtpSession, tls: bool) -> String {
+        if self.opt.sinkhole {
+            format!("220 {}\r\n", self.helo_name(session))
+        } else if tls {
+            format!("220 {} {} (TLS)\r\n", self.helo_name(session), self.banner_text(session))
+        } else {
+            format!("220 {} {} \r\n", self.helo_name(session), self.banner_text(session))
+        }
+    }
+    
+    /// Mode de fonctionnement, étiquette des transactions et captures
+    fn mode(&self) -> &'static str {
+        if self.opt.sinkhole { "sinkhole" } else { "normal" }
+    }
+    
+    /// Message de rejet RCPT : persona du domaine visé, puis celle de la session,
+    /// puis --reject-code/--reject-message ; `{arg}` reprend le destinataire
+    fn reject_message(&self, session: &session::SmtpSession, recipient: &str) -> String {
+        let template = recipient.split_once('@')
+            .and_then(|(_, domain)| self.persona_for_domain(domain))
+            .and_then(|p| p.reject.clone())
+            .or_else(|| session.persona.as_ref().and_then(|p| p.reject.clone()))
+            .unwrap_or_else(|| Self::default_reject_template(&self.opt));
+        self.render_error(session, &template, recipient)
+    }
+    
+    /// Modèle tiré de --reject-code/--reject-message ; le code ne préfixe que la première variante
+    fn default_reject_template(opt: &Opt) -> String {
+        format!("{} {}", opt.reject_code.unwrap_or(550), opt.reject_message.as_deref().unwrap_or("No such user"))
+    }
+    
+    /// Filtre de destinataire par défaut : domaines acceptés et boîtes valides
+    fn default_recipient_decision(&self, session: &session::SmtpSession, recipient: &str) -> Decision {
+        if self.is_valid_recipient(recipient) {
+            Decision::Accept
+        } else {
+            Decision::from_reply(&self.reject_message(session, recipient))
+        }
+    }
+    
+    /// Erreur de syntaxe MAIL/RCPT : modèle `syntax` de la persona, sinon réponse fixe
+    fn syntax_error(&self, session: &session::SmtpSession, arg: &str) -> String {
+        match session.persona.as_ref().and_then(|p| p.syntax.as_deref()) {
+            Some(template) => self.render_error(session, template, arg),
+            None => "501 Syntax error in parameters".to_string(),
+        }
+    }
+    
+    /// Variante tirée par session et par commande : deux sessions ne voient pas la même suite
+    fn render_error(&self, session: &session::SmtpSession, template: &str, arg: &str) -> String {
+        persona::render_error(template, arg, utils::seeded_unit(session.id, session.command_count as u64))
+    }
+    
+    /// Ajoute une ligne de DATA, en mémoire puis sur disque au-delà du seuil
+    /// Ligne du corps, fin de ligne comprise ; `complete` faux pour un morceau de ligne trop longue
+    async fn push_data_line(&self, session: &mut session::SmtpSession, raw: &[u8], complete: bool) {
+        // Suite d'une ligne lue par morceaux : rattachée à la précédente
+        let continuation = std::mem::replace(&mut session.data_partial, !complete);
+        let held_cr = std::mem::take(&mut session.data_held_cr) && !raw.starts_with(b"\n");
+        let mut line = if complete { strip_line_ending(raw) } else { raw };
+        if !complete && line.ends_with(b"\r") {
+            line = &line[..line.len() - 1];
+            session.data_held_cr = true;
+        }
+        let line = if held_cr { [b"\r", line].concat() } else { line.to_vec() };
+        
+        if continuation && session.too_many_lines {
+            return;
+        }
+        // Au-delà du nombre maximal de lignes, on compte sans plus rien stocker
+        if let (false, Some(max_lines)) = (continuation, self.opt.max_data_lines) {
+            if session.data_lines >= max_lines {
+                if !session.too_many_lines {
+                    self.logger.log(&session.client_addr, &format!("DATA exceeded {} lines, discarding message", max_lines)).await;
+                    session.too_many_lines = true;
+                }
+                session.data_lines += 1;
+                return;
+            }
+        }
         
-        let logger = Logger::new(opt.log_file.clone(), opt.raw_display)?;
+        let separator = if session.data_lines > 0 && !continuation { "\r\n" } else { "" };
+        session.data_size += separator.len() + line.len();
+        if !continuation {
+            session.data_lines += 1;
+        }
         
-        // Créer le dossier data si spécifié
-        if let Some(data_dir) = &opt.data_dir {
-            eprintln!("[DEBUG] Checking data directory: {:?}", data_dir);
-            if !data_dir.exists() {
-                eprintln!("[DEBUG] Creating data directory: {:?}", data_dir);
-                std::fs::create_dir_all(data_dir)
-                    .with_context(|| format!("Failed to create data directory: {:?}", data_dir))?;
-                eprintln!("[INFO] Data directory created: {:?}", data_dir);
-            } else {
-                eprintln!("[DEBUG] Data directory already exists: {:?}", data_dir);
+        let over_threshold = self.opt.spill_threshold
+            .is_some_and(|threshold| session.data_size > threshold);
+        
+        if over_threshold && session.spill.is_none() {
+            match self.reserve_spill_slot() {
+                Some(slot) => match self.open_spill_file(slot).await {
+                    Ok(spill) => {
+                        self.logger.log(&session.client_addr,
+                                        &format!("DATA exceeded spill threshold ({} bytes), streaming to {:?}",
+                                                 session.data_size, spill.path)).await;
+                        session.spill = Some(spill);
+                        session.spilled = true;
+                    }
+                    Err(e) => {
+                        self.logger.log(&session.client_addr, &format!("Failed to open spill file: {}", e)).await;
+                    }
+                },
+                // Nouvel essai à chaque ligne : une place peut se libérer avant la fin du DATA
+                None if !session.spill_deferred => {
+                    session.spill_deferred = true;
+                    self.spill_fallbacks.fetch_add(1, Ordering::Relaxed);
+                    self.logger.log(&session.client_addr,
+                                    &format!("Open spill file cap reached ({} open), buffering DATA in memory",
+                                             self.open_spill_files.load(Ordering::Relaxed))).await;
+                }
+                None => {}
             }
         }
         
-        // Configurer TLS avec RustLS
-        let tls_acceptor = if let (Some(cert_path), Some(key_path)) = (&opt.tls_cert, &opt.tls_key) {
-            eprintln!("[DEBUG] Loading TLS certificate from: {:?}", cert_path);
-            
-            // Lire le certificat
-            let cert_file = &mut std::fs::File::open(cert_path)
-                .with_context(|| format!("Failed to open certificate: {:?}", cert_path))?;
-            let mut cert_reader = StdBufReader::new(cert_file);
-            let cert_chain = certs(&mut cert_reader)
-                .map_err(|_| anyhow::anyhow!("Failed to parse certificate"))?
-                .into_iter()
-                .map(Certificate)
-                .collect();
-            
-            // Lire la clé privée
-            eprintln!("[DEBUG] Loading private key from: {:?}", key_path);
-            let key_file = &mut std::fs::File::open(key_path)
-                .with_context(|| format!("Failed to open private key: {:?}", key_path))?;
-            let mut key_reader = StdBufReader::new(key_file);
-            let mut keys = pkcs8_private_keys(&mut key_reader)
-                .map_err(|_| anyhow::anyhow!("Failed to parse private key"))?;
-            
-            if keys.is_empty() {
-                return Err(anyhow::anyhow!("No private key found"));
+        if let Some(spill) = session.spill.as_mut() {
+            // Après un échec, plus rien n'est écrit : pas de trou au milieu du corps enregistré
+            if !session.spill_failed {
+                let chunk = [separator.as_bytes(), &line].concat();
+                if let Err(e) = spill.file.write_all(&chunk).await {
+                    session.spill_failed = true;
+                    self.logger.log(&session.client_addr, &format!("Failed to write spill file, capture will be truncated: {}", e)).await;
+                }
             }
-            
-            let private_key = PrivateKey(keys.remove(0));
-            
-            // Configurer le serveur TLS
-            eprintln!("[DEBUG] Building TLS server config...");
-            let config = ServerConfig::builder()
-                .with_safe_defaults()
-                .with_no_client_auth()
-                .with_single_cert(cert_chain, private_key)
-                .map_err(|e| anyhow::anyhow!("Failed to build TLS config: {}", e))?;
-            
-            let acceptor = TlsAcceptor::from(Arc::new(config));
-            
-            eprintln!("[INFO] TLS enabled with certificate: {:?}", cert_path);
-            Some(Arc::new(acceptor))
+        } else if let (true, Some(last)) = (continuation, session.data.last_mut()) {
+            last.extend_from_slice(&line);
         } else {
-            if opt.ports.contains(&465) || opt.ports.contains(&587) {
-                eprintln!("[WARNING] TLS ports specified but no certificates provided");
+            session.data.push(line);
+        }
+    }
+    
+    /// Place pour un nouveau fichier de débordement ; aucune si --max-open-spill-files est atteint
+    fn reserve_spill_slot(&self) -> Option<session::SpillSlot> {
+        let permit = match &self.spill_slots {
+            Some(slots) => Some(slots.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        Some(session::SpillSlot::new(self.open_spill_files.clone(), permit))
+    }
+    
+    async fn open_spill_file(&self, slot: session::SpillSlot) -> Result<session::SpillFile> {
+        static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+        let dir = self.opt.data_dirs.first().cloned().unwrap_or_else(std::env::temp_dir);
+        let path = dir.join(format!(".spill_{}_{}.tmp", std::process::id(),
+                                    SPILL_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let file = self.opt.file_modes().create_file(&path).await
+            .with_context(|| format!("Failed to create spill file: {:?}", path))?;
+        Ok(session::SpillFile { path, file, _slot: slot })
+    }
+    
+    /// Réponse au point final ; en LMTP, une ligne de statut par destinataire accepté
+    fn data_response(&self, session: &session::SmtpSession, status: &str) -> String {
+        if self.opt.lmtp {
+            session.rcpt_to.iter()
+                .map(|rcpt| format!("{} for <{}>\r\n", status, rcpt))
+                .collect()
+        } else {
+            format!("{}\r\n", status)
+        }
+    }
+    
+    /// Termine la phase DATA : journalise, sauvegarde et construit la réponse
+    async fn complete_data(&self, session: &mut session::SmtpSession) -> String {
+        let client_addr = session.client_addr;
+        session.expecting_data = false;
+        session.message_seq += 1;
+        
+        if session.too_many_lines {
+            let reason = format!("{} lines exceed the limit of {}", session.data_lines, self.opt.max_data_lines.unwrap_or(0));
+            return self.discard_message(session, &reason, "552 Too many lines in message").await;
+        }
+        if let Some(limit) = self.size_limit().filter(|limit| session.data_size as u64 > *limit) {
+            let reason = format!("{} bytes exceed the advertised SIZE of {}", session.data_size, limit);
+            return self.discard_message(session, &reason, "552 Message size exceeds fixed maximum message size").await;
+        }
+        
+        if self.opt.data_policy == DataPolicy::Capture {
+            let mut details = utils::escape_bytes(&session.body());
+            if session.spill.is_some() {
+                details.push_str(&format!("\r\n[... body truncated, {} bytes total, remainder spilled to disk]", session.data_size));
             }
-            eprintln!("[DEBUG] TLS not enabled");
-            None
+            self.logger.log_verbose(&client_addr, "EMAIL DATA", &details).await;
+        }
+        
+        if let Some(spill) = session.spill.as_mut() {
+            if let Err(e) = spill.file.flush().await {
+                session.spill_failed = true;
+                self.logger.log(&client_addr, &format!("Failed to flush spill file, capture will be truncated: {}", e)).await;
+            }
+        }
+        
+        let delivery = self.check_retry(session).await;
+        if let Some(origin) = session.retry_of {
+            self.logger.log(&client_addr, &format!("Retry of s{}/m{}, attempt {} after {}s: duplicate not stored",
+                                                   origin.session_id, origin.message_seq, origin.attempt,
+                                                   origin.interval.as_secs())).await;
+        } else if let Err(e) = self.save_email_data(&client_addr, session, delivery).await {
+            self.capture_failed(&client_addr, e).await;
+        }
+        
+        self.check_declared_size(session).await;
+        
+        let response = if self.storage_refused() {
+            self.data_response(session, "452 Insufficient storage")
+        } else {
+            self.data_response(session, "250 OK: Message accepted")
         };
         
-        eprintln!("[DEBUG] SmtpHoneypot::new() completed successfully");
+        self.count_response(&response);
         
-        Ok(Self {
-            opt: opt.clone(),
-            logger,
-            rate_limiter: Arc::new(Mutex::new(ratelimiter::RateLimiter::new(opt.max_connections_per_minute))),
-            valid_mailboxes: opt.valid_mailboxes.clone(),
-            tls_acceptor,
-        })
+        let label = match session.retry_of {
+            Some(origin) => format!("Transaction (retry of s{}/m{}, attempt {})",
+                                    origin.session_id, origin.message_seq, origin.attempt),
+            None => "Transaction".to_string(),
+        };
+        self.logger.event(self.transaction_event(session, &label)).await;
+        
+        session.reset();
+        response
     }
     
-    async fn save_email_data(&self, client_addr: &SocketAddr, session: &session::SmtpSession) -> Result<()> {
-        if let Some(data_dir) = &self.opt.data_dir {
-            let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-            let filename = format!("{}_{}.eml", timestamp, client_addr.ip().to_string().replace('.', "_"));
-            let filepath = data_dir.join(filename);
-            
-            let mut content = String::new();
-            content.push_str(&format!("X-Honeypot-Client: {}\r\n", client_addr));
-            content.push_str(&format!("X-Honeypot-Date: {}\r\n", Local::now().format("%Y-%m-%d %H:%M:%S")));
-            if let Some(helo) = &session.helo {
-                content.push_str(&format!("X-Honeypot-HELO: {}\r\n", helo));
+    /// Message refusé au point final : rien n'est enregistré
+    async fn discard_message(&self, session: &mut session::SmtpSession, reason: &str, status: &str) -> String {
+        self.logger.log(&session.client_addr, &format!("Message discarded: {}", reason)).await;
+        self.check_declared_size(session).await;
+        let response = self.data_response(session, status);
+        self.count_response(&response);
+        self.logger.event(self.transaction_event(session, "Transaction")).await;
+        session.reset();
+        response
+    }
+    
+    /// Taille maximale annoncée en EHLO par le profil tiré (--randomize-persona)
+    fn size_limit(&self) -> Option<u64> {
+        self.random_persona.as_ref()?.size_limit()
+    }
+    
+    /// --on-storage-error refuse et stockage connu inaccessible
+    fn storage_refused(&self) -> bool {
+        self.opt.on_storage_error == StorageErrorPolicy::Refuse && !self.storage_writable.load(Ordering::Relaxed)
+    }
+    
+    /// Annonce d'AUTH en EHLO (--auth-advertise-rate) : tirage reproductible, une fois par connexion
+    async fn advertise_auth(&self, session: &mut session::SmtpSession) -> bool {
+        let (Some(rate), Some(seed)) = (self.opt.auth_advertise_rate, self.opt.auth_advertise_seed) else {
+            return false;
+        };
+        if let Some(advertised) = session.auth_advertised {
+            return advertised;
+        }
+        let advertised = utils::seeded_unit(seed, session.id) < rate;
+        session.auth_advertised = Some(advertised);
+        self.logger.log(&session.client_addr,
+                        &format!("AUTH advertisement: {} (rate {}, seed {}, draw {})",
+                                 if advertised { "yes" } else { "no" }, rate, seed, session.id)).await;
+        advertised
+    }
+    
+    /// Liste des commandes réellement gérées, cohérente avec ce qui est annoncé en EHLO
+    fn supported_commands(&self, session: &session::SmtpSession) -> Vec<&'static str> {
+        let mut commands = if self.opt.lmtp {
+            vec!["LHLO"]
+        } else {
+            vec!["HELO", "EHLO"]
+        };
+        if session.starttls_enabled && !session.tls_active && self.tls_acceptor.is_some() {
+            commands.push("STARTTLS");
+        }
+        if !self.opt.sinkhole {
+            commands.push("AUTH");
+        }
+        commands.extend(["MAIL", "RCPT", "DATA", "RSET", "NOOP", "VRFY", "EXPN", "HELP", "QUIT"]);
+        commands
+    }
+    
+    /// Signale les octets déjà reçus après une commande de synchronisation (RFC 2920)
+    async fn check_pipelining(&self, session: &mut session::SmtpSession, cmd_line: &str, buffered: &[u8]) {
+        if buffered.is_empty() {
+            return;
+        }
+        
+        let verb = cmd_line.split_whitespace().next().unwrap_or("").to_uppercase();
+        if matches!(verb.as_str(), "EHLO" | "HELO" | "LHLO" | "DATA" | "QUIT" | "STARTTLS") {
+            session.add_signal(Signal::PipeliningViolation);
+            self.logger.log(&session.client_addr,
+                            &format!("Pipelining violation: {} bytes sent after {} before reply", buffered.len(), verb)).await;
+        }
+    }
+    
+    /// --data-prompt-delay : retient le 354, puis relève le corps envoyé sans l'attendre
+    async fn delay_data_prompt<R: AsyncBufRead + Unpin>(&self, reader: &mut R, session: &mut session::SmtpSession) {
+        let Some(delay) = self.opt.data_prompt_delay else {
+            return;
+        };
+        time::sleep(Duration::from_millis(delay)).await;
+        
+        // Une seule scrutation : les octets arrivés passent dans le tampon, rien n'est consommé
+        let early = std::future::poll_fn(|cx| match Pin::new(&mut *reader).poll_fill_buf(cx) {
+            Poll::Ready(Ok(buf)) => Poll::Ready(buf.len()),
+            _ => Poll::Ready(0),
+        }).await;
+        if early > 0 {
+            session.add_signal(Signal::BodyBeforePrompt);
+            self.logger.log(&session.client_addr,
+                            &format!("Body sent before 354: {} bytes within {}ms of DATA", early, delay)).await;
+        }
+    }
+    
+    /// Compte la commande ; retourne la réponse 421 si --max-commands est dépassé
+    async fn check_command_limit(&self, session: &mut session::SmtpSession, raw_line: &str) -> Option<String> {
+        session.record_command(raw_line);
+        let max = self.opt.max_commands?;
+        if session.command_count <= max {
+            return None;
+        }
+        session.disposition = "command-limit";
+        self.logger.log(&session.client_addr,
+                        &format!("Command limit reached ({} commands), closing", max)).await;
+        Some("421 Too many commands\r\n".to_string())
+    }
+    
+    /// Débit de commandes de la session (--max-commands-per-second) : retarde la commande
+    /// ou renvoie la réponse de refus
+    async fn throttle_command(&self, session: &mut session::SmtpSession) -> Option<String> {
+        let max = self.opt.max_commands_per_second?;
+        let wait = session.command_rate_excess(max)?;
+        session.rate_limited_commands += 1;
+        if session.rate_limited_commands == 1 {
+            self.logger.log(&session.client_addr,
+                            &format!("Command rate limit exceeded ({} per second), action: {}",
+                                     max, self.opt.command_rate_action.as_str())).await;
+        }
+        
+        match self.opt.command_rate_action {
+            CommandRateAction::Tarpit => {
+                time::sleep(wait).await;
+                // La commande compte à l'instant où elle est réellement traitée
+                if let Some(last) = session.recent_commands.back_mut() {
+                    *last += wait;
+                }
+                None
             }
-            if let Some(mail_from) = &session.mail_from {
-                content.push_str(&format!("X-Honeypot-MailFrom: {}\r\n", mail_from));
+            CommandRateAction::Reject => {
+                let response = "450 Too many commands, slow down\r\n".to_string();
+                self.count_response(&response);
+                Some(response)
             }
-            for rcpt in &session.rcpt_to {
-                content.push_str(&format!("X-Honeypot-RcptTo: {}\r\n", rcpt));
+        }
+    }
+    
+    /// Lit brièvement après le 221 : un client réel ferme, certains robots continuent d'écrire
+    async fn observe_after_quit<R: AsyncRead + Unpin>(&self, reader: &mut BufReader<R>, session: &mut session::SmtpSession) {
+        let mut received = reader.buffer().to_vec();
+        reader.consume(received.len());
+        let deadline = time::Instant::now() + POST_QUIT_WINDOW;
+        let mut chunk = [0u8; 1024];
+        while received.len() < POST_QUIT_MAX_BYTES {
+            match time::timeout_at(deadline, reader.read(&mut chunk)).await {
+                Ok(Ok(n)) if n > 0 => received.extend_from_slice(&chunk[..n]),
+                _ => break,
+            }
+        }
+        
+        if !received.is_empty() {
+            received.truncate(POST_QUIT_MAX_BYTES);
+            session.add_signal(Signal::PostQuitData);
+            self.logger.log(&session.client_addr,
+                            &format!("Post-QUIT data ({} bytes): {}", received.len(),
+                                     utils::safe_log_string(&String::from_utf8_lossy(&received)))).await;
+        }
+    }
+    
+    /// Compare la taille annoncée (SIZE=) à la taille reçue
+    async fn check_declared_size(&self, session: &mut session::SmtpSession) {
+        let Some(declared) = session.declared_size else {
+            return;
+        };
+        let actual = session.data_size as u64;
+        let tolerance = ((declared as f64 * SIZE_TOLERANCE_RATIO) as u64).max(SIZE_TOLERANCE_MIN);
+        if actual.abs_diff(declared) > tolerance {
+            session.add_signal(Signal::SizeMismatch);
+            self.logger.log(&session.client_addr,
+                            &format!("SIZE mismatch: declared {} bytes, received {} bytes", declared, actual)).await;
+        }
+    }
+    
+    /// Envoie la bannière, octet par octet avec --banner-drip
+    ///
+    /// Retourne faux si le client s'est déconnecté pendant l'envoi lent.
+    async fn send_banner<W: AsyncWrite + Unpin>(&self, writer: &mut W, session: &mut session::SmtpSession, banner: &str) -> Result<bool> {
+        let Some(drip) = self.opt.banner_drip else {
+            writer.write_all(banner.as_bytes()).await?;
+            return Ok(true);
+        };
+        
+        let bytes = banner.as_bytes();
+        for (sent, byte) in bytes.iter().enumerate() {
+            if sent > 0 {
+                time::sleep(Duration::from_millis(drip)).await;
+            }
+            let written = match writer.write_all(std::slice::from_ref(byte)).await {
+                Ok(()) => writer.flush().await,
+                Err(e) => Err(e),
+            };
+            if written.is_err() {
+                session.add_signal(Signal::ImpatientBannerGrab);
+                self.logger.log(&session.client_addr,
+                                &format!("Impatient banner grab: disconnected after {}/{} banner bytes",
+                                         sent, bytes.len())).await;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+    
+    /// MAIL ou RCPT démesuré : argument de commande suspect (injection cachée dans l'adresse)
+    async fn oversized_argument(&self, session: &mut session::SmtpSession, verb: &str, line: &str) {
+        session.add_signal(Signal::OversizedArgument);
+        let sample: String = line.chars().take(RAW_STREAM_SAMPLE).collect();
+        self.logger.log(&session.client_addr,
+                        &format!("Oversized command argument: {} line of {} bytes, first bytes: {}",
+                                 verb, line.len(), sample.escape_default())).await;
+    }
+    
+    /// Ligne de commande trop longue : flux brut si rien n'a encore été reçu sous forme de lignes
+    async fn line_too_long(&self, session: &mut session::SmtpSession, line: &str) -> String {
+        session.disposition = "line-too-long";
+        let verb = line.split_whitespace().next().unwrap_or("").to_uppercase();
+        if matches!(verb.as_str(), "MAIL" | "RCPT") {
+            self.oversized_argument(session, &verb, line).await;
+        } else if session.command_count == 0 {
+            session.add_signal(Signal::RawStream);
+            let sample: String = line.chars().take(RAW_STREAM_SAMPLE).collect();
+            self.logger.log(&session.client_addr,
+                            &format!("Raw stream: no CRLF within {} bytes, first bytes: {}",
+                                     MAX_LINE_BYTES, sample.escape_default())).await;
+        } else {
+            self.logger.log(&session.client_addr,
+                            &format!("Line too long (more than {} bytes), closing", MAX_LINE_BYTES)).await;
+        }
+        "500 Line too long\r\n".to_string()
+    }
+    
+    /// Vrai si le domaine du destinataire n'est pas un domaine local
+    fn is_external_recipient(&self, recipient: &str) -> bool {
+        match recipient.rsplit_once('@') {
+            Some((_, domain)) => !self.opt.domains.iter().any(|d| d.eq_ignore_ascii_case(domain)),
+            None => false,
+        }
+    }
+    
+    /// Fin de connexion : enregistre une éventuelle transaction inachevée
+    async fn close_session(&self, session: &mut session::SmtpSession) {
+        if session.command_count == 0 {
+            session.add_signal(Signal::ImmediateDisconnect);
+        }
+        if session.mail_from.is_some() || !session.rcpt_to.is_empty() || session.relay_attempted {
+            self.logger.event(self.transaction_event(session, "Transaction (incomplete)")).await;
+        }
+        
+        if session.rate_limited_commands > 0 {
+            self.logger.log(&session.client_addr,
+                            &format!("Session command-rate-limited: {} commands over {} per second",
+                                     session.rate_limited_commands,
+                                     self.opt.max_commands_per_second.unwrap_or_default())).await;
+        }
+        
+        if self.opt.alert_line_endings && session.line_endings.is_irregular() {
+            let endings = session.line_endings;
+            self.logger.event(Event::new(EventKind::Alert, session.client_addr,
+                                         format!("ALERT: non-CRLF line endings ({})", endings.class()))
+                .with("alert", "line-endings")
+                .with("line_endings", endings.class())
+                .with("crlf_lines", endings.crlf)
+                .with("bare_lf_lines", endings.bare_lf)
+                .with("bare_cr_lines", endings.bare_cr)).await;
+        }
+        
+        let (score, contributions) = self.scorer.score(&session.signals);
+        if session.dropped {
+            session.disposition = "dropped";
+        } else if session.close_after_reply && session.disposition == "disconnected" {
+            session.disposition = "server-closed";
+        }
+        let (mail_from, rcpt_count, data_bytes) = session.connection_totals();
+        self.logger.event(Event::new(EventKind::Connection, session.client_addr, "Connection closed")
+            .with("port", session.port)
+            .with("helo", session.helo.as_deref().unwrap_or(""))
+            .with("mail_from", mail_from.unwrap_or(""))
+            .with("rcpt_count", rcpt_count)
+            .with("data_bytes", data_bytes)
+            .with("tls", session.tls_info.as_ref().and_then(|t| t.version.as_deref()).unwrap_or(""))
+            .with("auth_attempts", session.auth_attempts)
+            .with("disposition", session.disposition)
+            .with("rate_limited_commands", session.rate_limited_commands)
+            .with("line_endings", session.line_endings.class())
+            .with("score", score)
+            .with("score_signals", scoring::format_contributions(&contributions))).await;
+        
+        if let Some(ban_score) = self.opt.ban_score {
+            if score >= ban_score && self.banned.lock().await.insert(session.client_addr.ip()) {
+                self.logger.event(Event::new(EventKind::Alert, session.client_addr,
+                                             format!("ALERT: client banned (threat score {} >= {})", score, ban_score))
+                    .with("alert", "banned")
+                    .with("score", score)
+                    .with("score_signals", scoring::format_contributions(&contributions))).await;
             }
-            content.push_str("\r\n");
-            content.push_str(&session.data.join("\r\n"));
-            
-            tokio::fs::write(&filepath, content).await?;
-            self.logger.log(client_addr, &format!("Email saved to: {:?}", filepath)).await;
         }
-        Ok(())
+    }
+    
+    fn transaction_event(&self, session: &session::SmtpSession, label: &str) -> Event {
+        let signals: Vec<&str> = session.signals.iter().map(|s| s.as_str()).collect();
+        let (score, contributions) = self.scorer.score(&session.signals);
+        let (gap_min, gap_median, gap_max) = match session.gap_summary() {
+            Some((min, median, max)) => (min.to_string(), median.to_string(), max.to_string()),
+            None => Default::default(),
+        };
+        let mut event = Event::new(EventKind::Transaction, session.client_addr,
+                                   format!("{}: {} score={}", label, session.transaction_summary(), score))
+            .with("helo", session.helo.as_deref().unwrap_or(""))
+            .with("helo_class", session.helo_class.map(|c| c.as_str()).unwrap_or(""))
+            .with("mail_from", session.mail_from.as_deref().unwrap_or(""))
+            .with("rcpt_to", session.rcpt_to.join(","))
+            .with("signals", signals.join(","))
+            .with("relay_attempted", session.relay_attempted)
+            .with("would_reject", session.would_reject.join(","))
+            .with("size_declared", session.declared_size.map(|size| size.to_string()).unwrap_or_default())
+            .with("size", session.data_size)
+            .with("command_gaps", session.command_gaps().iter()
+                .map(|(label, gap)| format!("{}:{}", label, gap))
+                .collect::<Vec<_>>()
+                .join(","))
+            .with("gap_min_ms", gap_min)
+            .with("gap_median_ms", gap_median)
+            .with("gap_max_ms", gap_max)
+            .with("score", score)
+            .with("score_signals", scoring::format_contributions(&contributions))
+            .with("mode", self.mode())
+            .with("retry_of", session.retry_of
+                .map(|origin| format!("s{}/m{}", origin.session_id, origin.message_seq))
+                .unwrap_or_default())
+            .with("retry_attempt", session.retry_of.map(|origin| origin.attempt.to_string()).unwrap_or_default())
+            .with("retry_interval_s", session.retry_of
+                .map(|origin| origin.interval.as_secs().to_string())
+                .unwrap_or_default())
+            .with("command_format", session.command_formats().join(","))
+            .with("line_endings", session.line_endings.class())
+            .with("client_cert_subject", session.client_cert.as_ref().map(|c| c.subject.as_str()).unwrap_or(""))
+            .with("client_cert_issuer", session.client_cert.as_ref().map(|c| c.issuer.as_str()).unwrap_or(""))
+            .with("client_cert_sha256", session.client_cert.as_ref().map(|c| c.sha256.as_str()).unwrap_or(""))
+            .with("auth_advertised", match session.auth_advertised {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "",
+            })
+            .with("tls_source", session.tls_info.as_ref().map(|t| t.source).unwrap_or(""))
+            .with("tls_version", session.tls_info.as_ref().and_then(|t| t.version.as_deref()).unwrap_or(""))
+            .with("tls_cipher", session.tls_info.as_ref().and_then(|t| t.cipher.as_deref()).unwrap_or(""))
+            .with("tls_sni", session.tls_info.as_ref().and_then(|t| t.sni.as_deref()).unwrap_or(""))
+            .with("tls_handshake", session.tls_handshake.as_ref().map(|h| h.kind()).unwrap_or(""))
+            .with("tls_resumed_id", session.tls_handshake.as_ref().and_then(|h| h.resumed.as_deref()).unwrap_or(""))
+            .with("tls_issued_id", session.tls_handshake.as_ref().and_then(|h| h.issued.as_deref()).unwrap_or(""))
+            .with("auth_mechanisms", session.auth_mechanisms.join(","))
+            .with("auth_enumerations", session.auth_enumerations);
+        
+        // En-têtes du message lui-même, distincts de l'enveloppe ; rien avant le DATA
+        if session.data_lines > 0 {
+            let headers = MessageHeaders::parse(&session.data, session.spilled);
+            event = event
+                .with("header_section", headers.section())
+                .with("header_count", headers.count);
+            for (_, field) in mailheaders::KEY_HEADERS {
+                event = event.with(field, headers.get(field));
+            }
+        }
+        event
     }
     
     fn is_valid_recipient(&self, recipient: &str) -> bool {
-        if self.opt.open_relay {
+        if self.opt.open_relay || self.is_honey_mailbox(recipient) {
             return true;
         }
         
@@ -152,7 +1808,108 @@ impl SmtpHoneypot {
         false
     }
     
+    /// Recherche faite pour une connexion (--log-lookups), sur la chronologie de l'adresse du client
+    /// et rattachée à sa session
+    async fn log_lookup(&self, client_addr: &SocketAddr, session_id: u64, kind: &str, query: &str, answer: &str) {
+        self.logger.log(client_addr, &format!("Lookup s{} {}: {} -> {}", session_id, kind, query, answer)).await;
+    }
+    
+    fn is_honey_mailbox(&self, recipient: &str) -> bool {
+        self.opt.honey_mailboxes.iter().any(|mb| mb.eq_ignore_ascii_case(recipient))
+    }
+    
+    /// Boîte leurre visée : un événement par sondage et un compteur par boîte
+    async fn honey_mailbox_probed(&self, session: &session::SmtpSession, mailbox: &str, command: &str) {
+        let mailbox = mailbox.to_ascii_lowercase();
+        let hits = {
+            let mut hits = self.honey_hits.lock().unwrap();
+            let count = hits.entry(mailbox.clone()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        self.logger.event(Event::new(EventKind::Probe, session.client_addr,
+                                     format!("Honey mailbox probed: {} ({}, hit {})", mailbox, command, hits))
+            .with("mailbox", &mailbox)
+            .with("command", command)
+            .with("hits", hits)
+            .with("mail_from", session.mail_from.as_deref().unwrap_or(""))).await;
+    }
+    
+    /// Sondages par boîte leurre, au format `boîte=nombre ...`
+    fn honey_hit_summary(&self) -> Option<String> {
+        let hits = self.honey_hits.lock().ok()?;
+        if hits.is_empty() {
+            return None;
+        }
+        Some(hits.iter().map(|(mailbox, count)| format!("{}={}", mailbox, count)).collect::<Vec<_>>().join(" "))
+    }
+    
+    /// Compte le code de la réponse (premier nombre de la première ligne)
+    fn count_response(&self, response: &str) {
+        if let Some(code) = response.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+            if let Ok(mut codes) = self.response_codes.lock() {
+                *codes.entry(code).or_insert(0) += 1;
+            }
+        }
+    }
+    
+    /// Répartition des codes de réponse émis, au format `code=nombre ...`
+    fn response_code_summary(&self) -> Option<String> {
+        let codes = self.response_codes.lock().ok()?;
+        if codes.is_empty() {
+            return None;
+        }
+        Some(codes.iter().map(|(code, count)| format!("{}={}", code, count)).collect::<Vec<_>>().join(" "))
+    }
+    
+    /// Suite d'un échange AUTH : identifiants journalisés, 235 une fois l'échange mené à terme
+    async fn auth_continuation(&self, session: &mut session::SmtpSession, step: AuthExchange, line: &str) -> String {
+        let line = line.trim();
+        if line == "*" {
+            self.logger.log(&session.client_addr, &format!("AUTH {} cancelled by client", step.mechanism())).await;
+            return "501 Authentication cancelled\r\n".to_string();
+        }
+        let Some(decoded) = utils::decode_sasl_response(line) else {
+            self.logger.log(&session.client_addr, &format!("AUTH {} response is not base64", step.mechanism())).await;
+            return "501 Invalid base64 data\r\n".to_string();
+        };
+        
+        let what = match step {
+            AuthExchange::Plain => "credentials",
+            AuthExchange::LoginUsername => "username",
+            AuthExchange::LoginPassword => "password",
+        };
+        self.logger.log_verbose(&session.client_addr, &format!("AUTH {}", what),
+                                &format!("mechanism: {}\nraw: {}\ndecoded: {}",
+                                         step.mechanism(), line, decoded)).await;
+        self.logger.dispatch(&Event::new(EventKind::Auth, session.client_addr,
+                                         format!("AUTH {} {}", step.mechanism(), what))
+            .with("mechanism", step.mechanism())
+            .with("raw", line)
+            .with("decoded", decoded));
+        
+        if step == AuthExchange::LoginUsername {
+            session.auth_exchange = Some(AuthExchange::LoginPassword);
+            return "334 UGFzc3dvcmQ6\r\n".to_string();
+        }
+        session.authenticated = true;
+        "235 Authentication successful\r\n".to_string()
+    }
+    
     async fn process_command(&self, cmd_line: &str, session: &mut session::SmtpSession) -> Option<String> {
+        let response = self.command_response(cmd_line, session).await;
+        if let Some(resp) = &response {
+            self.count_response(resp);
+        }
+        response
+    }
+    
+    async fn command_response(&self, cmd_line: &str, session: &mut session::SmtpSession) -> Option<String> {
+        // Ligne de réponse à un défi AUTH 334, pas une commande
+        if let Some(step) = session.auth_exchange.take() {
+            return Some(self.auth_continuation(session, step, cmd_line).await);
+        }
+        
         let parts: Vec<&str> = cmd_line.split_whitespace().collect();
         if parts.is_empty() {
             return Some("500 Syntax error\r\n".to_string());
@@ -160,17 +1917,77 @@ impl SmtpHoneypot {
         
         let cmd = parts[0].to_uppercase();
         
+        // Un client de messagerie réel passe en TLS quand STARTTLS lui est proposé
+        if matches!(cmd.as_str(), "MAIL" | "AUTH") && session.starttls_offered && !session.tls_active
+            && !session.signals.contains(&Signal::IgnoredStarttls) {
+            session.add_signal(Signal::IgnoredStarttls);
+            self.logger.log(&session.client_addr,
+                            &format!("STARTTLS was offered but client sent {} in plaintext", cmd)).await;
+        }
+        
+        // La limite porte sur la ligne complète, CRLF compris
+        if matches!(cmd.as_str(), "MAIL" | "RCPT") && cmd_line.len() + 2 > MAX_COMMAND_LINE {
+            self.oversized_argument(session, &cmd, cmd_line).await;
+            return Some("501 Command line too long\r\n".to_string());
+        }
+        
         match cmd.as_str() {
-            "HELO" | "EHLO" => {
+            "HELO" | "EHLO" if self.opt.lmtp => {
+                Some("500 Use LHLO in LMTP mode\r\n".to_string())
+            }
+            
+            "LHLO" if !self.opt.lmtp => {
+                Some("500 Command not recognized\r\n".to_string())
+            }
+            
+            "HELO" | "EHLO" | "LHLO" => {
+                let helo_class = utils::classify_helo(parts.get(1).copied());
+                session.helo_class = Some(helo_class);
+                if helo_class != HeloClass::ValidFqdn {
+                    if helo_class.is_bad() {
+                        session.add_signal(Signal::BadHelo);
+                    }
+                    self.logger.log(&session.client_addr, &format!("HELO classification: {}", helo_class.as_str())).await;
+                }
+                if helo_class.is_bad() && self.opt.reject_bad_helo {
+                    return Some("550 HELO requires domain address\r\n".to_string());
+                }
+                
                 let helo_name = parts.get(1).unwrap_or(&"unknown");
                 session.helo = Some(helo_name.to_string());
                 self.logger.log_verbose(&session.client_addr, "HELO/EHLO", helo_name).await;
                 
-                let mut response = format!("250-{} Hello {}\r\n", self.opt.helo, helo_name);
+                // Domaine annoncé connu : sa persona l'emporte sur celle du point d'écoute
+                if let Some(persona) = self.persona_for_helo(helo_name) {
+                    if session.persona.as_ref().map(|p| &p.domain) != Some(&persona.domain) {
+                        self.logger.log(&session.client_addr, &format!("Persona selected from HELO domain: {}", persona.domain)).await;
+                        session.persona = Some(persona.clone());
+                    }
+                }
+                
+                // HELO (RFC 821) : une seule ligne, sans extensions ESMTP ; de même en mode sinkhole
+                if cmd == "HELO" || self.opt.sinkhole {
+                    return Some(format!("250 {} Hello {}\r\n", self.helo_name(session), helo_name));
+                }
+                
+                let mut extensions = Vec::new();
                 if session.starttls_enabled && !session.tls_active && self.tls_acceptor.is_some() {
-                    response.push_str("250-STARTTLS\r\n");
+                    extensions.push("STARTTLS");
+                    session.starttls_offered = true;
+                }
+                if self.advertise_auth(session).await {
+                    extensions.push("AUTH PLAIN LOGIN");
+                }
+                extensions.push("HELP");
+                if let Some(persona) = &self.random_persona {
+                    extensions = persona.order_extensions(&extensions);
+                }
+                
+                let mut response = format!("250-{} Hello {}\r\n", self.helo_name(session), helo_name);
+                for (i, extension) in extensions.iter().enumerate() {
+                    let separator = if i + 1 == extensions.len() { ' ' } else { '-' };
+                    response.push_str(&format!("250{}{}\r\n", separator, extension));
                 }
-                response.push_str("250 HELP\r\n");
                 Some(response)
             }
             
@@ -182,31 +1999,108 @@ impl SmtpHoneypot {
                 }
             }
             
+            "MAIL" | "RCPT" if self.throttle_engaged().await => {
+                self.logger.event(Event::new(EventKind::Rejection, session.client_addr,
+                                             format!("{} refused: adaptive throttling", cmd))
+                    .with("reason", "throttled")
+                    .with("port", session.port)).await;
+                Some("450 Server busy, try later\r\n".to_string())
+            }
+            
             "MAIL" => {
                 if parts.len() < 2 || !parts[1].to_uppercase().starts_with("FROM:") {
-                    return Some("501 Syntax error in parameters\r\n".to_string());
+                    return Some(format!("{}\r\n", self.syntax_error(session, &parts[1..].join(" "))));
                 }
                 
-                let from = parts[1][5..].trim_matches('<').trim_matches('>').to_string();
+                if let Some(max) = self.opt.max_messages_per_connection.filter(|max| session.message_seq >= *max) {
+                    self.logger.log(&session.client_addr,
+                                    &format!("Message limit reached ({} messages), closing", max)).await;
+                    session.close_after_reply = true;
+                    return Some("421 Too many messages this session\r\n".to_string());
+                }
+                
+                let arg = &cmd_line.trim_start()[parts[0].len()..].trim_start()["FROM:".len()..];
+                let (from, params) = utils::parse_path_params(arg);
+                let declared_size = params.iter()
+                    .find(|(key, _)| key == "SIZE")
+                    .and_then(|(_, value)| value.parse().ok());
+                if let (Some(declared), Some(limit)) = (declared_size, self.size_limit()) {
+                    if declared > limit {
+                        self.logger.log(&session.client_addr,
+                                        &format!("MAIL FROM SIZE={} exceeds the advertised SIZE of {}", declared, limit)).await;
+                        return Some("552 Message size exceeds fixed maximum message size\r\n".to_string());
+                    }
+                }
                 session.mail_from = Some(from.clone());
+                session.declared_size = declared_size;
+                // AUTH=<> : l'émetteur déclare que l'expéditeur n'est pas authentifié (RFC 4954)
+                session.mail_auth = params.iter()
+                    .find(|(key, _)| key == "AUTH")
+                    .map(|(_, value)| if value == "<>" { value.clone() } else { utils::decode_xtext(value) });
+                if let Some(auth) = &session.mail_auth {
+                    let meaning = if auth == "<>" { " (not authenticated)" } else { "" };
+                    self.logger.log(&session.client_addr,
+                                    &format!("MAIL FROM AUTH={}{}", utils::safe_log_string(auth), meaning)).await;
+                }
                 self.logger.log_verbose(&session.client_addr, "MAIL FROM", &from).await;
                 Some("250 OK\r\n".to_string())
             }
             
             "RCPT" => {
                 if parts.len() < 2 || !parts[1].to_uppercase().starts_with("TO:") {
-                    return Some("501 Syntax error in parameters\r\n".to_string());
+                    return Some(format!("{}\r\n", self.syntax_error(session, &parts[1..].join(" "))));
                 }
                 
                 let to = parts[1][3..].trim_matches('<').trim_matches('>').to_string();
                 
-                if self.is_valid_recipient(&to) {
-                    session.rcpt_to.push(to.clone());
-                    self.logger.log_verbose(&session.client_addr, "RCPT TO (accepted)", &to).await;
-                    Some("250 OK\r\n".to_string())
-                } else {
-                    self.logger.log_verbose(&session.client_addr, "RCPT TO (rejected)", &to).await;
-                    Some("550 No such user\r\n".to_string())
+                if self.is_external_recipient(&to) {
+                    if !session.relay_attempted {
+                        self.logger.event(Event::new(EventKind::Alert, session.client_addr,
+                                                     format!("ALERT: relay attempt to external recipient {}", to))
+                            .with("alert", "relay-attempt")
+                            .with("rcpt_to", &to)).await;
+                    }
+                    session.relay_attempted = true;
+                    session.add_signal(Signal::RelayAttempt);
+                    
+                    // AUTH réussi puis relais : comportement type d'un compte compromis
+                    if session.authenticated && !session.signals.contains(&Signal::AuthRelay) {
+                        session.add_signal(Signal::AuthRelay);
+                        self.logger.event(Event::new(EventKind::Alert, session.client_addr,
+                                                     format!("ALERT: authenticated session attempted relay to {}", to))
+                            .with("alert", "auth-relay")
+                            .with("severity", "high")
+                            .with("mail_from", session.mail_from.as_deref().unwrap_or(""))
+                            .with("rcpt_to", &to)).await;
+                    }
+                }
+                
+                if self.is_honey_mailbox(&to) {
+                    self.honey_mailbox_probed(session, &to, "RCPT").await;
+                }
+                
+                let decision = match &self.filters.recipient {
+                    Some(filter) => filter(session.client_addr.ip(), &to),
+                    None => self.default_recipient_decision(session, &to),
+                };
+                match decision {
+                    Decision::Accept => {
+                        session.rcpt_to.push(to.clone());
+                        self.logger.log_verbose(&session.client_addr, "RCPT TO (accepted)", &to).await;
+                        Some("250 OK\r\n".to_string())
+                    }
+                    Decision::Reject(..) if self.opt.accept_all_rcpt => {
+                        // Accepté pour capturer la charge utile, mais la décision de politique est conservée
+                        session.rcpt_to.push(to.clone());
+                        session.would_reject.push(to.clone());
+                        self.logger.log_verbose(&session.client_addr, "RCPT TO (would reject, accepted for capture)", &to).await;
+                        Some("250 OK\r\n".to_string())
+                    }
+                    Decision::Reject(..) => {
+                        self.logger.log_verbose(&session.client_addr, "RCPT TO (rejected)", &to).await;
+                        decision.reject_line()
+                    }
+                    Decision::Drop => self.apply_decision(session, "RCPT", &decision).await,
                 }
             }
             
@@ -214,24 +2108,83 @@ impl SmtpHoneypot {
                 if session.mail_from.is_none() || session.rcpt_to.is_empty() {
                     return Some("503 Bad sequence of commands\r\n".to_string());
                 }
+                if self.storage_refused() {
+                    self.logger.log(&session.client_addr, "DATA refused: storage unwritable").await;
+                    return Some("452 Insufficient storage\r\n".to_string());
+                }
                 Some("354 Start mail input; end with <CRLF>.<CRLF>\r\n".to_string())
             }
             
             "AUTH" => {
+                if let (Some(filter), Some(mechanism)) = (&self.filters.auth, parts.get(1)) {
+                    let decision = filter(session.client_addr.ip(), &mechanism.to_uppercase());
+                    if decision != Decision::Accept {
+                        return self.apply_decision(session, "AUTH", &decision).await;
+                    }
+                }
+                
+                session.auth_attempts += 1;
+                if parts.len() == 1 {
+                    session.auth_enumerations += 1;
+                    self.logger.event(Event::new(EventKind::Auth, session.client_addr,
+                                                 "AUTH enumeration probe (no mechanism)")
+                        .with("enumeration", true)
+                        .with("auth_line", cmd_line)).await;
+                }
+                
                 if parts.len() > 1 {
+                    session.record_auth_mechanism(parts[1]);
                     self.logger.log_verbose(&session.client_addr, "AUTH attempt", cmd_line).await;
+                    let mut event = Event::new(EventKind::Auth, session.client_addr, "AUTH attempt")
+                        .with("mechanism", parts[1].to_uppercase())
+                        .with("auth_line", cmd_line);
+                    
+                    // Réponse initiale en ligne, quel que soit le mécanisme (même inconnu)
+                    if let Some(initial) = parts.get(2) {
+                        let decoded = utils::decode_sasl_response(initial);
+                        self.logger.log_verbose(&session.client_addr, "AUTH credentials",
+                                                &format!("mechanism: {}\nraw: {}\ndecoded: {}",
+                                                         parts[1].to_uppercase(), initial,
+                                                         decoded.as_deref().unwrap_or("(not base64)"))).await;
+                        event = event.with("raw", initial);
+                        if let Some(decoded) = decoded {
+                            event = event.with("decoded", decoded);
+                        }
+                    }
+                    self.logger.dispatch(&event);
                 }
                 
-                if parts.len() >= 2 && parts[1].to_uppercase() == "LOGIN" {
-                    Some("334 VXNlcm5hbWU6\r\n".to_string())
-                } else if parts.len() == 1 {
+                let mechanism = parts.get(1).map(|m| m.to_uppercase());
+                let initial = parts.get(2).is_some();
+                if self.opt.sinkhole {
+                    Some("502 Command not implemented\r\n".to_string())
+                } else if mechanism.as_deref() == Some("LOGIN") {
+                    // Réponse initiale = nom d'utilisateur : reste le mot de passe
+                    if initial {
+                        session.auth_exchange = Some(AuthExchange::LoginPassword);
+                        Some("334 UGFzc3dvcmQ6\r\n".to_string())
+                    } else {
+                        session.auth_exchange = Some(AuthExchange::LoginUsername);
+                        Some("334 VXNlcm5hbWU6\r\n".to_string())
+                    }
+                } else if mechanism.as_deref() == Some("PLAIN") && !initial {
+                    session.auth_exchange = Some(AuthExchange::Plain);
+                    Some("334 \r\n".to_string())
+                } else if mechanism.is_none() || !initial {
                     Some("504 Unrecognized authentication type\r\n".to_string())
                 } else {
+                    session.authenticated = true;
                     Some("235 Authentication successful\r\n".to_string())
                 }
             }
             
+            "HELP" => {
+                self.logger.log_verbose(&session.client_addr, "HELP request", cmd_line).await;
+                Some(format!("214-Commands supported:\r\n214 {}\r\n", self.supported_commands(session).join(" ")))
+            }
+            
             "QUIT" => {
+                session.disposition = "quit";
                 Some("221 Bye\r\n".to_string())
             }
             
@@ -244,62 +2197,162 @@ impl SmtpHoneypot {
                 Some("250 OK\r\n".to_string())
             }
             
+            // VRFY refuse les boîtes inconnues seulement si le rejet est personnalisé
+            "VRFY" if self.opt.reject_code.is_some() || self.opt.reject_message.is_some() => {
+                let target = parts.get(1).map(|arg| arg.trim_matches('<').trim_matches('>')).unwrap_or("");
+                if self.is_honey_mailbox(target) {
+                    self.honey_mailbox_probed(session, target, "VRFY").await;
+                }
+                if self.is_valid_recipient(target) {
+                    Some("252 Cannot verify user\r\n".to_string())
+                } else {
+                    Some(format!("{}\r\n", self.reject_message(session, target)))
+                }
+            }
+            
             "VRFY" | "EXPN" => {
                 Some("252 Cannot verify user\r\n".to_string())
             }
             
+            // Commandes obsolètes de la RFC 821, sondées par les outils d'empreinte
+            "SEND" | "SOML" | "SAML" | "TURN" => {
+                self.logger.log(&session.client_addr, &format!("Obsolete command probe: {}", cmd_line)).await;
+                Some("502 Command not implemented\r\n".to_string())
+            }
+            
             _ => {
                 Some("500 Command not recognized\r\n".to_string())
             }
         }
     }
     
-    async fn handle_tls_stream(&self, stream: tokio_rustls::server::TlsStream<TcpStream>, client_addr: SocketAddr) -> Result<()> {
+    async fn handle_tls_stream(&self, stream: TlsStream<TcpStream>, handshake: tlsresume::Handshake,
+                               client_addr: SocketAddr, listener: &ListenerDef, session_id: u64) -> Result<()> {
         self.logger.log(&client_addr, "TLS session established").await;
         
+        let mut session = session::SmtpSession::new(session_id, client_addr, false);
+        session.quiet = self.logger.quiet_flag(&client_addr);
+        session.tls_active = true;
+        session.tls_handshake = Some(handshake);
+        // Certificat client éventuel (--request-client-cert)
+        session.client_cert = stream.get_ref().1.peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| clientcert::ClientCert::from_der(&cert.0));
+        if let Some(cert) = &session.client_cert {
+            self.logger.log(&client_addr, &format!("TLS client certificate: {}", cert)).await;
+        }
+        let conn = stream.get_ref().1;
+        let info = session::TlsInfo {
+            source: "handshake",
+            version: conn.protocol_version().map(|v| format!("{:?}", v)),
+            cipher: conn.negotiated_cipher_suite().map(|s| format!("{:?}", s.suite())),
+            sni: conn.server_name().map(str::to_string),
+        };
+        self.logger.log(&client_addr, &format!("TLS info from handshake: {}", info)).await;
+        session.tls_info = Some(info);
+        session.persona = self.persona_for_listener(listener).cloned();
+        session.port = listener.port;
+        
+        // Octets déchiffrés, avant tout découpage en lignes
+        let stream = RawTap::new(stream, self.start_raw_capture(&session).await);
         let (reader, mut writer) = tokio::io::split(stream);
         let mut reader = BufReader::new(reader);
         
-        let banner = format!("220 {} SMTP (TLS)\r\n", self.opt.helo);
-        writer.write_all(banner.as_bytes()).await?;
+        let banner = self.banner_line(&session, true);
+        if !self.send_banner(&mut writer, &mut session, &banner).await? {
+            self.close_session(&mut session).await;
+            return Ok(());
+        }
         
-        let mut line = String::new();
-        let mut session = session::SmtpSession::new(client_addr, false);
-        session.tls_active = true;
+        let mut raw = Vec::new();
+        // Fermeture à l'initiative du client (fin de flux ou erreur de lecture)
+        let mut client_closed = false;
+        let idle_timeout = self.opt.session_idle_timeout.map(Duration::from_secs);
         
         loop {
-            line.clear();
+            raw.clear();
             
-            match reader.read_line(&mut line).await {
-                Ok(0) => break,
-                Ok(_) => {
+            // Octets invalides en UTF-8 échappés (\xNN) : la session continue
+            let read = read_line_limited(&mut reader, &mut raw, MAX_LINE_BYTES, idle_timeout).await;
+            let line = utils::escape_bytes(&raw);
+            match read {
+                Ok(LineRead::Eof) => {
+                    client_closed = true;
+                    break;
+                }
+                Ok(LineRead::Idle) => {
+                    session.disposition = "idle-timeout";
+                    self.logger.log(&client_addr, &format!("Session idle for {}s, closing", idle_timeout.unwrap_or_default().as_secs())).await;
+                    let resp = "421 Idle timeout, closing connection\r\n";
+                    self.logger.log(&client_addr, &format!("<< (TLS) {}", resp.trim())).await;
+                    writer.write_all(resp.as_bytes()).await?;
+                    break;
+                }
+                // Ligne de corps trop longue (HTML non replié...) : gardée par morceaux, la session continue
+                Ok(LineRead::TooLong) if session.expecting_data => {
+                    self.logger.log(&client_addr, &format!(">> (TLS) {} (partial line, {} bytes)", line.trim_end(), raw.len())).await;
+                    self.push_data_line(&mut session, &raw, false).await;
+                    continue;
+                }
+                Ok(LineRead::TooLong) => {
+                    let resp = self.line_too_long(&mut session, &line).await;
+                    self.logger.log(&client_addr, &format!("<< (TLS) {}", resp.trim())).await;
+                    writer.write_all(resp.as_bytes()).await?;
+                    break;
+                }
+                Ok(LineRead::Line) => {
                     let cmd_line = line.trim_end();
+                    session.line_endings.record(&line);
                     self.logger.log(&client_addr, &format!(">> (TLS) {}", cmd_line)).await;
                     
                     if session.expecting_data {
-                        if cmd_line == "." {
-                            session.expecting_data = false;
-                            self.logger.log_verbose(&client_addr, "EMAIL DATA", &session.data.join("\r\n")).await;
-                            
-                            if let Err(e) = self.save_email_data(&client_addr, &session).await {
-                                self.logger.log(&client_addr, &format!("Failed to save email: {}", e)).await;
-                            }
-                            
-                            session.reset();
-                            writer.write_all(b"250 OK: Message accepted\r\n").await?;
+                        if cmd_line == "." && !session.data_partial {
+                            let resp = self.complete_data(&mut session).await;
+                            writer.write_all(resp.as_bytes()).await?;
                         } else {
-                            session.data.push(cmd_line.to_string());
+                            self.push_data_line(&mut session, &raw, true).await;
                         }
                         continue;
                     }
                     
+                    self.check_pipelining(&mut session, cmd_line, reader.buffer()).await;
+                    
+                    if let Some(resp) = self.check_command_limit(&mut session, &line).await {
+                        self.logger.log(&client_addr, &format!("<< (TLS) {}", resp.trim())).await;
+                        writer.write_all(resp.as_bytes()).await?;
+                        break;
+                    }
+                    
+                    if let Some(resp) = self.throttle_command(&mut session).await {
+                        self.logger.log(&client_addr, &format!("<< (TLS) {}", resp.trim())).await;
+                        writer.write_all(resp.as_bytes()).await?;
+                        continue;
+                    }
+                    
                     let response = self.process_command(cmd_line, &mut session).await;
                     
+                    if session.dropped {
+                        break;
+                    }
+                    
                     if let Some(resp) = response {
+                        if resp.starts_with("354") {
+                            self.delay_data_prompt(&mut reader, &mut session).await;
+                        }
                         self.logger.log(&client_addr, &format!("<< (TLS) {}", resp.trim())).await;
+                        if inject::should_fail(&self.opt.test_inject, InjectPoint::WriteTimeout) {
+                            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "injected failure: write timeout").into());
+                        }
                         writer.write_all(resp.as_bytes()).await?;
                         
                         if resp.starts_with("221") {
+                            if self.opt.observe_after_quit {
+                                self.observe_after_quit(&mut reader, &mut session).await;
+                            }
+                            break;
+                        }
+                        
+                        if session.close_after_reply {
                             break;
                         }
                         
@@ -310,57 +2363,131 @@ impl SmtpHoneypot {
                 }
                 Err(e) => {
                     self.logger.log(&client_addr, &format!("TLS read error: {}", e)).await;
+                    client_closed = true;
                     break;
                 }
             }
         }
         
+        // Poignée de main menée à terme, puis départ sans la moindre commande : sondage TLS pur
+        if client_closed && session.command_count == 0 {
+            session.add_signal(Signal::TlsProbe);
+            self.tls_probes.fetch_add(1, Ordering::Relaxed);
+            self.logger.log(&client_addr, "TLS probe, no SMTP").await;
+        }
+        
+        self.close_session(&mut session).await;
         Ok(())
     }
     
-    async fn handle_plain_stream(&self, stream: TcpStream, client_addr: SocketAddr) -> Result<()> {
+    async fn handle_plain_stream(&self, stream: TcpStream, client_addr: SocketAddr, listener: &ListenerDef,
+                                 proxy_tls: Option<session::TlsInfo>, session_id: u64) -> Result<()> {
+        let port = listener.port;
         let banner_delay = self.opt.banner_delay;
         if banner_delay > 0 {
             time::sleep(Duration::from_millis(banner_delay)).await;
         }
         
+        let mut session = session::SmtpSession::new(session_id, client_addr, listener.starttls(self.opt.starttls, &self.opt.starttls_ports));
+        session.quiet = self.logger.quiet_flag(&client_addr);
+        session.persona = self.persona_for_listener(listener).cloned();
+        session.port = listener.port;
+        // TLS terminé par le répartiteur : la session est chiffrée côté client, sans STARTTLS
+        if let Some(info) = proxy_tls {
+            self.logger.log(&client_addr, &format!("TLS info from proxy TLV: {}", info)).await;
+            session.tls_active = true;
+            session.tls_info = Some(info);
+        }
+        let mut stream = RawTap::new(stream, self.start_raw_capture(&session).await);
+        
+        // Un client légitime attend la bannière avant de parler
+        if client_spoke_first(stream.get_ref()).await {
+            session.add_signal(Signal::FastTalker);
+            self.logger.log(&client_addr, "Pre-greeting traffic detected (fast talker)").await;
+            
+            if self.opt.reject_fast_talker {
+                let resp = "554 SMTP synchronization error\r\n";
+                self.logger.log(&client_addr, &format!("<< {}", resp.trim())).await;
+                stream.write_all(resp.as_bytes()).await?;
+                self.logger.event(Event::new(EventKind::Rejection, client_addr, "Connection rejected: fast talker")
+                    .with("reason", "fast-talker")
+                    .with("port", port)).await;
+                self.logger.event(Event::new(EventKind::Connection, client_addr, "Connection closed")).await;
+                return Ok(());
+            }
+        }
+        
         let (reader, mut writer) = tokio::io::split(stream);
         let mut reader = BufReader::new(reader);
         
-        let banner = format!("220 {} SMTP \r\n", self.opt.helo);
-        writer.write_all(banner.as_bytes()).await?;
+        let banner = self.banner_line(&session, false);
+        if !self.send_banner(&mut writer, &mut session, &banner).await? {
+            self.close_session(&mut session).await;
+            return Ok(());
+        }
         
-        let mut line = String::new();
-        let mut session = session::SmtpSession::new(client_addr, self.opt.starttls);
+        let mut raw = Vec::new();
+        let idle_timeout = self.opt.session_idle_timeout.map(Duration::from_secs);
         
         loop {
-            line.clear();
+            raw.clear();
             
-            match reader.read_line(&mut line).await {
-                Ok(0) => break,
-                Ok(_) => {
+            // Octets invalides en UTF-8 échappés (\xNN) : la session continue
+            let read = read_line_limited(&mut reader, &mut raw, MAX_LINE_BYTES, idle_timeout).await;
+            let line = utils::escape_bytes(&raw);
+            match read {
+                Ok(LineRead::Eof) => break,
+                Ok(LineRead::Idle) => {
+                    session.disposition = "idle-timeout";
+                    self.logger.log(&client_addr, &format!("Session idle for {}s, closing", idle_timeout.unwrap_or_default().as_secs())).await;
+                    let resp = "421 Idle timeout, closing connection\r\n";
+                    self.logger.log(&client_addr, &format!("<< {}", resp.trim())).await;
+                    writer.write_all(resp.as_bytes()).await?;
+                    break;
+                }
+                // Ligne de corps trop longue (HTML non replié...) : gardée par morceaux, la session continue
+                Ok(LineRead::TooLong) if session.expecting_data => {
+                    self.logger.log(&client_addr, &format!(">> {} (partial line, {} bytes)", line.trim_end(), raw.len())).await;
+                    self.push_data_line(&mut session, &raw, false).await;
+                    continue;
+                }
+                Ok(LineRead::TooLong) => {
+                    let resp = self.line_too_long(&mut session, &line).await;
+                    self.logger.log(&client_addr, &format!("<< {}", resp.trim())).await;
+                    writer.write_all(resp.as_bytes()).await?;
+                    break;
+                }
+                Ok(LineRead::Line) => {
                     let cmd_line = line.trim_end();
+                    session.line_endings.record(&line);
                     self.logger.log(&client_addr, &format!(">> {}", cmd_line)).await;
                     
                     if session.expecting_data {
-                        if cmd_line == "." {
-                            session.expecting_data = false;
-                            self.logger.log_verbose(&client_addr, "EMAIL DATA", &session.data.join("\r\n")).await;
-                            
-                            if let Err(e) = self.save_email_data(&client_addr, &session).await {
-                                self.logger.log(&client_addr, &format!("Failed to save email: {}", e)).await;
-                            }
-                            
-                            session.reset();
-                            writer.write_all(b"250 OK: Message accepted\r\n").await?;
+                        if cmd_line == "." && !session.data_partial {
+                            let resp = self.complete_data(&mut session).await;
+                            writer.write_all(resp.as_bytes()).await?;
                         } else {
-                            session.data.push(cmd_line.to_string());
+                            self.push_data_line(&mut session, &raw, true).await;
                         }
                         continue;
                     }
                     
+                    self.check_pipelining(&mut session, cmd_line, reader.buffer()).await;
+                    
+                    if let Some(resp) = self.check_command_limit(&mut session, &line).await {
+                        self.logger.log(&client_addr, &format!("<< {}", resp.trim())).await;
+                        writer.write_all(resp.as_bytes()).await?;
+                        break;
+                    }
+                    
+                    if let Some(resp) = self.throttle_command(&mut session).await {
+                        self.logger.log(&client_addr, &format!("<< {}", resp.trim())).await;
+                        writer.write_all(resp.as_bytes()).await?;
+                        continue;
+                    }
+                    
                     // Gestion spéciale pour STARTTLS
-                    if cmd_line.to_uppercase() == "STARTTLS" && self.tls_acceptor.is_some() && !session.tls_active {
+                    if cmd_line.to_uppercase() == "STARTTLS" && session.starttls_enabled && self.tls_acceptor.is_some() && !session.tls_active {
                         self.logger.log(&client_addr, "STARTTLS command received").await;
                         writer.write_all(b"220 Ready to start TLS\r\n").await?;
                         writer.flush().await?;
@@ -371,11 +2498,28 @@ impl SmtpHoneypot {
                     
                     let response = self.process_command(cmd_line, &mut session).await;
                     
+                    if session.dropped {
+                        break;
+                    }
+                    
                     if let Some(resp) = response {
+                        if resp.starts_with("354") {
+                            self.delay_data_prompt(&mut reader, &mut session).await;
+                        }
                         self.logger.log(&client_addr, &format!("<< {}", resp.trim())).await;
+                        if inject::should_fail(&self.opt.test_inject, InjectPoint::WriteTimeout) {
+                            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "injected failure: write timeout").into());
+                        }
                         writer.write_all(resp.as_bytes()).await?;
                         
                         if resp.starts_with("221") {
+                            if self.opt.observe_after_quit {
+                                self.observe_after_quit(&mut reader, &mut session).await;
+                            }
+                            break;
+                        }
+                        
+                        if session.close_after_reply {
                             break;
                         }
                         
@@ -391,64 +2535,207 @@ impl SmtpHoneypot {
             }
         }
         
-        self.logger.log(&client_addr, "Connection closed").await;
+        self.close_session(&mut session).await;
         Ok(())
     }
     
     #[allow(dead_code)]
-    async fn handle_starttls_stream(&self, stream: TcpStream, client_addr: SocketAddr) -> Result<()> {
+    async fn handle_starttls_stream(&self, stream: TcpStream, client_addr: SocketAddr, listener: &ListenerDef) -> Result<()> {
         self.logger.log(&client_addr, "Starting STARTTLS handshake").await;
         
-        if let Some(acceptor) = &self.tls_acceptor {
-            match acceptor.accept(stream).await {
-                Ok(tls_stream) => {
-                    self.handle_tls_stream(tls_stream, client_addr).await
+        match self.accept_tls(stream, client_addr).await {
+            Some((tls_stream, handshake)) => {
+                self.handle_tls_stream(tls_stream, handshake, client_addr, listener, session::SmtpSession::next_id()).await
+            }
+            None => Ok(()),
+        }
+    }
+    
+    /// Poignée de main TLS en conservant le ClientHello (SNI, ALPN, suites), même en cas d'échec
+    async fn accept_tls(&self, stream: TcpStream, client_addr: SocketAddr) -> Option<(TlsStream<TcpStream>, tlsresume::Handshake)> {
+        let config = self.tls_config.as_ref()?.read().unwrap().clone();
+        
+        if inject::should_fail(&self.opt.test_inject, InjectPoint::TlsHandshake) {
+            self.logger.log(&client_addr, "TLS handshake failed: injected failure").await;
+            return None;
+        }
+        
+        let start = match LazyConfigAcceptor::new(Acceptor::default(), stream).await {
+            Ok(start) => start,
+            Err(e) => {
+                self.logger.log(&client_addr, &format!("TLS handshake failed before ClientHello: {}", e)).await;
+                return None;
+            }
+        };
+        
+        let hello = start.client_hello();
+        let alpn: Vec<String> = hello.alpn()
+            .map(|protocols| protocols.map(|p| String::from_utf8_lossy(p).into_owned()).collect())
+            .unwrap_or_default();
+        let ciphers: Vec<String> = hello.cipher_suites().iter().map(|c| format!("{:?}", c)).collect();
+        let hello_summary = format!(
+            "sni={} alpn={} ciphers={}",
+            hello.server_name().unwrap_or("-"),
+            if alpn.is_empty() { "-".to_string() } else { alpn.join(",") },
+            ciphers.join(",")
+        );
+        
+        match tlsresume::observe(|| start.into_stream(config)).await {
+            (Ok(tls_stream), handshake) => {
+                self.logger.log(&client_addr, &format!("TLS ClientHello: {}", hello_summary)).await;
+                self.logger.log(&client_addr, &format!("TLS handshake: {}", handshake)).await;
+                if self.opt.tls_weak_profile {
+                    let (_, conn) = tls_stream.get_ref();
+                    self.logger.log(&client_addr, &format!(
+                        "TLS weak profile: client proceeded with version={} suite={} (insecure by design)",
+                        conn.protocol_version().map(|v| format!("{:?}", v)).unwrap_or_else(|| "-".to_string()),
+                        conn.negotiated_cipher_suite().map(|s| format!("{:?}", s.suite())).unwrap_or_else(|| "-".to_string())
+                    )).await;
                 }
-                Err(e) => {
-                    self.logger.log(&client_addr, &format!("TLS handshake failed: {}", e)).await;
-                    Ok(())
+                Some((tls_stream, handshake))
+            }
+            (Err(e), _) => {
+                self.logger.log(&client_addr, &format!("TLS handshake failed: {} (ClientHello: {})", e, hello_summary)).await;
+                if self.opt.tls_weak_profile {
+                    self.logger.log(&client_addr, &format!("TLS weak profile: client aborted: {}", e)).await;
                 }
+                None
             }
-        } else {
-            Ok(())
         }
     }
     
-    pub async fn handle_client(&self, stream: TcpStream, client_addr: SocketAddr, port: u16) -> Result<()> {
-        // Vérifier le rate limiting
-        {
+    pub async fn handle_client(&self, stream: TcpStream, client_addr: SocketAddr, listener: &ListenerDef,
+                               proxy_tls: Option<session::TlsInfo>) -> Result<()> {
+        let port = listener.port;
+        if self.draining.load(Ordering::Relaxed) {
+            self.logger.event(Event::new(EventKind::Rejection, client_addr, "Connection refused while draining")
+                .with("reason", "draining")
+                .with("port", port)).await;
+            let _ = stream.try_write(b"421 Service not available, closing transmission channel\r\n");
+            return Ok(());
+        }
+        
+        if self.banned.lock().await.contains(client_addr.ip()) {
+            let mut event = Event::new(EventKind::Rejection, client_addr, "Connection from banned IP")
+                .with("reason", "banned")
+                .with("port", port);
+            // Par défaut, fermeture silencieuse ; sinon la réponse choisie pour observer la réaction
+            if let Some(response) = &self.opt.banned_response {
+                event.message = format!("Connection from banned IP, served banned-response: {}", response);
+                event = event.with("banned_response", response);
+                let _ = stream.try_write(format!("{}\r\n", response).as_bytes());
+            }
+            self.logger.event(event).await;
+            return Ok(());
+        }
+        
+        if let Some(filter) = &self.filters.connection {
+            let decision = filter(client_addr.ip());
+            if decision != Decision::Accept {
+                self.logger.event(Event::new(EventKind::Rejection, client_addr,
+                                             format!("Connection filter decision: {}", decision.as_str()))
+                    .with("reason", "filter")
+                    .with("port", port)).await;
+                if let Some(line) = decision.reject_line() {
+                    let _ = stream.try_write(line.as_bytes());
+                }
+                return Ok(());
+            }
+        }
+        
+        // Identifiant alloué avant les recherches pour qu'elles soient rattachées à la session
+        let session_id = session::SmtpSession::next_id();
+        let asn_db = self.asn_db.read().unwrap().clone();
+        let asn = asn_db.as_ref().and_then(|db| db.lookup(client_addr.ip()));
+        if self.opt.log_lookups && asn_db.is_some() {
+            let answer = asn.map(|(number, name)| format!("AS{} ({})", number, name))
+                .unwrap_or_else(|| "no match".to_string());
+            self.log_lookup(&client_addr, session_id, "asn", &client_addr.ip().to_string(), &answer).await;
+        }
+        let asn_matched = asn.is_some_and(|(number, _)| self.opt.reject_asns.contains(&number));
+        if let (true, Some((number, name))) = (asn_matched, asn) {
+            if !self.opt.flag_asn {
+                self.logger.event(Event::new(EventKind::Rejection, client_addr,
+                                             format!("Connection from rejected ASN AS{} ({})", number, name))
+                    .with("reason", "asn")
+                    .with("asn", number)
+                    .with("asn_name", name)
+                    .with("port", port)).await;
+                let _ = stream.try_write(format!("{}\r\n", self.opt.reject_asn_response).as_bytes());
+                return Ok(());
+            }
+            self.logger.log(&client_addr, &format!("Connection from flagged ASN AS{} ({})", number, name)).await;
+        }
+        
+        // Vérifier le rate limiting, sauf pour les sources --allowlist
+        let allowlisted = self.opt.allowlist.iter().any(|prefix| prefix.contains(client_addr.ip()));
+        let mut soft_delay = None;
+        if self.opt.rate_limit_mode == RateLimitMode::Soft && !allowlisted {
+            let over = self.rate_limiter.lock().await.add_over_limit(client_addr.ip());
+            if over > 0 {
+                soft_delay = Some((over, SOFT_LIMIT_DELAY_STEP.saturating_mul(over as u32).min(SOFT_LIMIT_DELAY_MAX)));
+            }
+        } else if !allowlisted {
             let mut limiter = self.rate_limiter.lock().await;
-            if !limiter.check_and_add(client_addr) {
-                self.logger.log(&client_addr, &format!("Rate limit exceeded ({} per minute)", self.opt.max_connections_per_minute)).await;
-                let _ = stream.writable().await;
+            if !limiter.check_and_add(client_addr.ip()) {
+                self.logger.event(Event::new(EventKind::Rejection, client_addr,
+                                             format!("Rate limit exceeded ({} per minute)", self.opt.max_connections_per_minute))
+                    .with("reason", "rate-limit")
+                    .with("port", port)).await;
                 let _ = stream.try_write(b"421 Too many connections from your IP\r\n");
                 return Ok(());
             }
         }
         
-        self.logger.log(&client_addr, &format!("New connection on port {}", port)).await;
+        self.active_sessions.fetch_add(1, Ordering::Relaxed);
+        let _session_guard = SessionGuard(self.active_sessions.clone());
+        let _quiet_guard = (allowlisted && self.opt.quiet_allowlist)
+            .then(|| QuietGuard::new(self.logger.clone(), client_addr));
+        
+        let tcp = tcpinfo::tcp_metadata(&stream);
+        self.logger.event(Event::new(EventKind::Connection, client_addr, format!("New connection on port {} ({})", port, tcp))
+            .with("port", port)
+            .with("ttl", "unknown")
+            .with("mss", tcp.mss.map(|mss| mss.to_string()).unwrap_or_else(|| "unknown".to_string()))
+            .with("tcp_options", tcp.options_str())
+            .with("os_guess", tcp.os_guess())
+            .with("asn", asn.map(|(number, _)| number.to_string()).unwrap_or_default())
+            .with("asn_name", asn.map(|(_, name)| name).unwrap_or(""))
+            .with("asn_flagged", asn_matched)
+            .with("rate_limit_delay_s", soft_delay.map(|(_, delay)| delay.as_secs().to_string()).unwrap_or_default())).await;
         
-        // Port 465 : TLS implicite
-        if port == 465 {
-            if let Some(acceptor) = &self.tls_acceptor {
+        // Mode souple : la connexion est conservée, mais la bannière se fait attendre
+        if let Some((over, delay)) = soft_delay {
+            self.logger.log(&client_addr, &format!("Rate limit exceeded ({} per minute, {} over), soft mode: banner delayed by {}s",
+                                                   self.opt.max_connections_per_minute, over, delay.as_secs())).await;
+            time::sleep(delay).await;
+        }
+        
+        // TLS implicite : --implicit-tls-port ou tls=implicit
+        if listener.implicit_tls(&self.opt.implicit_tls_ports) {
+            if self.tls_config.is_some() {
+                // Commandes SMTP en clair avant toute ClientHello : la sonde est journalisée, la poignée n'est pas tentée
+                if let Some(sample) = plaintext_before_tls(&stream).await {
+                    self.logger.event(Event::new(EventKind::Rejection, client_addr,
+                                                 format!("Plaintext on implicit-TLS port {}: {}", port, sample))
+                        .with("reason", "plaintext-on-tls")
+                        .with("port", port)
+                        .with("probe", sample)).await;
+                    return Ok(());
+                }
                 self.logger.log(&client_addr, "Starting TLS handshake (implicit)").await;
-                match acceptor.accept(stream).await {
-                    Ok(tls_stream) => {
-                        self.handle_tls_stream(tls_stream, client_addr).await
-                    }
-                    Err(e) => {
-                        self.logger.log(&client_addr, &format!("TLS handshake failed: {}", e)).await;
-                        Ok(())
-                    }
+                match self.accept_tls(stream, client_addr).await {
+                    Some((tls_stream, handshake)) => self.handle_tls_stream(tls_stream, handshake, client_addr, listener, session_id).await,
+                    None => Ok(()),
                 }
             } else {
-                self.handle_plain_stream(stream, client_addr).await
+                self.handle_plain_stream(stream, client_addr, listener, proxy_tls, session_id).await
             }
         }
-        // Port 25 ou 587 : STARTTLS possible
-        else if (port == 25 || port == 587) && self.opt.starttls && self.tls_acceptor.is_some() {
+        // --starttls-port ou tls=starttls : STARTTLS possible
+        else if listener.starttls(self.opt.starttls, &self.opt.starttls_ports) && self.tls_acceptor.is_some() {
             // On commence en clair
-            match self.handle_plain_stream(stream, client_addr).await {
+            match self.handle_plain_stream(stream, client_addr, listener, proxy_tls, session_id).await {
                 Ok(()) => Ok(()),
                 Err(e) => {
                     if e.to_string().contains("STARTTLS") {
@@ -465,80 +2752,289 @@ impl SmtpHoneypot {
         }
         // Autres ports : clair seulement
         else {
-            self.handle_plain_stream(stream, client_addr).await
+            self.handle_plain_stream(stream, client_addr, listener, proxy_tls, session_id).await
         }
     }
     
-    async fn run_server(&self, port: u16) -> Result<()> {
-        let addr = format!("{}:{}", self.opt.address, port);
-        
-        // Logs de debug cruciaux
-        eprintln!("[DEBUG] run_server: attempting to bind to {}", addr);
-        eprintln!("[DEBUG] Current PID in run_server: {}", std::process::id());
+    async fn bind_port(&self, listener: &ListenerDef) -> Result<Vec<TcpListener>> {
+        let port = listener.port;
+        let addr = listener.bind_addr();
         
-        // Test d'écriture dans /tmp pour vérifier les permissions
-        let test_file = format!("/tmp/smtp-honeypot-server-test-{}", port);
-        if let Err(e) = std::fs::write(&test_file, b"test") {
-            eprintln!("[DEBUG] WARNING: Cannot write test file {}: {}", test_file, e);
-        } else {
-            eprintln!("[DEBUG] Successfully wrote test file {}", test_file);
-            let _ = std::fs::remove_file(&test_file);
-        }
-        
-        match TcpListener::bind(&addr).await {
-            Ok(listener) => {
-                eprintln!("[DEBUG] run_server: SUCCESSFULLY bound to {}", addr);
+        match self.bind_sockets(&addr).await {
+            Ok(sockets) => {
+                let reuseport = if self.opt.reuseport {
+                    format!(" (SO_REUSEPORT, {} accept loop(s))", sockets.len())
+                } else {
+                    String::new()
+                };
                 self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), 
-                              &format!("Listening on port {}", port)).await;
-                
-                loop {
-                    match listener.accept().await {
-                        Ok((stream, client_addr)) => {
-                            eprintln!("[DEBUG] Accepted connection from {} on port {}", client_addr, port);
-                            let this = Arc::new(self.clone());
-                            let port = port;
-                            
-                            tokio::spawn(async move {
-                                if let Err(e) = this.handle_client(stream, client_addr, port).await {
-                                    let _ = this.logger.log(&client_addr, &format!("Error: {}", e)).await;
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            eprintln!("[DEBUG] Accept error on port {}: {}", port, e);
-                            self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), 
-                                          &format!("Accept error on port {}: {}", port, e)).await;
-                        }
-                    }
-                }
+                              &format!("Listening on {}{}", listener, reuseport)).await;
+                Ok(sockets)
             }
             Err(e) => {
-                eprintln!("[ERROR] run_server: FAILED to bind to {}: {}", addr, e);
+                eprintln!("[ERROR] bind_port: FAILED to bind to {}: {}", addr, e);
                 self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), 
                               &format!("Failed to bind to {}: {}", addr, e)).await;
-                Err(e.into())
+                match privileged_port_hint(port, &e) {
+                    Some(hint) => {
+                        eprintln!("[ERROR] {}", hint);
+                        Err(anyhow::Error::new(e).context(hint))
+                    }
+                    None => Err(e.into()),
+                }
+            }
+        }
+    }
+    
+    /// Sockets d'écoute d'une adresse : une seule, ou --accept-loops sockets SO_REUSEPORT
+    async fn bind_sockets(&self, addr: &str) -> std::io::Result<Vec<TcpListener>> {
+        if !self.opt.reuseport {
+            return Ok(vec![TcpListener::bind(addr).await?]);
+        }
+        reuseport_listeners(addr, tokio::net::lookup_host(addr).await?, self.opt.accept_loops)
+    }
+    
+    async fn run_server(&self, socket: TcpListener, listener: Arc<ListenerDef>) -> Result<()> {
+        let port = listener.port;
+        loop {
+            match socket.accept().await {
+                Ok((stream, client_addr)) => {
+                    // Délestage global : ni tâche ni journal par connexion, seulement un compteur ;
+                    // un seul essai d'écriture, un client lent ne doit pas bloquer l'acceptation
+                    if let Some(bucket) = &self.accept_bucket {
+                        if !bucket.lock().unwrap().try_acquire() {
+                            self.shed_connections.fetch_add(1, Ordering::Relaxed);
+                            let _ = stream.try_write(b"421 Too many connections, try again later\r\n");
+                            continue;
+                        }
+                    }
+                    let this = Arc::new(self.clone());
+                    let listener = listener.clone();
+                    
+                    tokio::spawn(async move {
+                        let mut stream = stream;
+                        let mut client_addr = client_addr;
+                        let mut proxy_tls = None;
+                        if this.opt.proxy_protocol {
+                            match time::timeout(PROXY_HEADER_TIMEOUT, proxyproto::read_v2(&mut stream)).await {
+                                Ok(Ok(header)) => {
+                                    if let Some(source) = header.source {
+                                        this.logger.log(&source, &format!("PROXY v2 header from {}", client_addr)).await;
+                                        client_addr = source;
+                                    }
+                                    proxy_tls = header.tls;
+                                }
+                                Ok(Err(e)) => {
+                                    this.logger.log(&client_addr, &format!("Invalid PROXY v2 header, closing: {}", e)).await;
+                                    return;
+                                }
+                                Err(_) => {
+                                    this.logger.log(&client_addr, "No PROXY v2 header received, closing").await;
+                                    return;
+                                }
+                            }
+                        }
+                        if let Err(e) = this.handle_client(stream, client_addr, &listener, proxy_tls).await {
+                            let _ = this.logger.log(&client_addr, &format!("Error: {}", e)).await;
+                        }
+                    });
+                }
+                Err(e) => {
+                    self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), 
+                                  &format!("Accept error on port {}: {}", port, e)).await;
+                }
             }
         }
     }
     
     pub async fn run(self: Arc<Self>) -> Result<()> {
-        eprintln!("[DEBUG] SmtpHoneypot::run() started");
-        eprintln!("[DEBUG] Ports to listen on: {:?}", self.opt.ports);
+        let definitions = self.opt.listeners();
+        
+        // SIGUSR1 : vidage de l'état dans les journaux, sans arrêter le service ;
+        // installé avant de lier les ports, un signal reçu dès l'écoute ne tue pas le processus
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            match signal(SignalKind::user_defined1()) {
+                Ok(mut sigusr1) => {
+                    let this = self.clone();
+                    tokio::spawn(async move {
+                        while sigusr1.recv().await.is_some() {
+                            this.dump_state().await;
+                        }
+                    });
+                }
+                Err(e) => eprintln!("[WARNING] Cannot install SIGUSR1 handler: {}", e),
+            }
+        }
+        
+        // Lier tous les ports avant de servir, pour un bilan clair au démarrage
+        let mut listeners = vec![];
+        let mut failed_ports = vec![];
+        for definition in definitions {
+            match self.bind_port(&definition).await {
+                Ok(sockets) => listeners.push((Arc::new(definition), sockets)),
+                Err(_) => failed_ports.push(definition.bind_addr()),
+            }
+        }
+        
+        let live_ports: Vec<String> = listeners.iter().map(|(definition, _)| definition.bind_addr()).collect();
+        let summary = format!("Ports live: {:?}, failed: {:?}", live_ports, failed_ports);
+        eprintln!("[INFO] {}", summary);
+        self.logger.log(&SocketAddr::from(([0,0,0,0], 0)), &summary).await;
+        
+        if listeners.is_empty() {
+            return Err(anyhow::anyhow!("No port could be bound ({:?})", failed_ports));
+        }
+        if self.opt.require_all_ports && !failed_ports.is_empty() {
+            return Err(anyhow::anyhow!("Failed to bind ports {:?} (--require-all-ports)", failed_ports));
+        }
+        
+        // Stockage vérifié au démarrage, puis périodiquement pour détecter un retour à la normale
+        if !self.opt.data_dirs.is_empty() {
+            let state = self.probe_storage().await;
+            if let (Err(e), StorageErrorPolicy::Exit) = (&state, self.opt.on_storage_error) {
+                return Err(anyhow::anyhow!("Storage unwritable: {}", e));
+            }
+            self.set_storage_state(state).await;
+            
+            let this = self.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(STORAGE_CHECK_INTERVAL);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    let state = this.probe_storage().await;
+                    this.set_storage_state(state).await;
+                }
+            });
+        }
+        
+        // Délestage réévalué périodiquement : la sortie est journalisée même sans nouveau MAIL/RCPT
+        if self.opt.adaptive_throttle.is_some() {
+            let this = self.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(THROTTLE_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    this.throttle_engaged().await;
+                }
+            });
+        }
+        
+        if let Some(receiver) = self.save_receiver.lock().unwrap().take() {
+            let receiver = Arc::new(Mutex::new(receiver));
+            let workers = self.opt.save_workers.unwrap_or(1);
+            eprintln!("[INFO] {} capture writer(s), queue of {}", workers, SAVE_QUEUE_SIZE);
+            for _ in 0..workers {
+                let this = self.clone();
+                let receiver = receiver.clone();
+                tokio::spawn(async move {
+                    loop {
+                        let job = receiver.lock().await.recv().await;
+                        let Some(job) = job else { break };
+                        let client_addr = job.client_addr;
+                        if let Err(e) = this.write_capture(job).await {
+                            this.capture_failed(&client_addr, e).await;
+                        }
+                        this.pending_saves.fetch_sub(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        }
+        
+        if let Some(path) = &self.opt.control_socket {
+            control::spawn(path, self.opt.file_modes(), self.clone())?;
+        }
         
         let mut handles = vec![];
         
-        for port in self.opt.ports.clone() {
-            eprintln!("[DEBUG] Spawning server for port {}", port);
+        for (definition, sockets) in listeners {
+            // Avec --reuseport, une boucle d'acceptation par socket ; le noyau répartit les connexions
+            for socket in sockets {
+                let this = self.clone();
+                let definition = definition.clone();
+                let handle = tokio::spawn(async move {
+                    let addr = definition.bind_addr();
+                    if let Err(e) = this.run_server(socket, definition).await {
+                        eprintln!("[ERROR] Server on {} failed: {}", addr, e);
+                    }
+                });
+                handles.push(handle);
+            }
+        }
+        
+        // Résumé périodique des IP les plus refusées, des codes de réponse, des connexions délestées,
+        // des boîtes leurres et des lignes de journal perdues
+        {
+            let this = self.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(Duration::from_secs(60));
+                interval.tick().await;
+                let mut reported_lost = 0;
+                let mut reported_codes = None;
+                let mut reported_honey = None;
+                let mut reported_shed = 0;
+                loop {
+                    interval.tick().await;
+                    this.log_rate_limit_summary().await;
+                    
+                    let codes = this.response_code_summary();
+                    if codes.is_some() && codes != reported_codes {
+                        this.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                                        &format!("Response codes: {}", codes.as_deref().unwrap_or(""))).await;
+                        reported_codes = codes;
+                    }
+                    
+                    let shed = this.shed_connections.load(Ordering::Relaxed);
+                    if shed > reported_shed {
+                        this.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                                        &format!("Accept rate limit: {} connection(s) shed in the last period ({} total)",
+                                                 shed - reported_shed, shed)).await;
+                        reported_shed = shed;
+                    }
+                    
+                    let honey = this.honey_hit_summary();
+                    if honey.is_some() && honey != reported_honey {
+                        this.logger.log(&SocketAddr::from(([0,0,0,0], 0)),
+                                        &format!("Honey mailbox hits: {}", honey.as_deref().unwrap_or(""))).await;
+                        reported_honey = honey;
+                    }
+                    
+                    let lost = this.logger.lost_lines();
+                    if lost > reported_lost {
+                        eprintln!("[WARNING] Log file degraded: {} line(s) lost to write errors or a full queue", lost);
+                        reported_lost = lost;
+                    }
+                }
+            });
+        }
+        
+        // Résumé périodique des événements supprimés par l'échantillonnage ou --quiet-allowlist
+        if self.opt.log_sample > 1 || self.opt.quiet_allowlist {
             let this = self.clone();
-            let handle = tokio::spawn(async move {
-                if let Err(e) = this.run_server(port).await {
-                    eprintln!("[ERROR] Server on port {} failed: {}", port, e);
+            tokio::spawn(async move {
+                let mut interval = time::interval(Duration::from_secs(60));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    this.logger.log_suppressed_summary().await;
                 }
             });
-            handles.push(handle);
         }
         
-        eprintln!("[DEBUG] All servers spawned, waiting for completion...");
+        // Résumés des alertes regroupées (--alert-window), vérifiés chaque seconde
+        if self.opt.alert_window.is_some() {
+            let this = self.clone();
+            tokio::spawn(async move {
+                let mut interval = time::interval(Duration::from_secs(1));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    this.logger.flush_coalesced_alerts().await;
+                }
+            });
+        }
         
         for handle in handles {
             handle.await?;
@@ -548,15 +3044,253 @@ impl SmtpHoneypot {
     }
 }
 
-impl Clone for SmtpHoneypot {
-    fn clone(&self) -> Self {
-        Self {
-            opt: self.opt.clone(),
-            logger: Logger::new(self.opt.log_file.clone(), self.opt.raw_display).unwrap(),
-            rate_limiter: self.rate_limiter.clone(),
-            valid_mailboxes: self.valid_mailboxes.clone(),
-            tls_acceptor: self.tls_acceptor.clone(),
+/// Fichiers du certificat et de la clé : PEM combiné ou paire --tls-cert/--tls-key
+fn tls_files(opt: &Opt) -> Option<(&PathBuf, &PathBuf)> {
+    match (&opt.tls_pem, &opt.tls_cert, &opt.tls_key) {
+        (Some(pem_path), _, _) => Some((pem_path, pem_path)),
+        (None, Some(cert_path), Some(key_path)) => Some((cert_path, key_path)),
+        _ => None,
+    }
+}
+
+/// Charge le certificat et la clé, vérifie leur correspondance et construit la configuration TLS
+fn load_tls_config(cert_path: &Path, key_path: &Path, opt: &Opt) -> Result<ServerConfig> {
+    // Lire le certificat
+    let cert_file = &mut std::fs::File::open(cert_path)
+        .with_context(|| format!("Failed to open certificate: {:?}", cert_path))?;
+    let mut cert_reader = StdBufReader::new(cert_file);
+    let cert_chain: Vec<Certificate> = certs(&mut cert_reader)
+        .map_err(|_| anyhow::anyhow!("Failed to parse certificate"))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    
+    if cert_chain.is_empty() {
+        return Err(anyhow::anyhow!("No certificate found in {:?}", cert_path));
+    }
+    
+    // Lire la clé privée
+    let key_pem = std::fs::read(key_path)
+        .with_context(|| format!("Failed to open private key: {:?}", key_path))?;
+    let (private_key, key_type) = load_private_key(&key_pem)
+        .with_context(|| format!("Invalid private key file {:?}", key_path))?;
+    eprintln!("[INFO] Private key type: {}", key_type);
+    
+    // Vérifier que la clé correspond au certificat feuille
+    verify_key_matches_cert(&cert_chain[0], &private_key)
+        .with_context(|| format!("Private key {:?} does not match certificate {:?}", key_path, cert_path))?;
+    
+    // Configurer le serveur TLS
+    let builder = if opt.tls_weak_profile {
+        weak_profile_warnings(&cert_chain[0]);
+        ServerConfig::builder()
+            .with_cipher_suites(&weak_cipher_suites())
+            .with_kx_groups(&rustls::ALL_KX_GROUPS)
+            .with_protocol_versions(&[&rustls::version::TLS12])
+            .map_err(|e| anyhow::anyhow!("Failed to build weak TLS profile: {}", e))?
+    } else {
+        ServerConfig::builder().with_safe_defaults()
+    };
+    // Certificat client demandé sans être exigé : tout certificat présenté est accepté et journalisé
+    let builder = if opt.request_client_cert {
+        builder.with_client_cert_verifier(clientcert::verifier(&cert_chain[0])?)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut config = builder
+        .with_single_cert(cert_chain, private_key)
+        .map_err(|e| anyhow::anyhow!("Failed to build TLS config: {}", e))?;
+    // Profil faible : notre ordre de préférence s'impose à celui du client
+    config.ignore_client_order = opt.tls_weak_profile;
+    // Tickets et cache de sessions : les reprises trahissent un client déjà venu
+    tlsresume::enable(&mut config)?;
+    
+    Ok(config)
+}
+
+/// Suites du profil faible (--tls-weak-profile), de la plus faible à la plus forte
+///
+/// rustls ne sait servir ni SSLv3, ni TLS 1.0/1.1, ni suites sans AEAD : le profil se limite
+/// à TLS 1.2, sans TLS 1.3, avec AES-128 en tête.
+fn weak_cipher_suites() -> Vec<rustls::SupportedCipherSuite> {
+    use rustls::cipher_suite::*;
+    vec![
+        TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+        TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+        TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+        TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+        TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+        TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+    ]
+}
+
+/// Avertissements du profil faible : certificat auto-signé ou expiré servi tel quel
+fn weak_profile_warnings(cert: &Certificate) {
+    eprintln!("[WARNING] --tls-weak-profile: TLS configuration is INSECURE BY DESIGN, for research only");
+    eprintln!("[WARNING] --tls-weak-profile: rustls cannot offer SSLv3, TLS 1.0/1.1 or non-AEAD suites; serving TLS 1.2 only");
+    let Ok(x509) = openssl::x509::X509::from_der(&cert.0) else {
+        return;
+    };
+    let self_signed = x509.issuer_name().to_der().ok() == x509.subject_name().to_der().ok();
+    let expired = openssl::asn1::Asn1Time::days_from_now(0)
+        .map(|now| x509.not_after() < now)
+        .unwrap_or(false);
+    eprintln!("[WARNING] --tls-weak-profile: certificate self-signed={} expired={} (not after {})",
+              if self_signed { "yes" } else { "no" }, if expired { "yes" } else { "no" }, x509.not_after());
+}
+
+/// Charge la première clé privée trouvée, en essayant PKCS#8, puis PKCS#1 (RSA), puis EC
+fn load_private_key(pem: &[u8]) -> Result<(PrivateKey, &'static str)> {
+    type KeyParser = fn(&mut dyn std::io::BufRead) -> std::io::Result<Vec<Vec<u8>>>;
+    let parsers: [(&'static str, KeyParser); 3] = [
+        ("PKCS#8", pkcs8_private_keys),
+        ("PKCS#1 (RSA)", rsa_private_keys),
+        ("EC (SEC1)", ec_private_keys),
+    ];
+    
+    for (key_type, parser) in parsers {
+        let mut reader = StdBufReader::new(pem);
+        let mut keys = parser(&mut reader)
+            .map_err(|_| anyhow::anyhow!("Failed to parse private key"))?;
+        if !keys.is_empty() {
+            return Ok((PrivateKey(keys.remove(0)), key_type));
+        }
+    }
+    
+    Err(anyhow::anyhow!("No private key found (tried PKCS#8, PKCS#1, EC)"))
+}
+
+/// Résultat d'une lecture de ligne bornée
+enum LineRead {
+    Eof,
+    Line,
+    /// Aucun octet reçu pendant le délai d'inactivité
+    Idle,
+    /// Aucun saut de ligne dans la limite ; le début reçu est placé dans la ligne
+    TooLong,
+}
+
+/// Comme `read_line`, mais s'arrête au-delà de `max` octets sans saut de ligne,
+/// ou si aucun octet n'arrive pendant `idle`
+async fn read_line_limited<R: AsyncBufRead + Unpin>(reader: &mut R, bytes: &mut Vec<u8>, max: usize,
+                                                    idle: Option<Duration>) -> std::io::Result<LineRead> {
+    loop {
+        let available = match idle {
+            Some(idle) => match time::timeout(idle, reader.fill_buf()).await {
+                Ok(result) => result?,
+                Err(_) => return Ok(LineRead::Idle),
+            },
+            None => reader.fill_buf().await?,
+        };
+        if available.is_empty() {
+            break;
+        }
+        let (chunk_len, complete) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        let chunk_len = chunk_len.min(max + 1 - bytes.len());
+        bytes.extend_from_slice(&available[..chunk_len]);
+        reader.consume(chunk_len);
+        if bytes.len() > max {
+            return Ok(LineRead::TooLong);
+        }
+        if complete {
+            break;
+        }
+    }
+    
+    if bytes.is_empty() {
+        return Ok(LineRead::Eof);
+    }
+    Ok(LineRead::Line)
+}
+
+/// Ligne brute sans son terminateur (LF, éventuellement précédé de CR)
+fn strip_line_ending(raw: &[u8]) -> &[u8] {
+    let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+    raw.strip_suffix(b"\r").unwrap_or(raw)
+}
+
+/// Explication actionnable d'un refus de bind sur un port privilégié (< 1024)
+fn privileged_port_hint(port: u16, error: &std::io::Error) -> Option<String> {
+    if port >= 1024 || error.kind() != std::io::ErrorKind::PermissionDenied {
+        return None;
+    }
+    Some(format!(
+        "Port {} is privileged: run as root, grant the capability with \
+         `sudo setcap cap_net_bind_service=+ep <path-to-smtp-honeypot>`, \
+         or listen on a port >= 1024 and redirect {} to it with iptables/nftables",
+        port, port
+    ))
+}
+
+/// Vérifie que la clé privée correspond à la clé publique du certificat
+fn verify_key_matches_cert(cert: &Certificate, key: &PrivateKey) -> Result<()> {
+    let x509 = openssl::x509::X509::from_der(&cert.0)
+        .context("Failed to decode leaf certificate")?;
+    let private_key = openssl::pkey::PKey::private_key_from_der(&key.0)
+        .context("Failed to decode private key")?;
+    
+    if x509.public_key()?.public_eq(&private_key) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Public key mismatch"))
+    }
+}
+
+/// Premiers octets du client sur un port TLS implicite, échappés, s'ils ne forment pas un
+/// enregistrement TLS (type 0x16) mais du texte ; rien n'est consommé
+async fn plaintext_before_tls(stream: &TcpStream) -> Option<String> {
+    let mut buf = [0u8; RAW_STREAM_SAMPLE];
+    let n = stream.peek(&mut buf).await.ok()?;
+    let first = *buf[..n].first()?;
+    if first == TLS_HANDSHAKE_RECORD || !(first.is_ascii_graphic() || first.is_ascii_whitespace()) {
+        return None;
+    }
+    // Une seule ligne d'événement : fins de ligne échappées, le reste comme les lignes de session
+    Some(utils::escape_bytes(&buf[..n]).replace('\r', "\\r").replace('\n', "\\n"))
+}
+
+/// `count` sockets SO_REUSEPORT sur la première adresse résolue qui accepte le bind,
+/// comme TcpListener::bind essaie chaque adresse tour à tour
+fn reuseport_listeners(addr: &str, addrs: impl Iterator<Item = SocketAddr>, count: usize) -> std::io::Result<Vec<TcpListener>> {
+    let mut last_error = None;
+    for socket_addr in addrs {
+        match (0..count).map(|_| reuseport_listener(socket_addr)).collect() {
+            Ok(sockets) => return Ok(sockets),
+            Err(e) => last_error = Some(e),
         }
     }
+    Err(last_error.unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::AddrNotAvailable,
+                                                         format!("no address for {}", addr))))
+}
+
+/// Socket d'écoute partageable avec d'autres sockets SO_REUSEPORT du même utilisateur
+#[cfg(unix)]
+fn reuseport_listener(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    use tokio::net::TcpSocket;
+    // File d'attente de connexions, comme celle de TcpListener::bind
+    const LISTEN_BACKLOG: u32 = 1024;
+    
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.set_reuseaddr(true)?;
+    socket.set_reuseport(true)?;
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+#[cfg(not(unix))]
+fn reuseport_listener(_addr: SocketAddr) -> std::io::Result<TcpListener> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "SO_REUSEPORT is not supported on this platform"))
 }
 
+/// Vérifie, sans bloquer ni consommer, si le client a déjà envoyé des octets
+async fn client_spoke_first(stream: &TcpStream) -> bool {
+    let mut probe = [0u8; 1];
+    let mut buf = ReadBuf::new(&mut probe);
+    std::future::poll_fn(|cx| match stream.poll_peek(cx, &mut buf) {
+        Poll::Ready(Ok(n)) => Poll::Ready(n > 0),
+        _ => Poll::Ready(false),
+    }).await
+}
@@ -0,0 +1,40 @@
+use std::str::FromStr;
+
+/// Points où --test-inject simule une panne (compilé seulement avec la fonctionnalité `test-inject`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectPoint {
+    /// L'enregistrement de la capture échoue
+    Save,
+    /// La poignée de main TLS échoue
+    TlsHandshake,
+    /// L'écriture d'une réponse SMTP expire
+    WriteTimeout,
+}
+
+impl InjectPoint {
+    pub const ALL: [InjectPoint; 3] = [InjectPoint::Save, InjectPoint::TlsHandshake, InjectPoint::WriteTimeout];
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InjectPoint::Save => "save",
+            InjectPoint::TlsHandshake => "tls-handshake",
+            InjectPoint::WriteTimeout => "write-timeout",
+        }
+    }
+}
+
+impl FromStr for InjectPoint {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InjectPoint::ALL.iter()
+            .find(|point| point.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown injection point '{}' (expected save, tls-handshake or write-timeout)", s))
+    }
+}
+
+/// Vrai si une panne doit être simulée à ce point ; toujours faux sans la fonctionnalité `test-inject`
+pub fn should_fail(points: &[InjectPoint], point: InjectPoint) -> bool {
+    cfg!(feature = "test-inject") && points.contains(&point)
+}
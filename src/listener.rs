@@ -0,0 +1,124 @@
+use std::str::FromStr;
+
+/// Mode TLS d'un point d'écoute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsMode {
+    /// Selon le port : TLS implicite sur --implicit-tls-port (465), STARTTLS selon --starttls
+    /// sur --starttls-port (25, 587), clair ailleurs
+    Auto,
+    Plain,
+    Starttls,
+    Implicit,
+}
+
+impl TlsMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsMode::Auto => "auto",
+            TlsMode::Plain => "plain",
+            TlsMode::Starttls => "starttls",
+            TlsMode::Implicit => "implicit",
+        }
+    }
+}
+
+impl FromStr for TlsMode {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(TlsMode::Auto),
+            "plain" => Ok(TlsMode::Plain),
+            "starttls" => Ok(TlsMode::Starttls),
+            "implicit" => Ok(TlsMode::Implicit),
+            _ => Err(format!("invalid TLS mode '{}' (expected auto, plain, starttls or implicit)", s)),
+        }
+    }
+}
+
+/// Point d'écoute : adresse, port, mode TLS et persona
+///
+/// Format : `adresse:port[;tls=plain|starttls|implicit|auto][;profile=domaine]`,
+/// l'adresse IPv6 entre crochets (`[::1]:587`). `profile` désigne une --domain-persona par son domaine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerDef {
+    pub address: String,
+    pub port: u16,
+    pub tls: TlsMode,
+    pub profile: Option<String>,
+}
+
+impl ListenerDef {
+    /// Point d'écoute issu de --address et --port
+    pub fn from_port(address: &str, port: u16) -> Self {
+        Self { address: address.to_string(), port, tls: TlsMode::Auto, profile: None }
+    }
+    
+    /// Adresse de liaison, `adresse:port`
+    pub fn bind_addr(&self) -> String {
+        if self.address.contains(':') {
+            format!("[{}]:{}", self.address, self.port)
+        } else {
+            format!("{}:{}", self.address, self.port)
+        }
+    }
+    
+    /// TLS implicite ; en mode auto, selon --implicit-tls-port
+    pub fn implicit_tls(&self, implicit_ports: &[u16]) -> bool {
+        match self.tls {
+            TlsMode::Auto => implicit_ports.contains(&self.port),
+            TlsMode::Implicit => true,
+            TlsMode::Plain | TlsMode::Starttls => false,
+        }
+    }
+    
+    /// STARTTLS proposé sur ce point d'écoute ; en mode auto, selon --starttls et --starttls-port
+    pub fn starttls(&self, starttls_option: bool, starttls_ports: &[u16]) -> bool {
+        match self.tls {
+            TlsMode::Auto => starttls_option && starttls_ports.contains(&self.port),
+            TlsMode::Starttls => true,
+            TlsMode::Plain | TlsMode::Implicit => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ListenerDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.bind_addr())?;
+        if self.tls != TlsMode::Auto {
+            write!(f, " tls={}", self.tls.as_str())?;
+        }
+        if let Some(profile) = &self.profile {
+            write!(f, " profile={}", profile)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ListenerDef {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = s.split(';');
+        let endpoint = settings.next().unwrap_or("").trim();
+        let (address, port) = endpoint.rsplit_once(':')
+            .ok_or_else(|| format!("invalid listener '{}' (expected address:port)", s))?;
+        let address = address.trim_start_matches('[').trim_end_matches(']');
+        if address.is_empty() {
+            return Err(format!("missing address in listener '{}'", s));
+        }
+        
+        let mut listener = ListenerDef::from_port(address, port.parse()
+            .map_err(|_| format!("invalid port '{}' in listener '{}'", port, s))?);
+        for setting in settings.filter(|p| !p.trim().is_empty()) {
+            let (key, value) = setting.split_once('=')
+                .ok_or_else(|| format!("invalid listener setting '{}' (expected key=value)", setting))?;
+            match key.trim() {
+                "tls" => listener.tls = value.trim().parse()?,
+                "profile" => listener.profile = Some(value.trim().to_string()),
+                other => return Err(format!("unknown listener setting '{}'", other)),
+            }
+        }
+        Ok(listener)
+    }
+}
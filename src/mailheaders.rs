@@ -0,0 +1,112 @@
+use crate::utils;
+
+/// En-têtes du message relevés dans la transaction : (nom en minuscules, champ de l'événement)
+pub const KEY_HEADERS: [(&str, &str); 8] = [
+    ("from", "header_from"),
+    ("to", "header_to"),
+    ("cc", "header_cc"),
+    ("reply-to", "header_reply_to"),
+    ("subject", "header_subject"),
+    ("date", "header_date"),
+    ("message-id", "header_message_id"),
+    ("content-type", "header_content_type"),
+];
+
+/// En-têtes du message reçu en DATA, distincts de l'enveloppe SMTP
+#[derive(Debug, Default)]
+pub struct MessageHeaders {
+    /// Première occurrence de chaque en-tête de KEY_HEADERS, dépliée
+    values: Vec<(&'static str, String)>,
+    /// Nombre d'en-têtes de la section
+    pub count: usize,
+    /// Aucune ligne vide : tout le message est traité comme corps
+    pub malformed: bool,
+    /// Suite du message passée sur disque avant la ligne vide : en-têtes relevés en mémoire seulement
+    pub truncated: bool,
+}
+
+impl MessageHeaders {
+    /// Sépare les en-têtes du corps à la première ligne vide (lignes sans fin de ligne)
+    ///
+    /// `spilled` : le message continue dans un fichier de débordement ; sans ligne vide en mémoire,
+    /// la section d'en-têtes est incomplète plutôt qu'absente.
+    pub fn parse(lines: &[Vec<u8>], spilled: bool) -> Self {
+        let (end, truncated) = match lines.iter().position(|line| line.is_empty()) {
+            Some(end) => (end, false),
+            None if spilled => (lines.len(), true),
+            None => return Self { malformed: true, ..Default::default() },
+        };
+        
+        // Lignes de continuation (espace ou tabulation en tête) rattachées à l'en-tête précédent
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in &lines[..end] {
+            let text = utils::escape_bytes(line);
+            if text.starts_with([' ', '\t']) {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(text.trim());
+                }
+                continue;
+            }
+            if let Some((name, value)) = text.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+        
+        let values = KEY_HEADERS.iter()
+            .filter_map(|(name, field)| {
+                headers.iter()
+                    .find(|(header, _)| header == name)
+                    .map(|(_, value)| (*field, value.clone()))
+            })
+            .collect();
+        Self { values, count: headers.len(), malformed: false, truncated }
+    }
+    
+    /// État de la section d'en-têtes : ok, truncated ou missing
+    pub fn section(&self) -> &'static str {
+        if self.malformed {
+            "missing"
+        } else if self.truncated {
+            "truncated"
+        } else {
+            "ok"
+        }
+    }
+    
+    /// Valeur d'un champ de KEY_HEADERS, vide si l'en-tête est absent
+    pub fn get(&self, field: &str) -> &str {
+        self.values.iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn lines(text: &str) -> Vec<Vec<u8>> {
+        text.split("\r\n").map(|line| line.as_bytes().to_vec()).collect()
+    }
+    
+    #[test]
+    fn headers_cut_by_spill_are_truncated() {
+        let data = lines("From: a@b.example\r\nSubject: long\r\nX-Padding: aaaa");
+        
+        let headers = MessageHeaders::parse(&data, true);
+        assert_eq!(headers.section(), "truncated");
+        assert_eq!(headers.get("header_subject"), "long");
+        assert_eq!(headers.count, 3);
+        
+        assert_eq!(MessageHeaders::parse(&data, false).section(), "missing");
+    }
+    
+    #[test]
+    fn blank_line_in_memory_ends_headers() {
+        let headers = MessageHeaders::parse(&lines("Subject: hi\r\n\r\nbody"), true);
+        assert_eq!(headers.section(), "ok");
+        assert_eq!(headers.get("header_subject"), "hi");
+    }
+}
@@ -1,12 +1,184 @@
 mod utils;
+mod asn;
+mod bans;
+mod captureindex;
+mod clientcert;
+mod control;
+mod daemon;
+mod events;
+mod export;
+mod filters;
+mod sinks;
+mod grpc;
 mod ratelimiter;
 mod session;
+mod tcpinfo;
 mod honeypot;
+mod inject;
+mod listener;
+mod mailheaders;
+mod persona;
+mod proxyproto;
+mod rawcapture;
+mod retries;
+mod scoring;
+mod signatures;
+mod tlsresume;
 
 use structopt::StructOpt;
 use anyhow::Result;
 use std::sync::Arc;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Traitement du corps des messages reçus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataPolicy {
+    /// Sauvegarde complète en .eml
+    Capture,
+    /// Empreinte SHA-256 du corps seulement
+    HashOnly,
+    /// Corps ignoré, seule la taille est comptée
+    Discard,
+}
+
+impl DataPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataPolicy::Capture => "capture",
+            DataPolicy::HashOnly => "hash-only",
+            DataPolicy::Discard => "discard",
+        }
+    }
+}
+
+/// Traitement des commandes au-delà de --max-commands-per-second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandRateAction {
+    /// Commande retardée jusqu'à respecter le débit
+    Tarpit,
+    /// Commande refusée (450) sans être traitée
+    Reject,
+}
+
+impl CommandRateAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandRateAction::Tarpit => "tarpit",
+            CommandRateAction::Reject => "reject",
+        }
+    }
+}
+
+impl FromStr for CommandRateAction {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tarpit" => Ok(CommandRateAction::Tarpit),
+            "reject" => Ok(CommandRateAction::Reject),
+            _ => Err(format!("invalid command rate action '{}' (expected tarpit or reject)", s)),
+        }
+    }
+}
+
+/// Traitement des connexions au-delà de --max-connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// Connexion refusée (421)
+    Hard,
+    /// Connexion conservée, bannière retardée selon le dépassement
+    Soft,
+}
+
+impl RateLimitMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimitMode::Hard => "hard",
+            RateLimitMode::Soft => "soft",
+        }
+    }
+}
+
+impl FromStr for RateLimitMode {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(RateLimitMode::Hard),
+            "soft" => Ok(RateLimitMode::Soft),
+            _ => Err(format!("invalid rate limit mode '{}' (expected soft or hard)", s)),
+        }
+    }
+}
+
+/// Comportement quand les répertoires --data ne sont plus accessibles en écriture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageErrorPolicy {
+    /// Messages acceptés comme d'habitude, l'échec est seulement journalisé
+    Continue,
+    /// DATA refusé (452) tant que l'écriture est impossible
+    Refuse,
+    /// Arrêt du honeypot
+    Exit,
+}
+
+impl StorageErrorPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageErrorPolicy::Continue => "continue",
+            StorageErrorPolicy::Refuse => "refuse",
+            StorageErrorPolicy::Exit => "exit",
+        }
+    }
+}
+
+impl FromStr for StorageErrorPolicy {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "continue" => Ok(StorageErrorPolicy::Continue),
+            "refuse" => Ok(StorageErrorPolicy::Refuse),
+            "exit" => Ok(StorageErrorPolicy::Exit),
+            _ => Err(format!("invalid storage error policy '{}' (expected continue, refuse or exit)", s)),
+        }
+    }
+}
+
+impl FromStr for DataPolicy {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "capture" => Ok(DataPolicy::Capture),
+            "hash-only" => Ok(DataPolicy::HashOnly),
+            "discard" => Ok(DataPolicy::Discard),
+            _ => Err(format!("invalid data policy '{}' (expected capture, hash-only or discard)", s)),
+        }
+    }
+}
+
+/// Répartition des captures entre plusieurs répertoires --data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDistribution {
+    /// Même répertoire pour une même IP cliente
+    Hash,
+    /// Répertoires utilisés à tour de rôle
+    RoundRobin,
+}
+
+impl FromStr for DataDistribution {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(DataDistribution::Hash),
+            "round-robin" => Ok(DataDistribution::RoundRobin),
+            _ => Err(format!("invalid data distribution '{}' (expected hash or round-robin)", s)),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt, Clone)]
 #[structopt(
@@ -20,27 +192,44 @@ pub struct Opt {
     #[structopt(short = "d", long = "daemon")]
     pub daemon: bool,
     
+    /// PID file written in daemon mode (default: /tmp/smtp-honeypot.pid)
+    #[structopt(long = "pid-file", default_value = "/tmp/smtp-honeypot.pid", parse(from_os_str))]
+    pub pid_file: PathBuf,
+    
+    /// Trace the daemonization steps to this file (default: disabled)
+    #[structopt(long = "daemon-debug-log", parse(from_os_str))]
+    pub daemon_debug_log: Option<PathBuf>,
+    
     /// Listening ports (can be specified multiple times, default: 25)
     #[structopt(short = "p", long = "port", default_value = "25", number_of_values = 1)]
     pub ports: Vec<u16>,
     
+    /// Listener definition "address:port[;tls=plain|starttls|implicit|auto][;profile=<--domain-persona domain>]"
+    /// (can be specified multiple times); replaces --address x --port when given
+    #[structopt(long = "listen", number_of_values = 1)]
+    pub listen: Vec<listener::ListenerDef>,
+    
     /// Listening address (default: 0.0.0.0)
     #[structopt(short = "a", long = "address", default_value = "0.0.0.0")]
     pub address: String,
     
     /// Domain(s) to accept mail for (can be specified multiple times, required)
-    #[structopt(long = "domain", required = true, number_of_values = 1)]
+    #[structopt(long = "domain", required_unless = "print-event-schema", number_of_values = 1)]
     pub domains: Vec<String>,
     
     /// Valid mailbox(es) (e.g., user@domain.com) (can be specified multiple times)
     #[structopt(long = "valid-mailbox", number_of_values = 1)]
     pub valid_mailboxes: Vec<String>,
     
+    /// Fake mailbox that always accepts RCPT and logs each probe, e.g. admin@example.com (can be specified multiple times)
+    #[structopt(long = "honey-mailbox", number_of_values = 1)]
+    pub honey_mailboxes: Vec<String>,
+    
     /// Enable open relay mode (accept all recipients)
     #[structopt(long = "open-relay")]
     pub open_relay: bool,
     
-    /// HELO/EHLO response string (default: smtp-honeypot.local)
+    /// HELO/EHLO response string, or "auto" for the system FQDN (default: smtp.local)
     #[structopt(long = "helo", default_value = "smtp.local")]
     pub helo: String,
     
@@ -48,14 +237,42 @@ pub struct Opt {
     #[structopt(long = "logs", parse(from_os_str))]
     pub log_file: Option<PathBuf>,
     
-    /// Directory to save email contents
-    #[structopt(long = "data", parse(from_os_str))]
-    pub data_dir: Option<PathBuf>,
+    /// Format of log lines on stdout: text, ndjson or compact (one tab-separated line per closed
+    /// connection: timestamp, ip, port, helo, mail_from, rcpt_count, data_bytes, tls, auth_attempts, disposition)
+    /// (default: text)
+    #[structopt(long = "stdout-format", default_value = "text")]
+    pub stdout_format: utils::LogFormat,
+    
+    /// Format of log lines in the --logs file: text, ndjson or compact (default: text)
+    #[structopt(long = "file-format", default_value = "text")]
+    pub file_format: utils::LogFormat,
+    
+    /// Directory to save email contents (can be specified multiple times to spread captures)
+    #[structopt(long = "data", parse(from_os_str), number_of_values = 1)]
+    pub data_dirs: Vec<PathBuf>,
+    
+    /// How captures are spread across several --data directories: hash (by client IP) or round-robin (default: hash)
+    #[structopt(long = "data-distribution", default_value = "hash")]
+    pub data_distribution: DataDistribution,
     
     /// Maximum connections per minute from same IP (default: 10)
     #[structopt(long = "max-connections", default_value = "10")]
     pub max_connections_per_minute: usize,
     
+    /// Global cap on accepted connections per second across all ports; excess connections
+    /// get a 421 and are closed (default: unlimited)
+    #[structopt(long = "max-accepts-per-second")]
+    pub max_accepts_per_second: Option<u32>,
+    
+    /// Extra connections allowed above --max-connections in a first burst; renewed only after
+    /// the IP has been quiet for a full minute (default: 0)
+    #[structopt(long = "burst-allowance", default_value = "0")]
+    pub burst_allowance: usize,
+    
+    /// Over --max-connections: hard (421 and close) or soft (delay the banner, longer the further over) (default: hard)
+    #[structopt(long = "rate-limit-mode", default_value = "hard")]
+    pub rate_limit_mode: RateLimitMode,
+    
     /// Verbose mode - display SMTP details
     #[structopt(short = "v", long = "verbose")]
     pub verbose: bool,
@@ -64,7 +281,7 @@ pub struct Opt {
     #[structopt(short = "r", long = "raw")]
     pub raw_display: bool,
     
-    /// TLS certificate file (for ports 465/587)
+    /// TLS certificate file (for implicit TLS and STARTTLS ports)
     #[structopt(long = "tls-cert", parse(from_os_str))]
     pub tls_cert: Option<PathBuf>,
     
@@ -72,138 +289,420 @@ pub struct Opt {
     #[structopt(long = "tls-key", parse(from_os_str))]
     pub tls_key: Option<PathBuf>,
     
+    /// Combined PEM file with the certificate chain and private key (instead of --tls-cert/--tls-key)
+    #[structopt(long = "tls-pem", parse(from_os_str))]
+    pub tls_pem: Option<PathBuf>,
+    
+    /// Expect a PROXY protocol v2 header on every connection (behind a load balancer); the client
+    /// address and any upstream TLS parameters are taken from it
+    #[structopt(long = "proxy-protocol")]
+    pub proxy_protocol: bool,
+    
+    /// Probability (0.0-1.0) that AUTH is advertised in the EHLO reply of a connection, for A/B
+    /// experiments (default: AUTH not advertised)
+    #[structopt(long = "auth-advertise-rate", parse(try_from_str = utils::parse_probability))]
+    pub auth_advertise_rate: Option<f64>,
+    
+    /// Seed of the --auth-advertise-rate draws, to reproduce an experiment (default: random, logged at startup)
+    #[structopt(long = "auth-advertise-seed")]
+    pub auth_advertise_seed: Option<u64>,
+    
+    /// At startup, draw a random server profile (banner and EHLO extensions), a plausible hostname
+    /// under the first --domain and a slightly shuffled extension order, so that each instance of a
+    /// fleet looks like a different server; an explicit --helo is kept
+    #[structopt(long = "randomize-persona")]
+    pub randomize_persona: bool,
+    
+    /// Seed of --randomize-persona, to relaunch the same persona (default: random, logged at startup)
+    #[structopt(long = "persona-seed")]
+    pub persona_seed: Option<u64>,
+    
+    /// Request (but do not require) a TLS client certificate and log the one presented, if any
+    #[structopt(long = "request-client-cert")]
+    pub request_client_cert: bool,
+    
+    /// Deliberately weak TLS profile for downgrade research: TLS 1.2 only, weakest suites
+    /// preferred over the client's order (insecure by design)
+    #[structopt(long = "tls-weak-profile")]
+    pub tls_weak_profile: bool,
+    
     /// Banner delay in milliseconds (default: 0)
     #[structopt(long = "banner-delay", default_value = "0")]
     pub banner_delay: u64,
     
-    /// Enable STARTTLS on port 25/587
+    /// Hold the 354 reply to DATA for this many milliseconds and flag clients that send the body before it
+    #[structopt(long = "data-prompt-delay")]
+    pub data_prompt_delay: Option<u64>,
+    
+    /// Send the banner byte by byte with this delay in milliseconds between bytes (default: disabled)
+    #[structopt(long = "banner-drip")]
+    pub banner_drip: Option<u64>,
+    
+    /// Enable STARTTLS on the --starttls-port ports
     #[structopt(long = "starttls")]
     pub starttls: bool,
+    
+    /// Ports served with implicit TLS by listeners in tls=auto mode
+    /// (comma-separated or specified multiple times, default: 465)
+    #[structopt(long = "implicit-tls-port", default_value = "465", use_delimiter = true)]
+    pub implicit_tls_ports: Vec<u16>,
+    
+    /// Ports offering STARTTLS with --starttls for listeners in tls=auto mode
+    /// (comma-separated or specified multiple times, default: 25,587)
+    #[structopt(long = "starttls-port", default_value = "25,587", use_delimiter = true)]
+    pub starttls_ports: Vec<u16>,
+    
+    /// Reject clients that send data before the banner (554)
+    #[structopt(long = "reject-fast-talker")]
+    pub reject_fast_talker: bool,
+    
+    /// Speak LMTP instead of SMTP (LHLO, one reply per recipient after DATA)
+    #[structopt(long = "lmtp")]
+    pub lmtp: bool,
+    
+    /// Source address or CIDR range exempt from rate limiting, e.g. monitoring (can be specified multiple times)
+    #[structopt(long = "allowlist", number_of_values = 1)]
+    pub allowlist: Vec<utils::IpPrefix>,
+    
+    /// Do not log routine events (connection, commands, close) of --allowlist sources; rejections, AUTH,
+    /// alerts and captures are still logged, and a session is logged in full from its first anomaly on
+    #[structopt(long = "quiet-allowlist")]
+    pub quiet_allowlist: bool,
+    
+    /// Log only 1 in N low-severity connection events per IP (default: 1, log all)
+    #[structopt(long = "log-sample", default_value = "1")]
+    pub log_sample: u64,
+    
+    /// Coalesce identical alerts (same type, same IP) within this window into one summary alert (e.g. 60s, 5m)
+    #[structopt(long = "alert-window", parse(try_from_str = utils::parse_duration))]
+    pub alert_window: Option<std::time::Duration>,
+    
+    /// Raise an alert when a session uses bare-LF, stray CR or mixed line endings instead of strict CRLF
+    #[structopt(long = "alert-line-endings")]
+    pub alert_line_endings: bool,
+    
+    /// Write captured messages through N background writer tasks, capping disk I/O concurrency
+    /// independently of sessions (default: write inline in each session)
+    #[structopt(long = "save-workers")]
+    pub save_workers: Option<usize>,
+    
+    /// What to do with message bodies: capture, hash-only or discard (default: capture)
+    #[structopt(long = "data-policy", default_value = "capture")]
+    pub data_policy: DataPolicy,
+    
+    /// When the --data directories become unwritable: continue, refuse (452 to DATA) or exit (default: continue)
+    #[structopt(long = "on-storage-error", default_value = "continue")]
+    pub on_storage_error: StorageErrorPolicy,
+    
+    /// Per-domain persona, e.g. "example.com:port=2525;helo=mx.example.com;banner=ESMTP;reject=550 User unknown" (can be specified multiple times);
+    /// reject= and syntax= (malformed MAIL/RCPT) accept "{arg}" for the client's value and "|"-separated variants.
+    /// Selected by listener port or profile, then by the HELO/EHLO domain (or a subdomain of it) when one matches
+    #[structopt(long = "domain-persona", number_of_values = 1)]
+    pub domain_personas: Vec<persona::DomainPersona>,
+    
+    /// Send events in CEF format to an http:// URL (POST) or append them to a file
+    #[structopt(long = "cef-url")]
+    pub cef_url: Option<String>,
+    
+    /// Keep the last N events in memory, dumped by the control socket command `recent [n]`
+    #[structopt(long = "recent-events")]
+    pub recent_events: Option<usize>,
+    
+    /// Also write events to the Windows Event Log under the "smtp-honeypot" source (Windows only;
+    /// falls back to stdout/file logging if the source cannot be registered)
+    #[structopt(long = "windows-event-log")]
+    pub windows_event_log: bool,
+    
+    /// Stream DATA to a temporary file once the body exceeds this many bytes
+    #[structopt(long = "spill-threshold")]
+    pub spill_threshold: Option<usize>,
+    
+    /// Maximum number of spill files open at once; sessions over the cap keep DATA in memory
+    /// (default: unlimited)
+    #[structopt(long = "max-open-spill-files")]
+    pub max_open_spill_files: Option<usize>,
+    
+    /// Set SO_REUSEPORT on the listening sockets, so that several instances can share a port
+    /// (Unix only; the kernel spreads connections between sockets on Linux, other systems may not)
+    #[structopt(long = "reuseport")]
+    pub reuseport: bool,
+    
+    /// Accept loops per port, each on its own SO_REUSEPORT socket (requires --reuseport, default: 1)
+    #[structopt(long = "accept-loops", default_value = "1")]
+    pub accept_loops: usize,
+    
+    /// Abort startup if any listening port fails to bind
+    #[structopt(long = "require-all-ports")]
+    pub require_all_ports: bool,
+    
+    /// Reply code for rejected recipients, also used by VRFY when set (default: 550)
+    #[structopt(long = "reject-code")]
+    pub reject_code: Option<u16>,
+    
+    /// Reply text for rejected recipients, may start with an enhanced status code such as
+    /// "5.1.1 User unknown"; "{arg}" is replaced by the recipient; also used by VRFY when set. Variants separated by "|"
+    /// after the first must carry their own 4xx/5xx code (default: "No such user")
+    #[structopt(long = "reject-message")]
+    pub reject_message: Option<String>,
+    
+    /// Accept every recipient while recording those the policy would have rejected
+    #[structopt(long = "accept-all-rcpt")]
+    pub accept_all_rcpt: bool,
+    
+    /// Sinkhole mode: capture payloads while offering as little as possible. Changes: banner
+    /// "220 <helo>" only; EHLO answers one line without extensions; STARTTLS gets 454 and
+    /// AUTH 502 (attempts still logged) and HELP omits AUTH; every recipient is accepted (--accept-all-rcpt).
+    /// Transactions and captures are tagged mode=sinkhole
+    #[structopt(long = "sinkhole")]
+    pub sinkhole: bool,
+    
+    /// Answer MAIL/RCPT with 450 while more than this many sessions are active
+    #[structopt(long = "adaptive-throttle")]
+    pub adaptive_throttle: Option<usize>,
+    
+    /// Maximum number of DATA lines per message; larger messages get 552 and are discarded
+    #[structopt(long = "max-data-lines")]
+    pub max_data_lines: Option<usize>,
+    
+    /// Serve a gRPC event stream on this port (on --address)
+    #[structopt(long = "grpc-port")]
+    pub grpc_port: Option<u16>,
+    
+    /// Route an event type to sinks (stdout, file, cef, grpc), e.g. auth=file,cef; repeatable.
+    /// Unrouted event types go to every enabled sink
+    #[structopt(long = "route", number_of_values = 1)]
+    pub routes: Vec<events::Route>,
+    
+    /// Reject HELO/EHLO that is missing, localhost or malformed (550)
+    #[structopt(long = "reject-bad-helo")]
+    pub reject_bad_helo: bool,
+    
+    /// Maximum number of commands per session, NOOP and RSET included (421 then close)
+    #[structopt(long = "max-commands")]
+    pub max_commands: Option<usize>,
+    
+    /// Maximum number of messages per connection: the next MAIL gets 421 and the connection is closed
+    #[structopt(long = "max-messages-per-connection")]
+    pub max_messages_per_connection: Option<u32>,
+    
+    /// Maximum number of commands per second within a session, DATA lines excluded
+    #[structopt(long = "max-commands-per-second")]
+    pub max_commands_per_second: Option<u32>,
+    
+    /// Commands over --max-commands-per-second: tarpit (delay) or reject (450) (default: tarpit)
+    #[structopt(long = "command-rate-action", default_value = "tarpit")]
+    pub command_rate_action: CommandRateAction,
+    
+    /// Print the JSON Schema of the NDJSON event lines and exit
+    #[structopt(long = "print-event-schema")]
+    pub print_event_schema: bool,
+    
+    /// Validate the configuration, certificates and port binds, then exit
+    #[structopt(long = "check-config")]
+    pub check_config: bool,
+    
+    /// Keep reading for 2 seconds after 221 to log bytes sent after QUIT
+    #[structopt(long = "observe-after-quit")]
+    pub observe_after_quit: bool,
+    
+    /// Permissions of created files (captures, logs), octal, Unix only (default: 0600)
+    #[structopt(long = "file-mode", default_value = "0600", parse(try_from_str = utils::parse_octal_mode))]
+    pub file_mode: u32,
+    
+    /// Permissions of created directories, octal, Unix only (default: 0700)
+    #[structopt(long = "dir-mode", default_value = "0700", parse(try_from_str = utils::parse_octal_mode))]
+    pub dir_mode: u32,
+    
+    /// Override a signal's threat score weight, e.g. "relay-attempt=50" (can be specified multiple times)
+    #[structopt(long = "signal-weight", number_of_values = 1)]
+    pub signal_weights: Vec<scoring::SignalWeight>,
+    
+    /// Ban client IPs whose session threat score reaches this value
+    #[structopt(long = "ban-score")]
+    pub ban_score: Option<u32>,
+    
+    /// Lift bans (--ban-score or control socket) after this long, e.g. 6h (default: 24h)
+    #[structopt(long = "ban-ttl", default_value = "24h", parse(try_from_str = utils::parse_duration))]
+    pub ban_ttl: std::time::Duration,
+    
+    /// Maximum number of banned IPs kept; the oldest ban is dropped beyond it (default: 100000)
+    #[structopt(long = "max-banned", default_value = "100000")]
+    pub max_banned: usize,
+    
+    /// Link a delivery with the same MAIL FROM, RCPT TO and body seen within this window (e.g. 1h)
+    /// to the original capture instead of storing a duplicate
+    #[structopt(long = "dedup-retries", parse(try_from_str = utils::parse_duration))]
+    pub dedup_retries: Option<std::time::Duration>,
+    
+    /// Simulate failures at these points for resilience tests: save, tls-handshake, write-timeout
+    /// (comma-separated; requires a build with the test-inject feature)
+    #[structopt(long = "test-inject", use_delimiter = true, hidden = true)]
+    pub test_inject: Vec<inject::InjectPoint>,
+    
+    /// IP to ASN database in ip2asn TSV format (start, end, ASN, country, description)
+    #[structopt(long = "asn-db", parse(from_os_str))]
+    pub asn_db: Option<PathBuf>,
+    
+    /// Refuse connections from this ASN (can be specified multiple times, requires --asn-db)
+    #[structopt(long = "reject-asn", number_of_values = 1)]
+    pub reject_asns: Vec<u32>,
+    
+    /// Only tag connections from --reject-asn ASNs instead of refusing them
+    #[structopt(long = "flag-asn")]
+    pub flag_asn: bool,
+    
+    /// Log every lookup made for a connection (currently the --asn-db lookup) with its session id, query and answer
+    #[structopt(long = "log-lookups")]
+    pub log_lookups: bool,
+    
+    /// Response sent to connections refused by --reject-asn (default: "554 Service unavailable")
+    #[structopt(long = "reject-asn-response", default_value = "554 Service unavailable")]
+    pub reject_asn_response: String,
+    
+    /// Alert when a captured message body matches this regex (can be specified multiple times;
+    /// GTUBE and EICAR are always checked)
+    #[structopt(long = "body-signature", number_of_values = 1)]
+    pub body_signatures: Vec<String>,
+    
+    /// Save messages matching a body signature to this directory instead of --data
+    #[structopt(long = "quarantine", parse(from_os_str))]
+    pub quarantine: Option<PathBuf>,
+    
+    /// Export all .eml captures from --data into this mbox file, then exit
+    #[structopt(long = "export-mbox", parse(from_os_str))]
+    pub export_mbox: Option<PathBuf>,
+    
+    /// Unix socket accepting runtime commands: stats, ban <ip>, unban <ip>, reload, drain
+    /// (permissions from --file-mode)
+    #[structopt(long = "control-socket", parse(from_os_str))]
+    pub control_socket: Option<PathBuf>,
+    
+    /// Line sent to banned IPs before closing, e.g. "554 Access denied" (default: silent drop)
+    #[structopt(long = "banned-response")]
+    pub banned_response: Option<String>,
+    
+    /// Shut down gracefully after this uptime (e.g. 12h) and exit with code 75, for supervisor restarts;
+    /// new connections are refused and active sessions get up to 60s to finish
+    #[structopt(long = "max-uptime", parse(try_from_str = utils::parse_duration))]
+    pub max_uptime: Option<std::time::Duration>,
+    
+    /// Close sessions that send nothing for this many seconds, at any point (421)
+    #[structopt(long = "session-idle-timeout")]
+    pub session_idle_timeout: Option<u64>,
+    
+    /// Write the exact bytes of each session (both directions, timestamped frames) to this directory
+    #[structopt(long = "capture-raw", parse(from_os_str))]
+    pub capture_raw: Option<PathBuf>,
+    
+    /// Append one JSON line per saved capture (timestamp, ip, helo, mail_from, rcpts, sha256, size,
+    /// filename) to this file, rotated daily to <file>.YYYY-MM-DD
+    #[structopt(long = "index-file", parse(from_os_str))]
+    pub index_file: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+// Code de sortie après --max-uptime (EX_TEMPFAIL) : le superviseur doit relancer
+const EXIT_MAX_UPTIME: i32 = 75;
+// Attente maximale de la fin des sessions en cours après --max-uptime
+const MAX_UPTIME_DRAIN: std::time::Duration = std::time::Duration::from_secs(60);
+// Code de sortie après un arrêt pour stockage inaccessible (EX_IOERR, --on-storage-error exit)
+const EXIT_STORAGE_ERROR: i32 = 74;
+
+impl Opt {
+    /// Points d'écoute : --listen, sinon chaque --port sur --address
+    pub fn listeners(&self) -> Vec<listener::ListenerDef> {
+        if !self.listen.is_empty() {
+            return self.listen.clone();
+        }
+        self.ports.iter()
+            .map(|port| listener::ListenerDef::from_port(&self.address, *port))
+            .collect()
+    }
+    
+    pub fn file_modes(&self) -> utils::FileModes {
+        utils::FileModes { file: self.file_mode, dir: self.dir_mode }
+    }
+}
+
+/// Ligne d'information : sur stdout en format texte, sur stderr avec --stdout-format ndjson
+/// ou compact, pour que la sortie standard reste lisible par jq ou awk
+macro_rules! info {
+    ($opt:expr, $($arg:tt)*) => {
+        if $opt.stdout_format == utils::LogFormat::Text {
+            println!($($arg)*)
+        } else {
+            eprintln!($($arg)*)
+        }
+    };
+}
+
+fn main() -> Result<()> {
     let opt = Opt::from_args();
     
+    // Avant toute autre sortie : le schéma seul sur la sortie standard
+    if opt.print_event_schema {
+        println!("{}", events::json_schema());
+        return Ok(());
+    }
+    
     if opt.domains.is_empty() {
         eprintln!("[ERROR] At least one domain must be specified with --domain");
         std::process::exit(1);
     }
     
-    println!("==========================================");
-    println!("SMTP Honeypot v{}", env!("CARGO_PKG_VERSION"));
-    println!("==========================================");
+    info!(opt, "==========================================");
+    info!(opt, "SMTP Honeypot v{}", env!("CARGO_PKG_VERSION"));
+    info!(opt, "==========================================");
+    
+    if let Some(mbox_path) = &opt.export_mbox {
+        if opt.data_dirs.is_empty() {
+            eprintln!("[ERROR] --export-mbox requires --data");
+            std::process::exit(1);
+        }
+        let count = export::export_mbox(&opt.data_dirs, mbox_path, opt.file_modes())?;
+        info!(opt, "[INFO] Exported {} message(s) to {:?}", count, mbox_path);
+        return Ok(());
+    }
+    
+    if opt.check_config {
+        let ok = tokio::runtime::Runtime::new()?.block_on(honeypot::SmtpHoneypot::check_config(opt));
+        std::process::exit(if ok { 0 } else { 1 });
+    }
     
     eprintln!("[INFO] Starting as user: {}", 
               std::env::var("USER").unwrap_or_else(|_| "unknown".to_string()));
     eprintln!("[INFO] PID: {}", std::process::id());
     eprintln!("[INFO] Working directory: {:?}", std::env::current_dir().unwrap());
     
-    // Vérifier/Créer les répertoires nécessaires AVANT daemonisation
+    // Daemonisation AVANT la création du runtime : un fork ne doit jamais traverser un runtime tokio actif
+    if opt.daemon {
+        eprintln!("[INFO] Starting daemon mode...");
+        if let Err(e) = daemon::daemonize(&opt) {
+            eprintln!("[ERROR] Daemon startup failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+    
+    tokio::runtime::Runtime::new()?.block_on(run(opt))
+}
+
+async fn run(opt: Opt) -> Result<()> {
+    // Vérifier/Créer les répertoires nécessaires
     if let Some(log_path) = &opt.log_file {
         if let Some(parent) = log_path.parent() {
             if !parent.exists() {
-                std::fs::create_dir_all(parent)?;
+                opt.file_modes().create_dir_all(parent)?;
                 eprintln!("[INFO] Created log directory: {:?}", parent);
             }
         }
     }
     
-    if let Some(data_dir) = &opt.data_dir {
+    for data_dir in &opt.data_dirs {
         if !data_dir.exists() {
-            std::fs::create_dir_all(data_dir)?;
+            opt.file_modes().create_dir_all(data_dir)?;
             eprintln!("[INFO] Created data directory: {:?}", data_dir);
         }
     }
     
-    // === DAEMONISATION DANS UN THREAD SÉPARÉ ===
-    if opt.daemon {
-        eprintln!("[INFO] Starting daemon mode...");
-        
-        use tokio::sync::oneshot;
-        let (tx, rx) = oneshot::channel();
-        
-        // Cloner opt pour le mouvement dans le thread
-        let opt_clone = opt.clone();
-        
-        std::thread::spawn(move || {
-            use daemonize::Daemonize;
-            use std::fs;
-            
-            // Daemonisation
-            let daemonize = Daemonize::new()
-                .pid_file("/tmp/smtp-honeypot.pid")
-                .chown_pid_file(true)
-                .working_directory(".");  // Garde le répertoire courant
-            
-            match daemonize.start() {
-                Ok(_) => {
-                    // Dans le processus enfant
-                    let pid = std::process::id();
-                    
-                    // Écrire un fichier de test
-                    let _ = fs::write("/tmp/smtp-honeypot-daemon-test.txt", 
-                                      format!("Child process started at PID {}\n", pid));
-                    
-                    // Tester la création d'un socket simple
-                    use std::net::TcpListener;
-                    match TcpListener::bind("127.0.0.1:0") {
-                        Ok(_) => {
-                            let _ = fs::write("/tmp/smtp-honeypot-socket-test.txt", 
-                                             "Socket test successful\n");
-                        }
-                        Err(e) => {
-                            let _ = fs::write("/tmp/smtp-honeypot-socket-test.txt", 
-                                             format!("Socket test failed: {}\n", e));
-                        }
-                    }
-                    
-                    // Signaler au parent que la daemonisation a réussi
-                    let _ = tx.send(pid);
-                    
-                    // CRÉER UN NOUVEAU RUNTIME TOKIO DANS L'ENFANT
-                    let runtime = tokio::runtime::Runtime::new().unwrap();
-                    runtime.block_on(async {
-                        eprintln!("[INFO] Child process: creating honeypot...");
-                        
-                        match honeypot::SmtpHoneypot::new(opt_clone).await {
-                            Ok(h) => {
-                                let honeypot = Arc::new(h);
-                                eprintln!("[INFO] Child process: honeypot created successfully");
-                                eprintln!("[INFO] Child process: starting main loop...");
-                                
-                                if let Err(e) = honeypot.run().await {
-                                    eprintln!("[ERROR] Child process: server error: {}", e);
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("[ERROR] Child process: failed to create honeypot: {}", e);
-                            }
-                        }
-                    });
-                }
-                Err(e) => {
-                    eprintln!("[ERROR] Daemon startup failed: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        });
-        
-        // Attendre que le processus enfant signale qu'il est prêt
-        match rx.await {
-            Ok(pid) => {
-                eprintln!("[INFO] Daemon child process running (PID: {})", pid);
-                eprintln!("[INFO] Parent process exiting");
-                std::process::exit(0);
-            }
-            Err(_) => {
-                eprintln!("[ERROR] Daemon child failed to start");
-                std::process::exit(1);
-            }
-        }
-    }
-    
-    // === MODE NORMAL (PAS DE DAEMON) ===
     eprintln!("[INFO] Creating honeypot instance...");
     
     let honeypot = match honeypot::SmtpHoneypot::new(opt).await {
@@ -214,26 +713,98 @@ async fn main() -> Result<()> {
         }
     };
     
-    println!("[INFO] SMTP honeypot started in foreground");
-    println!("[INFO] PID: {}", std::process::id());
-    println!("[INFO] Ports: {:?}", honeypot.opt.ports);
-    println!("[INFO] Domains: {:?}", honeypot.opt.domains);
-    println!("[INFO] Open relay mode: {}", honeypot.opt.open_relay);
+    info!(honeypot.opt, "[INFO] SMTP honeypot started in {}", if honeypot.opt.daemon { "background" } else { "foreground" });
+    info!(honeypot.opt, "[INFO] PID: {}", std::process::id());
+    info!(honeypot.opt, "[INFO] Listeners: {}", honeypot.opt.listeners().iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", "));
+    info!(honeypot.opt, "[INFO] Domains: {:?}", honeypot.opt.domains);
+    info!(honeypot.opt, "[INFO] Open relay mode: {}", honeypot.opt.open_relay);
+    if honeypot.opt.sinkhole {
+        info!(honeypot.opt, "[INFO] Sinkhole mode: minimal banner and EHLO, no STARTTLS/AUTH, all recipients accepted");
+    } else if honeypot.opt.accept_all_rcpt {
+        info!(honeypot.opt, "[INFO] Accepting all recipients for capture");
+    }
     if !honeypot.valid_mailboxes.is_empty() {
-        println!("[INFO] Valid mailboxes: {:?}", honeypot.valid_mailboxes);
+        info!(honeypot.opt, "[INFO] Valid mailboxes: {:?}", honeypot.valid_mailboxes);
     }
     if honeypot.tls_acceptor.is_some() {
-        println!("[INFO] TLS enabled");
+        info!(honeypot.opt, "[INFO] TLS enabled");
     }
     if honeypot.opt.starttls {
-        println!("[INFO] STARTTLS enabled on port 25/587");
+        info!(honeypot.opt, "[INFO] STARTTLS enabled on ports {:?}", honeypot.opt.starttls_ports);
+    }
+    if honeypot.opt.data_policy != DataPolicy::Capture {
+        info!(honeypot.opt, "[INFO] Data policy: {}", honeypot.opt.data_policy.as_str());
+    }
+    if honeypot.opt.lmtp {
+        info!(honeypot.opt, "[INFO] LMTP mode enabled");
     }
-    println!("[INFO] Max connections per minute per IP: {}", honeypot.opt.max_connections_per_minute);
-    println!("[INFO] Waiting for connections...");
-    println!("[INFO] Press Ctrl+C to stop");
+    info!(honeypot.opt, "[INFO] Max connections per minute per IP: {} ({} mode)", honeypot.opt.max_connections_per_minute,
+          honeypot.opt.rate_limit_mode.as_str());
+    if honeypot.opt.burst_allowance > 0 {
+        info!(honeypot.opt, "[INFO] Burst allowance: {} extra connections per IP", honeypot.opt.burst_allowance);
+    }
+    info!(honeypot.opt, "[INFO] Waiting for connections...");
+    info!(honeypot.opt, "[INFO] Press Ctrl+C to stop");
     
-    honeypot.run().await?;
+    let max_uptime = honeypot.opt.max_uptime;
+    if let Some(uptime) = max_uptime {
+        let deadline = chrono::Local::now() + chrono::Duration::from_std(uptime).unwrap_or(chrono::Duration::MAX);
+        info!(honeypot.opt, "[INFO] Scheduled shutdown at {} (--max-uptime), exit code {}",
+              deadline.format("%Y-%m-%d %H:%M:%S"), EXIT_MAX_UPTIME);
+    }
+    
+    tokio::select! {
+        result = honeypot.clone().run() => result?,
+        reason = wait_for_shutdown_signal() => {
+            eprintln!("[INFO] {} received, shutting down", reason);
+            honeypot.shutdown(reason).await;
+        }
+        reason = honeypot.stopped() => {
+            eprintln!("[INFO] Stop requested ({}), shutting down", reason);
+            honeypot.shutdown(reason).await;
+            if reason == "storage-error" {
+                std::process::exit(EXIT_STORAGE_ERROR);
+            }
+        }
+        _ = async {
+            match max_uptime {
+                Some(uptime) => tokio::time::sleep(uptime).await,
+                None => std::future::pending().await,
+            }
+        } => {
+            eprintln!("[INFO] Max uptime reached, draining (up to {}s)", MAX_UPTIME_DRAIN.as_secs());
+            let remaining = honeypot.drain("Max uptime", MAX_UPTIME_DRAIN).await;
+            if remaining > 0 {
+                eprintln!("[WARNING] {} session(s) still active, shutting down anyway", remaining);
+            }
+            honeypot.shutdown("max-uptime").await;
+            std::process::exit(EXIT_MAX_UPTIME);
+        }
+    }
     
     Ok(())
 }
 
+/// Attend Ctrl+C ou SIGTERM et retourne le nom du signal reçu
+async fn wait_for_shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = sigterm.recv() => "SIGTERM",
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use crate::utils;
+
+/// Longueur maximale de la valeur du client reprise dans une réponse
+const ECHO_MAX_LEN: usize = 64;
+
+/// Extensions EHLO propres au honeypot, placées parmi celles du profil tiré
+pub const OWN_EXTENSIONS: [&str; 3] = ["STARTTLS", "AUTH PLAIN LOGIN", "HELP"];
+
+/// Profils de serveurs connus pour --randomize-persona : nom, bannière, extensions EHLO annoncées
+/// (limitées à ce que le honeypot honore : ni CHUNKING ni ETRN, ni ENHANCEDSTATUSCODES faute de
+/// codes X.Y.Z dans les réponses ; la limite SIZE annoncée est appliquée)
+const PROFILES: [(&str, &str, &[&str]); 5] = [
+    ("postfix", "ESMTP Postfix", &["PIPELINING", "SIZE 10240000", "VRFY", "8BITMIME", "SMTPUTF8"]),
+    ("exim", "ESMTP Exim 4.96", &["SIZE 52428800", "8BITMIME", "PIPELINING"]),
+    ("sendmail", "ESMTP Sendmail 8.17.1/8.17.1", &["PIPELINING", "8BITMIME", "SIZE"]),
+    ("exchange", "Microsoft ESMTP MAIL Service ready", &["SIZE 37748736", "PIPELINING", "8BITMIME"]),
+    ("opensmtpd", "ESMTP OpenSMTPD", &["8BITMIME", "SIZE 36700160"]),
+];
+
+/// Préfixes plausibles du nom d'hôte tiré
+const HOST_PREFIXES: [&str; 8] = ["mx", "mx1", "mx2", "mail", "smtp", "mta", "relay", "mail01"];
+
+/// Probabilité d'échanger deux extensions voisines : un ordre à peine remanié
+const EXTENSION_SWAP_RATE: f64 = 0.3;
+
+/// Identité présentée pour un domaine : bannière, nom HELO et messages d'erreur
+///
+/// Format : `domaine:port=2525;helo=mx.example.com;banner=ESMTP Postfix;reject=550 5.1.1 User unknown`
+///
+/// `reject` (destinataire refusé) et `syntax` (MAIL/RCPT mal formé) sont des modèles :
+/// `{arg}` y est remplacé par la valeur du client, et plusieurs variantes séparées par `|`
+/// sont tirées au hasard, ex. `syntax=501 5.1.3 Bad recipient address syntax: {arg}|501 Syntax error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainPersona {
+    pub domain: String,
+    pub port: Option<u16>,
+    pub helo: Option<String>,
+    pub banner: Option<String>,
+    pub reject: Option<String>,
+    pub syntax: Option<String>,
+}
+
+impl FromStr for DomainPersona {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (domain, settings) = s.split_once(':').unwrap_or((s, ""));
+        if domain.is_empty() {
+            return Err(format!("missing domain in persona '{}'", s));
+        }
+        
+        let mut persona = DomainPersona {
+            domain: domain.to_string(),
+            port: None,
+            helo: None,
+            banner: None,
+            reject: None,
+            syntax: None,
+        };
+        
+        for setting in settings.split(';').filter(|p| !p.trim().is_empty()) {
+            let (key, value) = setting.split_once('=')
+                .ok_or_else(|| format!("invalid persona setting '{}' (expected key=value)", setting))?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "port" => {
+                    persona.port = Some(value.parse()
+                        .map_err(|_| format!("invalid persona port '{}'", value))?);
+                }
+                "helo" => persona.helo = Some(value),
+                "banner" => persona.banner = Some(value),
+                "reject" => persona.reject = Some(parse_error_template("reject", value)?),
+                "syntax" => persona.syntax = Some(parse_error_template("syntax", value)?),
+                other => return Err(format!("unknown persona setting '{}'", other)),
+            }
+        }
+        
+        Ok(persona)
+    }
+}
+
+/// Chaque variante du modèle doit commencer par un code 4xx/5xx
+fn parse_error_template(key: &str, value: String) -> Result<String, String> {
+    match variant_without_code(&value) {
+        Some(variant) => Err(format!("persona {} message must start with a 4xx/5xx code: '{}'", key, variant)),
+        None => Ok(value),
+    }
+}
+
+/// Première variante du modèle qui ne commence pas par un code 4xx/5xx
+pub fn variant_without_code(template: &str) -> Option<&str> {
+    template.split('|').find(|variant| {
+        let code = variant.trim().get(..3).unwrap_or("");
+        !(code.starts_with('4') || code.starts_with('5')) || !code.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Réponse tirée d'un modèle d'erreur : variante choisie par `draw` (dans [0, 1)),
+/// `{arg}` remplacé par la valeur du client nettoyée
+pub fn render_error(template: &str, arg: &str, draw: f64) -> String {
+    let variants: Vec<&str> = template.split('|').map(str::trim).collect();
+    let index = ((draw * variants.len() as f64) as usize).min(variants.len() - 1);
+    variants[index].replace("{arg}", &echo_safe(arg))
+}
+
+/// Valeur du client reprise dans une réponse : ni fin de ligne ni caractère de contrôle
+/// (injection de réponse ou de journal), longueur bornée
+fn echo_safe(arg: &str) -> String {
+    let escaped = utils::safe_log_string(arg).replace('\r', "\\r").replace('\n', "\\n");
+    if escaped.chars().count() > ECHO_MAX_LEN {
+        format!("{}...", escaped.chars().take(ECHO_MAX_LEN).collect::<String>())
+    } else {
+        escaped
+    }
+}
+
+/// Identité tirée au démarrage par --randomize-persona, reproductible par sa graine
+#[derive(Debug, Clone)]
+pub struct RandomPersona {
+    pub seed: u64,
+    pub profile: &'static str,
+    pub hostname: String,
+    pub banner: &'static str,
+    /// Extensions du profil et celles du honeypot (OWN_EXTENSIONS), dans l'ordre annoncé
+    pub extensions: Vec<&'static str>,
+}
+
+impl RandomPersona {
+    pub fn draw(seed: u64, domain: &str) -> Self {
+        let mut draws = (0..).map(|n| utils::seeded_unit(seed, n));
+        let mut pick = |len: usize| ((draws.next().unwrap_or(0.0) * len as f64) as usize).min(len - 1);
+        
+        let (profile, banner, profile_extensions) = PROFILES[pick(PROFILES.len())];
+        let hostname = format!("{}.{}", HOST_PREFIXES[pick(HOST_PREFIXES.len())], domain);
+        
+        // HELP reste en dernier, comme chez la plupart des serveurs
+        let mut extensions: Vec<&'static str> = profile_extensions.to_vec();
+        extensions.extend(&OWN_EXTENSIONS[..2]);
+        for i in 0..extensions.len().saturating_sub(1) {
+            if pick(1000) < (EXTENSION_SWAP_RATE * 1000.0) as usize {
+                extensions.swap(i, i + 1);
+            }
+        }
+        extensions.push(OWN_EXTENSIONS[2]);
+        
+        Self { seed, profile, hostname, banner, extensions }
+    }
+    
+    /// Taille maximale annoncée par `SIZE <octets>`, None pour `SIZE` seul (pas de limite fixe)
+    pub fn size_limit(&self) -> Option<u64> {
+        self.extensions.iter().find_map(|ext| ext.strip_prefix("SIZE ")?.parse().ok())
+    }
+    
+    /// Extensions annoncées : celles du profil, plus celles du honeypot actives pour la session
+    pub fn order_extensions(&self, active: &[&str]) -> Vec<&'static str> {
+        self.extensions.iter()
+            .filter(|ext| !OWN_EXTENSIONS.contains(ext) || active.contains(ext))
+            .copied()
+            .collect()
+    }
+}
+
+impl std::fmt::Display for RandomPersona {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "profile={} hostname={} banner=\"{}\" extensions={} seed={}",
+               self.profile, self.hostname, self.banner, self.extensions.join(","), self.seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn profiles_advertise_only_honoured_extensions() {
+        for seed in 0..200 {
+            let persona = RandomPersona::draw(seed, "example.com");
+            assert!(!persona.extensions.contains(&"ENHANCEDSTATUSCODES"), "{}", persona);
+            let size = persona.extensions.iter().find(|ext| ext.starts_with("SIZE")).unwrap();
+            assert_eq!(persona.size_limit(), size.strip_prefix("SIZE ").map(|n| n.parse().unwrap()), "{}", persona);
+        }
+    }
+    
+    #[test]
+    fn echo_safe_neutralises_client_values() {
+        assert_eq!(echo_safe("a@b\r\n250 OK"), "a@b\\r\\n250 OK");
+        assert_eq!(echo_safe("jos\u{e9}@b"), "jos\\u{e9}@b");
+        assert_eq!(echo_safe(&"x".repeat(200)), format!("{}...", "x".repeat(ECHO_MAX_LEN)));
+    }
+    
+    #[test]
+    fn render_error_keeps_the_status_code() {
+        let template = "550 5.1.1 <{arg}> unknown | 553 5.1.3 <{arg}> rejected";
+        let arg = format!("victim@example.com\r\n{}", "y".repeat(200));
+        for (draw, code) in [(0.0, "550 5.1.1 <"), (0.99, "553 5.1.3 <")] {
+            let line = render_error(template, &arg, draw);
+            assert!(line.starts_with(code), "{}", line);
+            assert!(!line.contains('\r') && !line.contains('\n'), "{}", line);
+        }
+    }
+}
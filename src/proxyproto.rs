@@ -0,0 +1,199 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::Result;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use crate::session::TlsInfo;
+
+/// Signature d'un en-tête PROXY protocol v2
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+// Types TLV utiles (section 2.2 de la spécification)
+const PP2_TYPE_AUTHORITY: u8 = 0x02;
+const PP2_TYPE_SSL: u8 = 0x20;
+const PP2_SUBTYPE_SSL_VERSION: u8 = 0x21;
+const PP2_SUBTYPE_SSL_CIPHER: u8 = 0x23;
+// Bit du champ `client` de PP2_TYPE_SSL : le client était connecté en TLS
+const PP2_CLIENT_SSL: u8 = 0x01;
+
+/// En-tête PROXY v2 transmis par un répartiteur en amont (--proxy-protocol)
+#[derive(Debug, Default)]
+pub struct ProxyHeader {
+    /// Adresse du client d'origine ; absente pour LOCAL (sonde du répartiteur) ou une famille inconnue
+    pub source: Option<SocketAddr>,
+    /// TLS terminé en amont, d'après les TLV
+    pub tls: Option<TlsInfo>,
+}
+
+/// Lit l'en-tête PROXY v2 en tête de connexion ; aucun octet au-delà n'est consommé
+pub async fn read_v2(stream: &mut TcpStream) -> Result<ProxyHeader> {
+    let mut fixed = [0u8; 16];
+    stream.read_exact(&mut fixed).await?;
+    if fixed[..12] != SIGNATURE {
+        return Err(anyhow::anyhow!("missing PROXY v2 signature"));
+    }
+    let length = u16::from_be_bytes([fixed[14], fixed[15]]) as usize;
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).await?;
+    parse_v2(fixed[12], fixed[13], &payload)
+}
+
+fn parse_v2(version_command: u8, family: u8, payload: &[u8]) -> Result<ProxyHeader> {
+    if version_command >> 4 != 2 {
+        return Err(anyhow::anyhow!("unsupported PROXY version {}", version_command >> 4));
+    }
+    let (source, addresses_len) = match family >> 4 {
+        // AF_INET : source, destination, ports
+        1 if payload.len() >= 12 => {
+            let ip = Ipv4Addr::new(payload[0], payload[1], payload[2], payload[3]);
+            (Some(SocketAddr::new(IpAddr::V4(ip), u16::from_be_bytes([payload[8], payload[9]]))), 12)
+        }
+        // AF_INET6
+        2 if payload.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&payload[..16]);
+            let ip = Ipv6Addr::from(octets);
+            (Some(SocketAddr::new(IpAddr::V6(ip), u16::from_be_bytes([payload[32], payload[33]]))), 36)
+        }
+        // AF_UNIX
+        3 if payload.len() >= 216 => (None, 216),
+        0 => (None, 0),
+        _ => return Err(anyhow::anyhow!("truncated or unknown PROXY address family 0x{:02x}", family)),
+    };
+    
+    let mut header = ProxyHeader {
+        // LOCAL : connexion du répartiteur lui-même, l'adresse de la socket fait foi
+        source: if version_command & 0x0f == 1 { source } else { None },
+        tls: None,
+    };
+    let mut authority = None;
+    for (kind, value) in tlvs(&payload[addresses_len..])? {
+        match kind {
+            PP2_TYPE_AUTHORITY => authority = Some(String::from_utf8_lossy(value).into_owned()),
+            // client (1 octet), verify (4 octets), puis sous-TLV
+            PP2_TYPE_SSL if value.len() >= 5 && value[0] & PP2_CLIENT_SSL != 0 => {
+                let mut tls = TlsInfo { source: "proxy-tlv", ..Default::default() };
+                for (subtype, subvalue) in tlvs(&value[5..])? {
+                    let text = String::from_utf8_lossy(subvalue).into_owned();
+                    match subtype {
+                        PP2_SUBTYPE_SSL_VERSION => tls.version = Some(text),
+                        PP2_SUBTYPE_SSL_CIPHER => tls.cipher = Some(text),
+                        _ => {}
+                    }
+                }
+                header.tls = Some(tls);
+            }
+            _ => {}
+        }
+    }
+    // L'autorité transmise est le SNI présenté au répartiteur
+    if let Some(tls) = header.tls.as_mut() {
+        tls.sni = authority;
+    }
+    Ok(header)
+}
+
+/// Découpe une suite de TLV (type, longueur sur 2 octets, valeur) ; un TLV tronqué est une erreur
+fn tlvs(mut data: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut result = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 3 {
+            return Err(anyhow::anyhow!("truncated PROXY v2 TLV header ({} byte(s))", data.len()));
+        }
+        let length = u16::from_be_bytes([data[1], data[2]]) as usize;
+        if data.len() < 3 + length {
+            return Err(anyhow::anyhow!("truncated PROXY v2 TLV 0x{:02x}: {} byte(s) announced, {} available",
+                                       data[0], length, data.len() - 3));
+        }
+        result.push((data[0], &data[3..3 + length]));
+        data = &data[3 + length..];
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const PROXY: u8 = 0x21;
+    const LOCAL: u8 = 0x20;
+    const TCP4: u8 = 0x11;
+    const TCP6: u8 = 0x21;
+    
+    fn tlv(kind: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(value);
+        bytes
+    }
+    
+    fn ipv4_block() -> Vec<u8> {
+        let mut block = vec![198, 51, 100, 7, 10, 0, 0, 1];
+        block.extend_from_slice(&40000u16.to_be_bytes());
+        block.extend_from_slice(&25u16.to_be_bytes());
+        block
+    }
+    
+    #[test]
+    fn ipv4_and_ipv6_sources_are_read() {
+        let header = parse_v2(PROXY, TCP4, &ipv4_block()).unwrap();
+        assert_eq!(header.source, Some("198.51.100.7:40000".parse().unwrap()));
+        assert!(header.tls.is_none());
+        
+        let mut block = "2001:db8::7".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        block.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        block.extend_from_slice(&40001u16.to_be_bytes());
+        block.extend_from_slice(&25u16.to_be_bytes());
+        let header = parse_v2(PROXY, TCP6, &block).unwrap();
+        assert_eq!(header.source, Some("[2001:db8::7]:40001".parse().unwrap()));
+    }
+    
+    #[test]
+    fn local_command_has_no_source() {
+        assert!(parse_v2(LOCAL, TCP4, &ipv4_block()).unwrap().source.is_none());
+        assert!(parse_v2(LOCAL, 0x00, &[]).unwrap().source.is_none());
+    }
+    
+    #[test]
+    fn ssl_tlv_gives_tls_details() {
+        let mut ssl = vec![PP2_CLIENT_SSL, 0, 0, 0, 0];
+        ssl.extend(tlv(PP2_SUBTYPE_SSL_VERSION, b"TLSv1.3"));
+        ssl.extend(tlv(PP2_SUBTYPE_SSL_CIPHER, b"TLS_AES_128_GCM_SHA256"));
+        let mut payload = ipv4_block();
+        payload.extend(tlv(PP2_TYPE_AUTHORITY, b"mail.example.com"));
+        payload.extend(tlv(PP2_TYPE_SSL, &ssl));
+        
+        let tls = parse_v2(PROXY, TCP4, &payload).unwrap().tls.unwrap();
+        assert_eq!(tls.source, "proxy-tlv");
+        assert_eq!(tls.version.as_deref(), Some("TLSv1.3"));
+        assert_eq!(tls.cipher.as_deref(), Some("TLS_AES_128_GCM_SHA256"));
+        assert_eq!(tls.sni.as_deref(), Some("mail.example.com"));
+        
+        // Client en clair : pas de TLS malgré le TLV
+        let mut payload = ipv4_block();
+        payload.extend(tlv(PP2_TYPE_SSL, &[0, 0, 0, 0, 0]));
+        assert!(parse_v2(PROXY, TCP4, &payload).unwrap().tls.is_none());
+    }
+    
+    #[test]
+    fn truncated_input_is_an_error() {
+        assert!(parse_v2(PROXY, TCP4, &ipv4_block()[..11]).is_err());
+        assert!(parse_v2(PROXY, TCP6, &[0; 35]).is_err());
+        
+        let mut payload = ipv4_block();
+        payload.extend(&tlv(PP2_TYPE_AUTHORITY, b"mail.example.com")[..10]);
+        assert!(parse_v2(PROXY, TCP4, &payload).is_err());
+        
+        let mut payload = ipv4_block();
+        payload.extend([PP2_TYPE_AUTHORITY, 0]);
+        assert!(parse_v2(PROXY, TCP4, &payload).is_err());
+        
+        // Sous-TLV tronqué dans PP2_TYPE_SSL
+        let mut ssl = vec![PP2_CLIENT_SSL, 0, 0, 0, 0];
+        ssl.extend(&tlv(PP2_SUBTYPE_SSL_VERSION, b"TLSv1.3")[..5]);
+        let mut payload = ipv4_block();
+        payload.extend(tlv(PP2_TYPE_SSL, &ssl));
+        assert!(parse_v2(PROXY, TCP4, &payload).is_err());
+    }
+}
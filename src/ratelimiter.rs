@@ -1,39 +1,167 @@
 use std::collections::{HashMap, VecDeque};
-use std::net::SocketAddr;
+use std::net::IpAddr;
 use std::time::{Duration, Instant};
 
+/// Connexions récentes d'une IP
+#[derive(Default)]
+struct History {
+    entries: VecDeque<Instant>,
+    /// Connexions admises au-delà de la limite grâce à --burst-allowance
+    burst_used: usize,
+}
+
 pub struct RateLimiter {
-    connections: HashMap<SocketAddr, VecDeque<Instant>>,
+    connections: HashMap<IpAddr, History>,
+    /// Nombre de connexions refusées par IP
+    rejections: HashMap<IpAddr, u64>,
     max_per_minute: usize,
+    /// Connexions admises en plus de la limite lors d'une première rafale
+    burst: usize,
 }
 
 impl RateLimiter {
-    pub fn new(max_per_minute: usize) -> Self {
+    pub fn new(max_per_minute: usize, burst: usize) -> Self {
         Self {
             connections: HashMap::new(),
+            rejections: HashMap::new(),
             max_per_minute,
+            burst,
         }
     }
     
-    pub fn check_and_add(&mut self, addr: SocketAddr) -> bool {
-        let now = Instant::now();
-        let entries = self.connections.entry(addr).or_insert_with(VecDeque::new);
-        
-        // Nettoyer les entrées plus vieilles qu'une minute
-        while let Some(&time) = entries.front() {
+    /// Historique de l'IP, nettoyé des entrées plus vieilles qu'une minute
+    ///
+    /// Une IP restée silencieuse une minute entière retrouve sa rafale : un abus soutenu
+    /// ne la recharge jamais.
+    fn recent_history(&mut self, ip: IpAddr, now: Instant) -> &mut History {
+        let history = self.connections.entry(ip).or_default();
+        while let Some(&time) = history.entries.front() {
             if now.duration_since(time) > Duration::from_secs(60) {
-                entries.pop_front();
+                history.entries.pop_front();
             } else {
                 break;
             }
         }
+        if history.entries.is_empty() {
+            history.burst_used = 0;
+        }
+        history
+    }
+    
+    pub fn check_and_add(&mut self, ip: IpAddr) -> bool {
+        self.check_and_add_at(ip, Instant::now())
+    }
+    
+    fn check_and_add_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let (max_per_minute, burst) = (self.max_per_minute, self.burst);
+        let history = self.recent_history(ip, now);
         
-        if entries.len() >= self.max_per_minute {
-            false
-        } else {
-            entries.push_back(now);
+        if history.entries.len() >= max_per_minute {
+            if history.burst_used >= burst {
+                *self.rejections.entry(ip).or_default() += 1;
+                return false;
+            }
+            history.burst_used += 1;
+        }
+        history.entries.push_back(now);
+        true
+    }
+    
+    /// Mode souple : la connexion est toujours acceptée et comptée ; renvoie le nombre
+    /// de connexions au-delà de la limite et de la rafale sur la dernière minute (0 si en deçà)
+    pub fn add_over_limit(&mut self, ip: IpAddr) -> usize {
+        self.add_over_limit_at(ip, Instant::now())
+    }
+    
+    fn add_over_limit_at(&mut self, ip: IpAddr, now: Instant) -> usize {
+        let (max_per_minute, burst) = (self.max_per_minute, self.burst);
+        let history = self.recent_history(ip, now);
+        if history.entries.len() >= max_per_minute && history.burst_used < burst {
+            history.burst_used += 1;
+        }
+        history.entries.push_back(now);
+        history.entries.len().saturating_sub(max_per_minute + history.burst_used)
+    }
+    
+    /// Les IP les plus refusées, par nombre de refus décroissant
+    pub fn top_rejections(&self, n: usize) -> Vec<(IpAddr, u64)> {
+        let mut top: Vec<(IpAddr, u64)> = self.rejections.iter().map(|(ip, count)| (*ip, *count)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+    
+    /// Supprime les IP sans connexion récente, avec leurs compteurs de refus
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        self.connections.retain(|_, history| {
+            history.entries.back().is_some_and(|&time| now.duration_since(time) <= Duration::from_secs(60))
+        });
+        let connections = &self.connections;
+        self.rejections.retain(|ip, _| connections.contains_key(ip));
+    }
+}
+
+/// Seau à jetons global des acceptations (--max-accepts-per-second), tous ports confondus
+///
+/// Capacité d'une seconde de débit : une courte rafale passe, un flot soutenu est écrêté.
+pub struct AcceptBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl AcceptBucket {
+    pub fn new(rate: u32) -> Self {
+        Self { rate: rate as f64, tokens: rate as f64, last: Instant::now() }
+    }
+    
+    /// Prend un jeton ; faux si la connexion doit être délestée
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
             true
+        } else {
+            false
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+    
+    #[test]
+    fn burst_then_throttle() {
+        let mut limiter = RateLimiter::new(3, 2);
+        let start = Instant::now();
+        // 3 par minute, puis 2 de rafale, puis refus
+        let admitted: Vec<bool> = (0..7).map(|i| limiter.check_and_add_at(IP, start + Duration::from_secs(i))).collect();
+        assert_eq!(admitted, [true, true, true, true, true, false, false]);
+        assert_eq!(limiter.top_rejections(10), [(IP, 2)]);
+        
+        // Abus soutenu : une place se libère à l'expiration des plus vieilles entrées, la rafale
+        // (comptée dans l'historique) ne revient pas
+        assert!(limiter.check_and_add_at(IP, start + Duration::from_secs(63)));
+        assert!(!limiter.check_and_add_at(IP, start + Duration::from_secs(63)));
+        
+        // Une minute de silence recharge la rafale
+        let quiet = start + Duration::from_secs(63 + 61);
+        let admitted: Vec<bool> = (0..6).map(|i| limiter.check_and_add_at(IP, quiet + Duration::from_secs(i))).collect();
+        assert_eq!(admitted, [true, true, true, true, true, false]);
+    }
+    
+    #[test]
+    fn soft_mode_counts_beyond_burst() {
+        let mut limiter = RateLimiter::new(3, 2);
+        let start = Instant::now();
+        let over: Vec<usize> = (0..8).map(|i| limiter.add_over_limit_at(IP, start + Duration::from_secs(i))).collect();
+        assert_eq!(over, [0, 0, 0, 0, 0, 1, 2, 3]);
+        assert!(limiter.top_rejections(10).is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter, ReadBuf};
+use tokio::sync::mpsc;
+
+const FROM_CLIENT: u8 = b'C';
+const FROM_SERVER: u8 = b'S';
+
+/// Enregistreur d'une session (--capture-raw) : un fichier par session, suite de trames
+///
+/// Trame : direction (1 octet, `C` client vers serveur, `S` serveur vers client),
+/// horodatage en microsecondes depuis l'époque Unix (u64 gros-boutiste),
+/// longueur (u32 gros-boutiste), puis les octets tels que lus ou écrits.
+#[derive(Clone)]
+pub struct RawRecorder {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl RawRecorder {
+    /// Ouvre le fichier de capture ; l'écriture se termine quand le dernier enregistreur disparaît
+    pub fn start(file: tokio::fs::File, path: PathBuf) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(file);
+            while let Some(frame) = rx.recv().await {
+                if let Err(e) = writer.write_all(&frame).await {
+                    eprintln!("[WARNING] Raw capture {:?}: {}", path, e);
+                    return;
+                }
+            }
+            if let Err(e) = writer.flush().await {
+                eprintln!("[WARNING] Raw capture {:?}: {}", path, e);
+            }
+        });
+        Self { tx }
+    }
+    
+    fn record(&self, direction: u8, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let micros = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        let mut frame = Vec::with_capacity(13 + data.len());
+        frame.push(direction);
+        frame.extend_from_slice(&micros.to_be_bytes());
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(data);
+        let _ = self.tx.send(frame);
+    }
+}
+
+/// Flux enregistrant les octets lus et écrits, avant tout traitement SMTP
+pub struct RawTap<S> {
+    inner: S,
+    recorder: Option<RawRecorder>,
+}
+
+impl<S> RawTap<S> {
+    pub fn new(inner: S, recorder: Option<RawRecorder>) -> Self {
+        Self { inner, recorder }
+    }
+    
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for RawTap<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(recorder)) = (&result, &self.recorder) {
+            recorder.record(FROM_CLIENT, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for RawTap<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(recorder)) = (&result, &self.recorder) {
+            recorder.record(FROM_SERVER, &buf[..*n]);
+        }
+        result
+    }
+    
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Livraison d'origine dont un message est la nouvelle tentative
+#[derive(Debug, Clone, Copy)]
+pub struct RetryOrigin {
+    pub session_id: u64,
+    pub message_seq: u32,
+    /// Numéro de cette tentative (2 pour la première nouvelle tentative)
+    pub attempt: u32,
+    /// Écart avec la tentative précédente
+    pub interval: Duration,
+}
+
+/// Livraison à enregistrer, une fois le message effectivement sauvegardé
+#[derive(Debug, Clone, Copy)]
+pub struct NewDelivery {
+    pub key: [u8; 32],
+    pub session_id: u64,
+    pub message_seq: u32,
+}
+
+struct Delivery {
+    session_id: u64,
+    message_seq: u32,
+    attempts: u32,
+    last_seen: Instant,
+}
+
+/// Livraisons récentes indexées par empreinte enveloppe + corps (--dedup-retries)
+pub struct RetryTracker {
+    window: Duration,
+    deliveries: HashMap<[u8; 32], Delivery>,
+}
+
+impl RetryTracker {
+    pub fn new(window: Duration) -> Self {
+        Self { window, deliveries: HashMap::new() }
+    }
+    
+    /// Origine du message s'il est une nouvelle tentative d'une livraison enregistrée dans la fenêtre
+    pub fn retry_of(&mut self, key: &[u8; 32]) -> Option<RetryOrigin> {
+        let now = Instant::now();
+        let delivery = self.deliveries.get_mut(key)?;
+        let interval = now.duration_since(delivery.last_seen);
+        if interval > self.window {
+            return None;
+        }
+        delivery.attempts += 1;
+        delivery.last_seen = now;
+        Some(RetryOrigin {
+            session_id: delivery.session_id,
+            message_seq: delivery.message_seq,
+            attempt: delivery.attempts,
+            interval,
+        })
+    }
+    
+    /// Enregistre une livraison sauvegardée : les envois identiques suivants seront des tentatives
+    pub fn record(&mut self, delivery: NewDelivery) {
+        self.deliveries.insert(delivery.key, Delivery {
+            session_id: delivery.session_id,
+            message_seq: delivery.message_seq,
+            attempts: 1,
+            last_seen: Instant::now(),
+        });
+    }
+    
+    /// Oublie les livraisons sans nouvelle tentative depuis plus d'une fenêtre
+    pub fn prune(&mut self) {
+        let window = self.window;
+        self.deliveries.retain(|_, delivery| delivery.last_seen.elapsed() <= window);
+    }
+}
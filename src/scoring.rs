@@ -0,0 +1,78 @@
+use crate::session::Signal;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Poids attribué à un signal, surchargé par `--signal-weight signal=poids`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalWeight {
+    pub signal: Signal,
+    pub weight: u32,
+}
+
+impl FromStr for SignalWeight {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, weight) = s.split_once('=')
+            .ok_or_else(|| format!("invalid signal weight '{}' (expected signal=weight)", s))?;
+        Ok(SignalWeight {
+            signal: name.trim().parse()?,
+            weight: weight.trim().parse()
+                .map_err(|_| format!("invalid weight '{}' for signal {}", weight, name))?,
+        })
+    }
+}
+
+/// Poids par défaut : les signaux propres aux robots pèsent le plus
+fn default_weight(signal: Signal) -> u32 {
+    match signal {
+        Signal::AuthRelay => 50,
+        Signal::RelayAttempt => 40,
+        Signal::FastTalker => 20,
+        Signal::RawStream => 20,
+        Signal::BodyBeforePrompt => 20,
+        Signal::PipeliningViolation => 15,
+        Signal::PostQuitData => 15,
+        Signal::OversizedArgument => 15,
+        Signal::BadHelo => 10,
+        Signal::SizeMismatch => 10,
+        Signal::ImpatientBannerGrab => 10,
+        Signal::TlsProbe => 10,
+        Signal::IgnoredStarttls => 5,
+        Signal::ImmediateDisconnect => 5,
+    }
+}
+
+/// Score de menace d'une session, calculé à partir de ses signaux
+pub struct Scorer {
+    weights: HashMap<Signal, u32>,
+}
+
+impl Scorer {
+    pub fn new(overrides: &[SignalWeight]) -> Self {
+        let mut weights: HashMap<Signal, u32> = Signal::ALL.iter()
+            .map(|signal| (*signal, default_weight(*signal)))
+            .collect();
+        for SignalWeight { signal, weight } in overrides {
+            weights.insert(*signal, *weight);
+        }
+        Self { weights }
+    }
+    
+    /// Score total et contribution de chaque signal
+    pub fn score(&self, signals: &[Signal]) -> (u32, Vec<(Signal, u32)>) {
+        let contributions: Vec<(Signal, u32)> = signals.iter()
+            .map(|signal| (*signal, self.weights.get(signal).copied().unwrap_or(0)))
+            .collect();
+        (contributions.iter().map(|(_, weight)| weight).sum(), contributions)
+    }
+}
+
+/// Contributions au format `signal:poids,...`
+pub fn format_contributions(contributions: &[(Signal, u32)]) -> String {
+    contributions.iter()
+        .map(|(signal, weight)| format!("{}:{}", signal, weight))
+        .collect::<Vec<_>>()
+        .join(",")
+}
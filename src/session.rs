@@ -1,46 +1,640 @@
+use crate::clientcert::ClientCert;
+use crate::persona::DomainPersona;
+use crate::retries::RetryOrigin;
+use crate::tlsresume::Handshake;
+use crate::utils::HeloClass;
+
+use std::collections::VecDeque;
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::sync::OwnedSemaphorePermit;
+
+// Identifiant unique des sessions depuis le démarrage
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+// Horodatages de commandes conservés par session, au-delà les suivantes sont ignorées
+const MAX_COMMAND_TIMES: usize = 256;
+
+// Lignes de commande brutes conservées par session
+const MAX_TRANSCRIPT_LINES: usize = 256;
+
+// Mécanismes AUTH distincts conservés par session, et longueur maximale d'un nom
+const MAX_AUTH_MECHANISMS: usize = 16;
+const MAX_AUTH_MECHANISM_LEN: usize = 32;
+
+// Commandes dont la mise en forme est relevée pour l'empreinte du client
+const FORMAT_VERBS: [&str; 6] = ["HELO", "EHLO", "LHLO", "MAIL", "RCPT", "AUTH"];
+
+/// Signaux comportementaux relevés pendant une session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    /// Le client a envoyé des données avant la bannière
+    FastTalker,
+    /// Commandes envoyées après un point de synchronisation sans attendre la réponse
+    PipeliningViolation,
+    /// Tentative de relais vers un domaine externe
+    RelayAttempt,
+    /// HELO absent, localhost ou malformé
+    BadHelo,
+    /// STARTTLS annoncé mais MAIL/AUTH envoyé en clair
+    IgnoredStarttls,
+    /// Données envoyées après la réponse au QUIT
+    PostQuitData,
+    /// Déconnexion sans aucune commande
+    ImmediateDisconnect,
+    /// Flux sans structure de lignes (aucun CRLF dès le départ) : sonde non-SMTP
+    RawStream,
+    /// Taille annoncée par MAIL FROM SIZE= très différente de la taille reçue
+    SizeMismatch,
+    /// Déconnexion pendant l'envoi lent de la bannière (--banner-drip)
+    ImpatientBannerGrab,
+    /// MAIL ou RCPT dépassant la longueur de ligne de commande de la RFC 5321
+    OversizedArgument,
+    /// Poignée de main TLS réussie puis déconnexion sans aucune commande SMTP
+    TlsProbe,
+    /// Corps du message envoyé avant la réponse 354 au DATA (--data-prompt-delay)
+    BodyBeforePrompt,
+    /// Relais vers un domaine externe après un AUTH réussi (compte compromis)
+    AuthRelay,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 14] = [
+        Signal::FastTalker,
+        Signal::PipeliningViolation,
+        Signal::RelayAttempt,
+        Signal::BadHelo,
+        Signal::IgnoredStarttls,
+        Signal::PostQuitData,
+        Signal::ImmediateDisconnect,
+        Signal::RawStream,
+        Signal::SizeMismatch,
+        Signal::ImpatientBannerGrab,
+        Signal::OversizedArgument,
+        Signal::TlsProbe,
+        Signal::BodyBeforePrompt,
+        Signal::AuthRelay,
+    ];
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Signal::FastTalker => "fast-talker",
+            Signal::PipeliningViolation => "pipelining-violation",
+            Signal::RelayAttempt => "relay-attempt",
+            Signal::BadHelo => "bad-helo",
+            Signal::IgnoredStarttls => "ignored-starttls",
+            Signal::PostQuitData => "post-quit-data",
+            Signal::ImmediateDisconnect => "immediate-disconnect",
+            Signal::RawStream => "raw-stream",
+            Signal::SizeMismatch => "size-mismatch",
+            Signal::ImpatientBannerGrab => "impatient-banner-grab",
+            Signal::OversizedArgument => "oversized-argument",
+            Signal::TlsProbe => "tls-probe",
+            Signal::BodyBeforePrompt => "body-before-prompt",
+            Signal::AuthRelay => "auth-relay",
+        }
+    }
+}
+
+impl FromStr for Signal {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Signal::ALL.iter()
+            .find(|signal| signal.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown signal '{}'", s))
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Fichier temporaire recevant la suite d'un DATA trop volumineux
+pub struct SpillFile {
+    pub path: PathBuf,
+    pub file: tokio::fs::File,
+    pub _slot: SpillSlot,
+}
+
+/// Place d'un fichier de débordement ouvert (--max-open-spill-files), libérée avec lui
+pub struct SpillSlot {
+    open: Arc<AtomicUsize>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl SpillSlot {
+    pub fn new(open: Arc<AtomicUsize>, permit: Option<OwnedSemaphorePermit>) -> Self {
+        open.fetch_add(1, Ordering::Relaxed);
+        Self { open, _permit: permit }
+    }
+}
+
+impl Drop for SpillSlot {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Étape d'un échange AUTH à plusieurs lignes (RFC 4954)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthExchange {
+    /// AUTH PLAIN sans réponse initiale : identifiants attendus sur une ligne
+    Plain,
+    LoginUsername,
+    LoginPassword,
+}
+
+impl AuthExchange {
+    pub fn mechanism(&self) -> &'static str {
+        match self {
+            AuthExchange::Plain => "PLAIN",
+            AuthExchange::LoginUsername | AuthExchange::LoginPassword => "LOGIN",
+        }
+    }
+}
 
 pub struct SmtpSession {
+    pub id: u64,
     pub client_addr: SocketAddr,
     pub helo: Option<String>,
+    pub helo_class: Option<HeloClass>,
     pub mail_from: Option<String>,
+    /// Taille annoncée par le paramètre SIZE= de MAIL FROM
+    pub declared_size: Option<u64>,
+    /// Identité authentifiée déclarée par le paramètre AUTH= de MAIL FROM (`<>` : aucune)
+    pub mail_auth: Option<String>,
     pub rcpt_to: Vec<String>,
-    pub data: Vec<String>,
+    /// Destinataires acceptés (--accept-all-rcpt) alors que la politique les aurait refusés
+    pub would_reject: Vec<String>,
+    /// Lignes de DATA gardées en mémoire, octets bruts sans fin de ligne
+    pub data: Vec<Vec<u8>>,
+    /// AUTH mené à terme (235) pendant la session
     pub authenticated: bool,
+    /// Échange AUTH en cours : la prochaine ligne répond au défi 334
+    pub auth_exchange: Option<AuthExchange>,
     pub tls_active: bool,
     pub starttls_enabled: bool,
+    /// STARTTLS a été annoncé dans la réponse EHLO
+    pub starttls_offered: bool,
     pub expecting_data: bool,
+    pub signals: Vec<Signal>,
+    /// Drapeau --quiet-allowlist de la connexion, levé au premier signal
+    pub quiet: Option<Arc<AtomicBool>>,
+    pub persona: Option<DomainPersona>,
+    pub relay_attempted: bool,
+    /// Taille du corps reçu (lignes jointes par CRLF), mémoire et disque confondus
+    pub data_size: usize,
+    pub data_lines: usize,
+    /// Message refusé pour dépassement de --max-data-lines
+    pub too_many_lines: bool,
+    /// Dernière ligne du corps incomplète : ligne trop longue, lue par morceaux
+    pub data_partial: bool,
+    /// CR en fin de morceau, rendu au corps s'il n'ouvre pas la fin de ligne
+    pub data_held_cr: bool,
+    pub spill: Option<SpillFile>,
+    /// Une partie du corps a été écrite dans un fichier de débordement
+    pub spilled: bool,
+    /// Débordement refusé faute de place (--max-open-spill-files) : corps gardé en mémoire
+    pub spill_deferred: bool,
+    /// Écriture du fichier de débordement en échec : la capture ne contient pas tout le corps
+    pub spill_failed: bool,
+    /// Nombre de commandes reçues sur la session (hors lignes DATA)
+    pub command_count: usize,
+    /// Verbe et instant de réception des commandes, dans l'ordre
+    pub command_times: Vec<(String, Instant)>,
+    /// Lignes de commande telles que reçues (casse et espaces d'origine, sans fin de ligne)
+    pub transcript: Vec<String>,
+    /// Instants des commandes de la dernière seconde (--max-commands-per-second)
+    pub recent_commands: VecDeque<Instant>,
+    /// Commandes retardées ou refusées par --max-commands-per-second
+    pub rate_limited_commands: u32,
+    /// Fins de ligne utilisées par le client, commandes et DATA confondues
+    pub line_endings: LineEndings,
+    /// Numéro du message courant sur la connexion (1 pour le premier DATA terminé)
+    pub message_seq: u32,
+    /// Un filtre a demandé la fermeture sans réponse
+    pub dropped: bool,
+    /// La connexion est fermée après l'envoi de la réponse courante
+    pub close_after_reply: bool,
+    /// Le message courant est une nouvelle tentative d'une livraison déjà capturée
+    pub retry_of: Option<RetryOrigin>,
+    /// Poignée de main TLS de la connexion (complète ou reprise)
+    pub tls_handshake: Option<Handshake>,
+    /// Certificat présenté par le client (--request-client-cert)
+    pub client_cert: Option<ClientCert>,
+    /// AUTH annoncé en EHLO (--auth-advertise-rate), tiré une fois par connexion
+    pub auth_advertised: Option<bool>,
+    /// Version, suite et SNI, de la poignée de main ou d'un répartiteur en amont
+    pub tls_info: Option<TlsInfo>,
+    /// Port d'écoute ayant accepté la connexion
+    pub port: u16,
+    /// Commandes AUTH reçues, énumérations comprises
+    pub auth_attempts: usize,
+    /// Dernier MAIL FROM, destinataires acceptés et octets de DATA des transactions terminées
+    pub totals: (Option<String>, usize, usize),
+    /// Fin de la connexion : quit, idle-timeout, line-too-long, command-limit, dropped,
+    /// server-closed ou disconnected
+    pub disposition: &'static str,
+    /// Mécanismes AUTH cités par le client, dans l'ordre de leur première apparition
+    pub auth_mechanisms: Vec<String>,
+    /// AUTH sans argument : énumération des mécanismes proposés
+    pub auth_enumerations: usize,
 }
 
 impl SmtpSession {
-    pub fn new(client_addr: SocketAddr, starttls_enabled: bool) -> Self {
+    /// Identifiant d'une nouvelle session, alloué dès l'acceptation pour les journaux qui la précèdent
+    pub fn next_id() -> u64 {
+        NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+    }
+    
+    pub fn new(id: u64, client_addr: SocketAddr, starttls_enabled: bool) -> Self {
         Self {
+            id,
             client_addr,
             helo: None,
+            helo_class: None,
             mail_from: None,
+            declared_size: None,
+            mail_auth: None,
             rcpt_to: Vec::new(),
+            would_reject: Vec::new(),
             data: Vec::new(),
             authenticated: false,
+            auth_exchange: None,
             tls_active: false,
             starttls_enabled,
+            starttls_offered: false,
             expecting_data: false,
+            signals: Vec::new(),
+            quiet: None,
+            persona: None,
+            relay_attempted: false,
+            data_size: 0,
+            data_lines: 0,
+            too_many_lines: false,
+            data_partial: false,
+            data_held_cr: false,
+            spill: None,
+            spilled: false,
+            spill_deferred: false,
+            spill_failed: false,
+            command_count: 0,
+            command_times: Vec::new(),
+            transcript: Vec::new(),
+            recent_commands: VecDeque::new(),
+            rate_limited_commands: 0,
+            line_endings: LineEndings::default(),
+            message_seq: 0,
+            dropped: false,
+            close_after_reply: false,
+            retry_of: None,
+            tls_handshake: None,
+            client_cert: None,
+            auth_advertised: None,
+            tls_info: None,
+            port: 0,
+            auth_attempts: 0,
+            totals: (None, 0, 0),
+            disposition: "disconnected",
+            auth_mechanisms: Vec::new(),
+            auth_enumerations: 0,
         }
     }
     
     pub fn reset(&mut self) {
+        if self.mail_from.is_some() {
+            self.totals.0 = self.mail_from.take();
+        }
+        self.totals.1 += self.rcpt_to.len();
+        self.totals.2 += self.data_size;
         self.mail_from = None;
+        self.declared_size = None;
+        self.mail_auth = None;
         self.rcpt_to.clear();
+        self.would_reject.clear();
         self.data.clear();
         self.expecting_data = false;
+        self.relay_attempted = false;
+        self.retry_of = None;
+        self.clear_data();
     }
     
+    #[allow(dead_code)]
     pub fn reset_all(&mut self) {
         self.helo = None;
+        self.helo_class = None;
         self.mail_from = None;
         self.rcpt_to.clear();
+        self.would_reject.clear();
         self.data.clear();
         self.authenticated = false;
         self.expecting_data = false;
+        self.relay_attempted = false;
+        self.clear_data();
+    }
+    
+    /// Oublie le corps reçu et supprime l'éventuel fichier de débordement
+    fn clear_data(&mut self) {
+        self.data.clear();
+        self.data_size = 0;
+        self.data_lines = 0;
+        self.too_many_lines = false;
+        self.data_partial = false;
+        self.data_held_cr = false;
+        self.spill_deferred = false;
+        self.spill_failed = false;
+        self.spilled = false;
+        if let Some(spill) = self.spill.take() {
+            let _ = std::fs::remove_file(&spill.path);
+        }
+    }
+    
+    pub fn add_signal(&mut self, signal: Signal) {
+        // Source --quiet-allowlist au comportement anormal : la session est de nouveau journalisée
+        if let Some(quiet) = self.quiet.take() {
+            quiet.store(false, Ordering::Relaxed);
+        }
+        if !self.signals.contains(&signal) {
+            self.signals.push(signal);
+        }
+    }
+    
+    /// Dernier MAIL FROM, destinataires et octets de DATA de toute la connexion,
+    /// transaction en cours comprise
+    pub fn connection_totals(&self) -> (Option<&str>, usize, usize) {
+        (self.mail_from.as_deref().or(self.totals.0.as_deref()),
+         self.totals.1 + self.rcpt_to.len(),
+         self.totals.2 + self.data_size)
+    }
+    
+    /// Note un mécanisme AUTH cité ; l'ordre de première apparition est conservé
+    pub fn record_auth_mechanism(&mut self, mechanism: &str) {
+        let mechanism: String = mechanism.to_uppercase().chars().take(MAX_AUTH_MECHANISM_LEN).collect();
+        if self.auth_mechanisms.len() < MAX_AUTH_MECHANISMS && !self.auth_mechanisms.contains(&mechanism) {
+            self.auth_mechanisms.push(mechanism);
+        }
+    }
+    
+    /// Compte une commande et note son instant de réception
+    pub fn record_command(&mut self, raw_line: &str) {
+        let line = raw_line.trim_end_matches(['\r', '\n']);
+        let verb = line.split_whitespace().next().unwrap_or("");
+        self.command_count += 1;
+        if self.command_times.len() < MAX_COMMAND_TIMES {
+            self.command_times.push((verb.to_uppercase(), Instant::now()));
+        }
+        if self.transcript.len() < MAX_TRANSCRIPT_LINES {
+            self.transcript.push(line.to_string());
+        }
+    }
+    
+    /// Mise en forme de la première occurrence de chaque commande notable,
+    /// au format `VERBE:trait+trait` (`plain` si rien de particulier)
+    pub fn command_formats(&self) -> Vec<String> {
+        let mut seen = Vec::new();
+        let mut formats = Vec::new();
+        for line in &self.transcript {
+            let verb = line.split(|c: char| c.is_ascii_whitespace() || c == ':')
+                .find(|word| !word.is_empty())
+                .unwrap_or("")
+                .to_uppercase();
+            if !FORMAT_VERBS.contains(&verb.as_str()) || seen.contains(&verb) {
+                continue;
+            }
+            let traits = command_format(line);
+            formats.push(format!("{}:{}", verb, if traits.is_empty() { "plain".to_string() } else { traits.join("+") }));
+            seen.push(verb);
+        }
+        formats
+    }
+    
+    /// Enregistre la commande courante et renvoie l'attente nécessaire pour ne pas
+    /// dépasser `max` commandes par seconde, si le débit est dépassé
+    pub fn command_rate_excess(&mut self, max: u32) -> Option<Duration> {
+        let now = Instant::now();
+        while self.recent_commands.front().is_some_and(|t| now.duration_since(*t) >= Duration::from_secs(1)) {
+            self.recent_commands.pop_front();
+        }
+        self.recent_commands.push_back(now);
+        
+        let max = max as usize;
+        if self.recent_commands.len() <= max {
+            return None;
+        }
+        // La commande située `max` rangs plus tôt doit avoir plus d'une seconde
+        let oldest = self.recent_commands[self.recent_commands.len() - 1 - max];
+        Some(Duration::from_secs(1).saturating_sub(now.duration_since(oldest)))
+    }
+    
+    /// Écarts entre commandes successives en millisecondes, étiquetés `PRÉCÉDENTE>SUIVANTE`
+    pub fn command_gaps(&self) -> Vec<(String, u128)> {
+        self.command_times.windows(2)
+            .map(|pair| (format!("{}>{}", pair[0].0, pair[1].0),
+                         pair[1].1.duration_since(pair[0].1).as_millis()))
+            .collect()
+    }
+    
+    /// Écarts minimal, médian et maximal entre commandes (millisecondes)
+    pub fn gap_summary(&self) -> Option<(u128, u128, u128)> {
+        let mut gaps: Vec<u128> = self.command_gaps().into_iter().map(|(_, gap)| gap).collect();
+        if gaps.is_empty() {
+            return None;
+        }
+        gaps.sort_unstable();
+        Some((gaps[0], gaps[gaps.len() / 2], gaps[gaps.len() - 1]))
     }
+    
+    /// Corps gardé en mémoire, lignes jointes par CRLF
+    pub fn body(&self) -> Vec<u8> {
+        self.data.join(&b"\r\n"[..])
+    }
+    
+    /// Résumé de la transaction courante, sous forme clé=valeur
+    pub fn transaction_summary(&self) -> String {
+        let signals: Vec<&str> = self.signals.iter().map(|s| s.as_str()).collect();
+        let formats = self.command_formats();
+        format!(
+            "helo={} helo_class={} mail_from={} mail_auth={} rcpt_count={} size_declared={} size={} would_reject={} relay_attempted={} gaps_ms={} tls={} format={} eol={} auth={} auth_enum={} signals={}",
+            self.helo.as_deref().unwrap_or("-"),
+            self.helo_class.map(|c| c.as_str()).unwrap_or("-"),
+            self.mail_from.as_deref().unwrap_or("-"),
+            self.mail_auth.as_deref().unwrap_or("-"),
+            self.rcpt_to.len(),
+            self.declared_size.map(|size| size.to_string()).unwrap_or_else(|| "-".to_string()),
+            self.data_size,
+            self.would_reject.len(),
+            self.relay_attempted,
+            self.gap_summary()
+                .map(|(min, median, max)| format!("{}/{}/{}", min, median, max))
+                .unwrap_or_else(|| "-".to_string()),
+            match (&self.tls_handshake, &self.tls_info) {
+                (Some(handshake), _) => handshake.kind(),
+                (None, Some(info)) => info.source,
+                (None, None) => "-",
+            },
+            if formats.is_empty() { "-".to_string() } else { formats.join(",") },
+            self.line_endings.class(),
+            if self.auth_mechanisms.is_empty() { "-".to_string() } else { self.auth_mechanisms.join(",") },
+            self.auth_enumerations,
+            if signals.is_empty() { "-".to_string() } else { signals.join(",") }
+        )
+    }
+}
+
+/// Paramètres TLS de la connexion et leur provenance
+#[derive(Debug, Default, Clone)]
+pub struct TlsInfo {
+    /// `handshake` (poignée de main du honeypot) ou `proxy-tlv` (TLS terminé en amont, PROXY v2)
+    pub source: &'static str,
+    pub version: Option<String>,
+    pub cipher: Option<String>,
+    pub sni: Option<String>,
+}
+
+impl fmt::Display for TlsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "source={} version={} cipher={} sni={}", self.source,
+               self.version.as_deref().unwrap_or("-"),
+               self.cipher.as_deref().unwrap_or("-"),
+               self.sni.as_deref().unwrap_or("-"))
+    }
+}
+
+/// Décompte des fins de ligne reçues sur la session
+///
+/// Un MTA légitime n'envoie que des CRLF ; LF seul, CR seul ou un mélange trahit
+/// le plus souvent un robot écrit à la main.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LineEndings {
+    pub crlf: u32,
+    pub bare_lf: u32,
+    pub bare_cr: u32,
 }
 
+impl LineEndings {
+    /// Relève la fin d'une ligne lue jusqu'au LF (terminateur compris)
+    pub fn record(&mut self, raw_line: &str) {
+        let Some(content) = raw_line.strip_suffix('\n') else {
+            return;
+        };
+        match content.strip_suffix('\r') {
+            Some(content) => {
+                self.crlf += 1;
+                self.bare_cr += content.matches('\r').count() as u32;
+            }
+            None => {
+                self.bare_lf += 1;
+                self.bare_cr += content.matches('\r').count() as u32;
+            }
+        }
+    }
+    
+    /// Classement de la session : `crlf`, `bare-lf`, `mixed` ou `none`
+    ///
+    /// Les lignes ne se terminent qu'au LF : un CR seul est toujours pris dans une ligne
+    /// terminée autrement, d'où `mixed` dès qu'il apparaît.
+    pub fn class(&self) -> &'static str {
+        match (self.crlf > 0, self.bare_lf > 0, self.bare_cr > 0) {
+            (false, false, _) => "none",
+            (true, false, false) => "crlf",
+            (false, true, false) => "bare-lf",
+            _ => "mixed",
+        }
+    }
+    
+    /// Vrai si le client s'est écarté du CRLF strict
+    pub fn is_irregular(&self) -> bool {
+        self.bare_lf > 0 || self.bare_cr > 0
+    }
+}
+
+/// Particularités de mise en forme d'une ligne de commande : casse, espaces, deux-points
+///
+/// La ligne est celle reçue, sans fin de ligne ; une ligne conforme en majuscules ne
+/// relève que la forme des deux-points de MAIL et RCPT.
+pub fn command_format(line: &str) -> Vec<&'static str> {
+    let mut traits = Vec::new();
+    let verb = line.split(|c: char| c.is_ascii_whitespace() || c == ':')
+        .find(|word| !word.is_empty())
+        .unwrap_or("");
+    let has_lower = verb.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = verb.chars().any(|c| c.is_ascii_uppercase());
+    match (has_lower, has_upper) {
+        (true, false) => traits.push("lowercase-verb"),
+        (true, true) => traits.push("mixed-case-verb"),
+        _ => {}
+    }
+    
+    if line.starts_with(char::is_whitespace) {
+        traits.push("leading-whitespace");
+    }
+    if line.ends_with(char::is_whitespace) {
+        traits.push("trailing-whitespace");
+    }
+    if line.contains('\t') {
+        traits.push("tab");
+    }
+    if line.trim().contains("  ") {
+        traits.push("extra-whitespace");
+    }
+    
+    if matches!(verb.to_uppercase().as_str(), "MAIL" | "RCPT") {
+        let keyword = line.trim_start()[verb.len()..].trim_start();
+        let keyword = keyword.split(|c: char| c.is_ascii_whitespace() || c == ':').next().unwrap_or("");
+        if keyword.chars().any(|c| c.is_ascii_lowercase()) {
+            traits.push("lowercase-keyword");
+        }
+        if let Some((before, after)) = line.split_once(':') {
+            if before.ends_with(char::is_whitespace) {
+                traits.push("space-before-colon");
+            }
+            traits.push(if after.starts_with(char::is_whitespace) { "space-after-colon" } else { "no-space-after-colon" });
+            if !after.trim_start().starts_with('<') {
+                traits.push("no-angle-brackets");
+            }
+        } else {
+            traits.push("no-colon");
+        }
+    }
+    traits
+}
+
+impl Drop for SmtpSession {
+    fn drop(&mut self) {
+        self.clear_data();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn class_of(lines: &[&str]) -> &'static str {
+        let mut endings = LineEndings::default();
+        for line in lines {
+            endings.record(line);
+        }
+        endings.class()
+    }
+    
+    #[test]
+    fn line_ending_classes() {
+        assert_eq!(class_of(&[]), "none");
+        assert_eq!(class_of(&["EHLO a\r\n", "QUIT\r\n"]), "crlf");
+        assert_eq!(class_of(&["EHLO a\n", "QUIT\n"]), "bare-lf");
+        assert_eq!(class_of(&["EHLO a\r\n", "QUIT\n"]), "mixed");
+        // CR seul au milieu d'une ligne : relevé, la session est mixte
+        assert_eq!(class_of(&["EHLO a\rNOOP\r\n"]), "mixed");
+        assert_eq!(class_of(&["EHLO a\rNOOP\n"]), "mixed");
+    }
+}
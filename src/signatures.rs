@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use regex::bytes::RegexSet;
+
+/// Octets de la fin d'un bloc rejoués avec le suivant : une signature plus courte que ce
+/// recouvrement est trouvée même à cheval sur deux blocs
+const SCAN_OVERLAP: usize = 4096;
+
+/// Signatures intégrées : charges de test connues des antispam et antivirus
+const BUILTIN_SIGNATURES: [(&str, &str); 2] = [
+    ("gtube", r"XJS\*C4JDBQADN1\.NSBN3\*2IDNEN\*GTUBE-STANDARD-ANTI-UBE-TEST-EMAIL\*C\.34X"),
+    ("eicar", r"X5O!P%@AP\[4\\PZX54\(P\^\)7CC\)7\}\$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!\$H\+H\*"),
+];
+
+/// Signatures de corps de message, compilées une seule fois au démarrage
+pub struct BodySignatures {
+    names: Vec<String>,
+    set: RegexSet,
+}
+
+impl BodySignatures {
+    /// Signatures intégrées suivies des expressions --body-signature, nommées custom-1, custom-2...
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut names: Vec<String> = BUILTIN_SIGNATURES.iter().map(|(name, _)| name.to_string()).collect();
+        let mut regexes: Vec<&str> = BUILTIN_SIGNATURES.iter().map(|(_, regex)| *regex).collect();
+        for (index, pattern) in patterns.iter().enumerate() {
+            regex::Regex::new(pattern)
+                .with_context(|| format!("Invalid --body-signature: {}", pattern))?;
+            names.push(format!("custom-{}", index + 1));
+            regexes.push(pattern);
+        }
+        Ok(Self { set: RegexSet::new(regexes)?, names })
+    }
+    
+    /// Recherche sur un corps lu par blocs (mémoire puis fichier de débordement)
+    pub fn scan(&self) -> SignatureScan<'_> {
+        SignatureScan { signatures: self, found: vec![false; self.names.len()], tail: Vec::new() }
+    }
+}
+
+/// Recherche en cours : chaque bloc est examiné précédé de la fin du bloc précédent
+pub struct SignatureScan<'a> {
+    signatures: &'a BodySignatures,
+    found: Vec<bool>,
+    tail: Vec<u8>,
+}
+
+impl<'a> SignatureScan<'a> {
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if chunk.is_empty() {
+            return;
+        }
+        self.tail.extend_from_slice(chunk);
+        for index in self.signatures.set.matches(&self.tail).iter() {
+            self.found[index] = true;
+        }
+        let keep = self.tail.len().min(SCAN_OVERLAP);
+        self.tail.drain(..self.tail.len() - keep);
+    }
+    
+    /// Noms des signatures présentes dans le corps
+    pub fn finish(self) -> Vec<&'a str> {
+        let signatures = self.signatures;
+        self.found.iter().enumerate()
+            .filter(|(_, found)| **found)
+            .map(|(index, _)| signatures.names[index].as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn signature_across_chunks_is_found() {
+        let signatures = BodySignatures::new(&["SPLIT-ME-[0-9]+".to_string()]).unwrap();
+        let mut scan = signatures.scan();
+        scan.feed(&vec![b'a'; 10_000]);
+        scan.feed(b"xx SPLIT-");
+        scan.feed(b"ME-42 yy");
+        scan.feed(&vec![b'b'; 10_000]);
+        assert_eq!(scan.finish(), ["custom-1"]);
+        
+        let mut scan = signatures.scan();
+        scan.feed(b"nothing here");
+        assert!(scan.finish().is_empty());
+    }
+}
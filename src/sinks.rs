@@ -0,0 +1,243 @@
+use crate::events::{self, Event, EventSink};
+use crate::utils::{self, FileModes};
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time;
+
+// Au-delà, les événements sont abandonnés plutôt que de ralentir les sessions
+const SINK_QUEUE_SIZE: usize = 4096;
+
+// Délais d'un POST : un collecteur bloqué ne doit pas immobiliser la tâche de la sortie
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HTTP_RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Découpe une URL http:// en (hôte:port, chemin)
+fn parse_http_url(url: &str) -> Result<(String, String)> {
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| anyhow::anyhow!("Only http:// URLs are supported: {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(anyhow::anyhow!("Missing host in URL: {}", url));
+    }
+    let authority = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    Ok((authority, path.to_string()))
+}
+
+/// Requête HTTP/1.1 POST minimale, sans dépendance supplémentaire
+pub async fn http_post(url: &str, content_type: &str, body: &str) -> Result<()> {
+    let (authority, path) = parse_http_url(url)?;
+    let mut stream = time::timeout(HTTP_CONNECT_TIMEOUT, TcpStream::connect(&authority)).await
+        .map_err(|_| anyhow::anyhow!("Timed out connecting to {}", authority))?
+        .with_context(|| format!("Failed to connect to {}", authority))?;
+    
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, authority, content_type, body.len(), body
+    );
+    let mut response = Vec::new();
+    time::timeout(HTTP_RESPONSE_TIMEOUT, async {
+        stream.write_all(request.as_bytes()).await?;
+        stream.read_to_end(&mut response).await
+    }).await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for {}", authority))??;
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or("");
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(anyhow::anyhow!("HTTP POST to {} failed: {}", url, status_line)),
+    }
+}
+
+/// Sortie CEF : POST vers une URL http:// ou ajout à un fichier
+pub struct CefSink {
+    tx: mpsc::Sender<String>,
+    /// Envois perdus : POST en échec ou hors délai, écriture de fichier en échec
+    failures: Arc<AtomicU64>,
+}
+
+impl CefSink {
+    pub fn new(target: &str, modes: FileModes) -> Result<Self> {
+        let (tx, mut rx) = mpsc::channel::<String>(SINK_QUEUE_SIZE);
+        let failures = Arc::new(AtomicU64::new(0));
+        
+        if target.starts_with("http://") {
+            parse_http_url(target)?;
+            let url = target.to_string();
+            let failures = failures.clone();
+            tokio::spawn(async move {
+                while let Some(line) = rx.recv().await {
+                    if let Err(e) = http_post(&url, "text/plain", &line).await {
+                        failures.fetch_add(1, Ordering::Relaxed);
+                        eprintln!("[WARNING] CEF sink: {}", e);
+                    }
+                }
+            });
+        } else {
+            let path = target.to_string();
+            let file = modes.open_options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open CEF output: {}", path))?;
+            let mut file = tokio::fs::File::from_std(file);
+            let failures = failures.clone();
+            tokio::spawn(async move {
+                while let Some(line) = rx.recv().await {
+                    if let Err(e) = file.write_all(format!("{}\n", line).as_bytes()).await {
+                        failures.fetch_add(1, Ordering::Relaxed);
+                        eprintln!("[WARNING] CEF sink: failed to write {}: {}", path, e);
+                    }
+                }
+            });
+        }
+        
+        Ok(Self { tx, failures })
+    }
+    
+    /// Envois perdus depuis le démarrage
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+impl EventSink for CefSink {
+    fn send(&self, event: &Event) {
+        let _ = self.tx.try_send(events::format_cef(event));
+    }
+}
+
+/// Derniers événements gardés en mémoire (--recent-events), lus par la commande de contrôle `recent`
+pub struct RecentEvents {
+    capacity: usize,
+    /// Objets JSON, du plus ancien au plus récent
+    entries: std::sync::Mutex<VecDeque<String>>,
+}
+
+impl RecentEvents {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: std::sync::Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+    
+    /// Les `count` derniers événements (tous par défaut), du plus ancien au plus récent, en tableau JSON
+    pub fn dump(&self, count: Option<usize>) -> String {
+        let entries = self.entries.lock().unwrap();
+        let skip = entries.len().saturating_sub(count.unwrap_or(entries.len()));
+        let items: Vec<&str> = entries.iter().skip(skip).map(String::as_str).collect();
+        format!("[{}]", items.join(","))
+    }
+}
+
+impl EventSink for RecentEvents {
+    fn send(&self, event: &Event) {
+        let entry = format!("{{\"timestamp\":{},\"type\":{},\"ip\":{},\"message\":{}}}",
+                            utils::json_string(&event.timestamp.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
+                            utils::json_string(event.kind.as_str()),
+                            utils::json_string(&event.client_addr.ip().to_string()),
+                            utils::json_string(&event.message));
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+/// Source sous laquelle les événements sont inscrits dans le journal Windows
+pub const EVENT_SOURCE: &str = "smtp-honeypot";
+
+/// Texte d'un événement pour le journal Windows : message puis un champ `clé=valeur` par ligne
+fn event_log_text(event: &Event) -> String {
+    let mut text = format!("{} {}", event.client_addr, event.message);
+    text.push_str(&format!("\r\ntype={}", event.kind.as_str()));
+    for (key, value) in &event.fields {
+        text.push_str(&format!("\r\n{}={}", key, value));
+    }
+    text
+}
+
+/// Sortie vers le journal d'événements Windows (journal Application, source `smtp-honeypot`)
+///
+/// Aucune DLL de messages n'est enregistrée : l'Observateur d'événements signale une
+/// description introuvable, mais affiche le texte de l'événement à la suite.
+pub struct EventLogSink {
+    tx: mpsc::Sender<(events::EventKind, String)>,
+}
+
+#[cfg(windows)]
+impl EventLogSink {
+    pub fn new() -> Result<Self> {
+        use windows_sys::Win32::System::EventLog::{
+            DeregisterEventSource, RegisterEventSourceW, ReportEventW,
+            EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+        };
+        
+        let source: Vec<u16> = EVENT_SOURCE.encode_utf16().chain(std::iter::once(0)).collect();
+        // SAFETY : `source` est une chaîne UTF-16 terminée par un zéro, valide pendant l'appel
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(anyhow::anyhow!("Failed to register event source {}: {}",
+                                       EVENT_SOURCE, std::io::Error::last_os_error()));
+        }
+        // Le handle n'est utilisé que par le thread d'écriture
+        let handle = handle as usize;
+        
+        let (tx, mut rx) = mpsc::channel::<(events::EventKind, String)>(SINK_QUEUE_SIZE);
+        // ReportEventW est bloquant : un thread dédié plutôt qu'une tâche tokio
+        std::thread::spawn(move || {
+            let handle = handle as windows_sys::Win32::Foundation::HANDLE;
+            while let Some((kind, text)) = rx.blocking_recv() {
+                let kind = match kind {
+                    events::EventKind::Alert => EVENTLOG_WARNING_TYPE,
+                    _ => EVENTLOG_INFORMATION_TYPE,
+                };
+                let text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+                let strings = [text.as_ptr()];
+                // SAFETY : handle ouvert ci-dessus, une chaîne terminée par un zéro, pas de données brutes
+                let ok = unsafe {
+                    ReportEventW(handle, kind, 0, 1, std::ptr::null_mut(), 1, 0,
+                                 strings.as_ptr(), std::ptr::null())
+                };
+                if ok == 0 {
+                    eprintln!("[WARNING] Windows Event Log sink: {}", std::io::Error::last_os_error());
+                }
+            }
+            // SAFETY : plus aucun appel n'utilise le handle
+            unsafe { DeregisterEventSource(handle) };
+        });
+        
+        Ok(Self { tx, failures })
+    }
+    
+    /// Envois perdus depuis le démarrage
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(not(windows))]
+impl EventLogSink {
+    pub fn new() -> Result<Self> {
+        Err(anyhow::anyhow!("--windows-event-log is only supported on Windows"))
+    }
+}
+
+impl EventSink for EventLogSink {
+    fn send(&self, event: &Event) {
+        let _ = self.tx.try_send((event.kind, event_log_text(event)));
+    }
+}
@@ -0,0 +1,92 @@
+use tokio::net::TcpStream;
+
+/// Métadonnées TCP d'une connexion acceptée, pour l'empreinte passive du système client
+///
+/// Champs renseignés selon la plateforme :
+/// - Linux : MSS, facteur d'échelle de fenêtre annoncé par le client, options du SYN
+///   (timestamps, SACK, window scaling, ECN) et RTT, via `TCP_INFO`.
+/// - Autres plateformes : rien, tout est "unknown".
+///
+/// Le TTL du SYN reçu n'est exposé par aucune plateforme sur une socket TCP acceptée :
+/// il reste "unknown".
+#[derive(Debug, Default, Clone)]
+pub struct TcpMetadata {
+    pub mss: Option<u32>,
+    pub window_scale: Option<u8>,
+    pub options: Option<Vec<&'static str>>,
+    pub rtt_us: Option<u32>,
+}
+
+impl TcpMetadata {
+    /// Estimation du système d'exploitation à partir des options TCP (au mieux)
+    pub fn os_guess(&self) -> &'static str {
+        let (Some(options), Some(scale)) = (&self.options, self.window_scale) else {
+            return "unknown";
+        };
+        let timestamps = options.contains(&"timestamps");
+        match (timestamps, scale) {
+            (false, 8) => "windows",
+            (true, 7..=10) => "linux",
+            (true, 5 | 6) => "macos/bsd",
+            _ => "unknown",
+        }
+    }
+    
+    pub fn options_str(&self) -> String {
+        match &self.options {
+            Some(options) if !options.is_empty() => options.join(","),
+            Some(_) => "none".to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+fn or_unknown<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "unknown".to_string())
+}
+
+impl std::fmt::Display for TcpMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ttl=unknown mss={} wscale={} options={} rtt_us={} os_guess={}",
+               or_unknown(self.mss), or_unknown(self.window_scale), self.options_str(),
+               or_unknown(self.rtt_us), self.os_guess())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn tcp_metadata(stream: &TcpStream) -> TcpMetadata {
+    use std::os::unix::io::AsRawFd;
+    
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    // SAFETY : `info` est un tampon valide de `len` octets pour toute la durée de l'appel
+    let result = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_INFO,
+                         &mut info as *mut libc::tcp_info as *mut libc::c_void, &mut len)
+    };
+    if result != 0 {
+        return TcpMetadata::default();
+    }
+    
+    // Options négociées à partir du SYN du client (TCPI_OPT_*)
+    let flags: [(u8, &'static str); 4] = [(1, "timestamps"), (2, "sack"), (4, "wscale"), (8, "ecn")];
+    let options: Vec<&'static str> = flags.iter()
+        .filter(|(bit, _)| info.tcpi_options & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    // Échelle annoncée par le client : 4 bits de poids faible (tcpi_snd_wscale)
+    let window_scale = if options.contains(&"wscale") { info.tcpi_snd_rcv_wscale & 0x0f } else { 0 };
+    
+    TcpMetadata {
+        // MSS d'émission : borné par le MSS annoncé dans le SYN du client
+        mss: Some(info.tcpi_snd_mss),
+        window_scale: Some(window_scale),
+        options: Some(options),
+        rtt_us: Some(info.tcpi_rtt),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn tcp_metadata(_stream: &TcpStream) -> TcpMetadata {
+    TcpMetadata::default()
+}
@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::Result;
+use openssl::sha::Sha256;
+use rustls::server::{ProducesTickets, ServerSessionMemoryCache, StoresServerSessions};
+use rustls::ServerConfig;
+
+use crate::utils;
+
+/// Nombre de sessions TLS conservées pour la reprise par identifiant de session
+const SESSION_CACHE_SIZE: usize = 1024;
+
+tokio::task_local! {
+    static HANDSHAKE: RefCell<Handshake>;
+}
+
+/// Observation d'une poignée de main TLS : complète ou reprise d'une session antérieure
+///
+/// Les identifiants sont un condensé court du ticket ou de l'identifiant de session :
+/// l'identifiant émis lors d'une poignée complète est celui présenté à la reprise.
+#[derive(Debug, Default, Clone)]
+pub struct Handshake {
+    /// Identifiant du ticket ou de la session repris
+    pub resumed: Option<String>,
+    /// Identifiant du dernier ticket ou de la dernière session émis au client
+    pub issued: Option<String>,
+}
+
+impl Handshake {
+    pub fn kind(&self) -> &'static str {
+        if self.resumed.is_some() { "resumed" } else { "full" }
+    }
+}
+
+impl std::fmt::Display for Handshake {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind())?;
+        if let Some(id) = &self.resumed {
+            write!(f, " id={}", id)?;
+        }
+        if let Some(id) = &self.issued {
+            write!(f, " issued={}", id)?;
+        }
+        Ok(())
+    }
+}
+
+fn short_id(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    utils::hex(&hasher.finish()[..8])
+}
+
+fn record(update: impl FnOnce(&mut Handshake)) {
+    // Hors d'une poignée observée (aucun contexte de tâche), rien à enregistrer
+    let _ = HANDSHAKE.try_with(|handshake| update(&mut handshake.borrow_mut()));
+}
+
+/// Cache de sessions (reprise par identifiant) qui note les succès de reprise
+struct ObservedStore {
+    inner: Arc<ServerSessionMemoryCache>,
+}
+
+impl StoresServerSessions for ObservedStore {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let id = short_id(&key);
+        let stored = self.inner.put(key, value);
+        // Un ticket émis sur la même poignée prime : c'est lui que le client présentera
+        if stored {
+            record(|handshake| {
+                handshake.issued.get_or_insert(id);
+            });
+        }
+        stored
+    }
+    
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.inner.get(key);
+        if value.is_some() {
+            record(|handshake| handshake.resumed = Some(short_id(key)));
+        }
+        value
+    }
+    
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.inner.take(key);
+        if value.is_some() {
+            record(|handshake| handshake.resumed = Some(short_id(key)));
+        }
+        value
+    }
+    
+    fn can_cache(&self) -> bool {
+        self.inner.can_cache()
+    }
+}
+
+/// Émetteur de tickets de session qui note les tickets émis et ceux acceptés
+struct ObservedTicketer {
+    inner: Arc<dyn ProducesTickets>,
+}
+
+impl ProducesTickets for ObservedTicketer {
+    fn enabled(&self) -> bool {
+        self.inner.enabled()
+    }
+    
+    fn lifetime(&self) -> u32 {
+        self.inner.lifetime()
+    }
+    
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let ticket = self.inner.encrypt(plain)?;
+        let id = short_id(&ticket);
+        record(|handshake| handshake.issued = Some(id));
+        Some(ticket)
+    }
+    
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        let plain = self.inner.decrypt(cipher)?;
+        record(|handshake| handshake.resumed = Some(short_id(cipher)));
+        Some(plain)
+    }
+}
+
+/// Active les tickets de session et la reprise par identifiant, avec observation
+pub fn enable(config: &mut ServerConfig) -> Result<()> {
+    config.session_storage = Arc::new(ObservedStore {
+        inner: ServerSessionMemoryCache::new(SESSION_CACHE_SIZE),
+    });
+    config.ticketer = Arc::new(ObservedTicketer {
+        inner: rustls::Ticketer::new()
+            .map_err(|e| anyhow::anyhow!("Failed to create TLS session ticketer: {}", e))?,
+    });
+    Ok(())
+}
+
+/// Exécute la poignée de main en relevant la reprise de session éventuelle
+///
+/// La poignée est construite dans le contexte observé : rustls traite le ClientHello
+/// dès la création du futur, avant sa première scrutation.
+pub async fn observe<F: Future>(handshake: impl FnOnce() -> F) -> (F::Output, Handshake) {
+    HANDSHAKE.scope(RefCell::new(Handshake::default()), async move {
+        let output = handshake().await;
+        (output, HANDSHAKE.with(|observed| observed.take()))
+    }).await
+}
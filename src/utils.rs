@@ -1,10 +1,15 @@
 use chrono::Local;
-use std::fs::{File, OpenOptions};
-use std::io::{Write, BufWriter};
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::events::{Event, EventKind, EventSink, Route, SinkName};
 
 /// Filtre pour ne garder que les caractères ASCII imprimables et les espaces blancs
 pub fn filter_printable_chars(input: &str) -> String {
@@ -19,19 +24,6 @@ pub fn filter_printable_chars(input: &str) -> String {
         .collect()
 }
 
-/// Remplace les caractères non imprimables par �
-pub fn filter_safe_display(input: &str) -> String {
-    input.chars()
-        .map(|c| {
-            if c.is_ascii_graphic() || c.is_ascii_whitespace() || c == '\n' || c == '\r' {
-                c
-            } else {
-                '�'
-            }
-        })
-        .collect()
-}
-
 /// Convertit les caractères non imprimables en séquences d'échappement
 pub fn safe_log_string(input: &str) -> String {
     let mut result = String::with_capacity(input.len());
@@ -52,61 +44,955 @@ pub fn safe_log_string(input: &str) -> String {
     result
 }
 
+/// Texte d'une suite d'octets : UTF-8 valide conservé, octets invalides en `\xNN`
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        result.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            result.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    result
+}
+
+/// Gravité d'un événement, utilisée pour l'échantillonnage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Bruit de connexion (ouverture/fermeture), échantillonnable
+    Low,
+    /// Tout le reste : captures, AUTH, alertes... toujours journalisé
+    Normal,
+}
+
+/// Échantillonnage 1 sur N des événements de faible gravité, par IP
+///
+/// La décision est prise par connexion, au premier événement : la fermeture d'une connexion
+/// est journalisée si et seulement si son ouverture l'a été.
+struct LogSampler {
+    rate: u64,
+    seen: std::sync::Mutex<HashMap<IpAddr, u64>>,
+    /// Décision des connexions ouvertes
+    connections: std::sync::Mutex<HashMap<SocketAddr, bool>>,
+    suppressed: AtomicU64,
+}
+
+impl LogSampler {
+    // Borne la mémoire lors d'un scan massif
+    const MAX_TRACKED_IPS: usize = 100_000;
+    
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            seen: std::sync::Mutex::new(HashMap::new()),
+            connections: std::sync::Mutex::new(HashMap::new()),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+    
+    /// `closing` : dernier événement de la connexion, sa décision est oubliée
+    fn should_log(&self, client_addr: &SocketAddr, closing: bool) -> bool {
+        let mut connections = self.connections.lock().unwrap();
+        let keep = match connections.get(client_addr) {
+            Some(&keep) => keep,
+            None => {
+                let keep = self.next_for_ip(client_addr.ip());
+                if !closing {
+                    if connections.len() >= Self::MAX_TRACKED_IPS {
+                        connections.clear();
+                    }
+                    connections.insert(*client_addr, keep);
+                }
+                keep
+            }
+        };
+        if closing {
+            connections.remove(client_addr);
+        }
+        if !keep {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+        }
+        keep
+    }
+    
+    fn next_for_ip(&self, ip: IpAddr) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() >= Self::MAX_TRACKED_IPS && !seen.contains_key(&ip) {
+            seen.clear();
+        }
+        let count = seen.entry(ip).or_insert(0);
+        // La première connexion d'une IP est toujours journalisée
+        let keep = (*count).is_multiple_of(self.rate);
+        *count += 1;
+        keep
+    }
+}
+
+/// Regroupement des alertes identiques (même type, même IP) sur une fenêtre (--alert-window)
+///
+/// La première alerte de la fenêtre est émise ; les suivantes sont comptées puis résumées
+/// en une seule alerte à l'expiration de la fenêtre.
+struct AlertCoalescer {
+    window: Duration,
+    /// Nombre de couples (type, IP) suivis ; borne la mémoire lors d'un scan massif
+    capacity: usize,
+    recent: std::sync::Mutex<HashMap<(String, IpAddr), CoalescedAlert>>,
+}
+
+struct CoalescedAlert {
+    started: Instant,
+    client_addr: SocketAddr,
+    suppressed: u64,
+}
+
+impl AlertCoalescer {
+    const MAX_TRACKED_ALERTS: usize = 100_000;
+    
+    fn new(window: Duration, capacity: usize) -> Self {
+        Self { window, capacity, recent: std::sync::Mutex::new(HashMap::new()) }
+    }
+    
+    /// Indique si l'alerte doit être émise, avec les résumés dus (fenêtre précédente, entrées oubliées)
+    fn admit(&self, event: &Event) -> (bool, Vec<Event>) {
+        let alert = alert_type(event);
+        let key = (alert.clone(), event.client_addr.ip());
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap();
+        
+        if let Some(entry) = recent.get_mut(&key) {
+            if now.duration_since(entry.started) < self.window {
+                entry.suppressed += 1;
+                return (false, Vec::new());
+            }
+            let summary = self.summary(&alert, entry);
+            entry.started = now;
+            entry.suppressed = 0;
+            return (true, summary.into_iter().collect());
+        }
+        
+        // Plein : fenêtres expirées d'abord, puis la plus ancienne ; leurs comptes sont résumés, pas perdus
+        let mut summaries = Vec::new();
+        if recent.len() >= self.capacity {
+            summaries = self.expire(&mut recent, now);
+        }
+        if recent.len() >= self.capacity {
+            let oldest = recent.iter().min_by_key(|(_, entry)| entry.started).map(|(key, _)| key.clone());
+            if let Some((alert, entry)) = oldest.and_then(|key| recent.remove_entry(&key)) {
+                summaries.extend(self.summary(&alert.0, &entry));
+            }
+        }
+        recent.insert(key, CoalescedAlert { started: now, client_addr: event.client_addr, suppressed: 0 });
+        (true, summaries)
+    }
+    
+    /// Retire les fenêtres expirées et renvoie les résumés des alertes regroupées
+    fn flush(&self) -> Vec<Event> {
+        self.expire(&mut self.recent.lock().unwrap(), Instant::now())
+    }
+    
+    fn expire(&self, recent: &mut HashMap<(String, IpAddr), CoalescedAlert>, now: Instant) -> Vec<Event> {
+        let mut summaries = Vec::new();
+        recent.retain(|(alert, _), entry| {
+            if now.duration_since(entry.started) < self.window {
+                return true;
+            }
+            summaries.extend(self.summary(alert, entry));
+            false
+        });
+        summaries
+    }
+    
+    fn summary(&self, alert: &str, entry: &CoalescedAlert) -> Option<Event> {
+        if entry.suppressed == 0 {
+            return None;
+        }
+        let total = entry.suppressed + 1;
+        Some(Event::new(EventKind::Alert, entry.client_addr,
+                        format!("ALERT: {} {} alerts from {} in the last {}s ({} coalesced)",
+                                total, alert, entry.client_addr.ip(), self.window.as_secs(), entry.suppressed))
+            .with("alert", alert)
+            .with("count", total)
+            .with("coalesced", entry.suppressed)
+            .with("window_s", self.window.as_secs()))
+    }
+}
+
+/// Type d'une alerte : son champ `alert`
+fn alert_type(event: &Event) -> String {
+    event.fields.iter()
+        .find(|(key, _)| *key == "alert")
+        .map(|(_, value)| value.clone())
+        .unwrap_or_else(|| "alert".to_string())
+}
+
+/// Classification de l'argument HELO/EHLO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeloClass {
+    ValidFqdn,
+    IpLiteral,
+    Missing,
+    Localhost,
+    Malformed,
+}
+
+impl HeloClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeloClass::ValidFqdn => "valid-fqdn",
+            HeloClass::IpLiteral => "ip-literal",
+            HeloClass::Missing => "missing",
+            HeloClass::Localhost => "localhost",
+            HeloClass::Malformed => "malformed",
+        }
+    }
+    
+    /// Catégories que les MTA légitimes ne produisent pratiquement jamais
+    pub fn is_bad(&self) -> bool {
+        matches!(self, HeloClass::Missing | HeloClass::Localhost | HeloClass::Malformed)
+    }
+}
+
+/// Classe l'argument HELO : FQDN valide, littéral IP [x.x.x.x], absent, localhost ou malformé
+pub fn classify_helo(arg: Option<&str>) -> HeloClass {
+    let arg = match arg {
+        Some(a) if !a.is_empty() => a,
+        _ => return HeloClass::Missing,
+    };
+    
+    if let Some(inner) = arg.strip_prefix('[').and_then(|a| a.strip_suffix(']')) {
+        let inner = inner.strip_prefix("IPv6:").unwrap_or(inner);
+        return if inner.parse::<IpAddr>().is_ok() {
+            HeloClass::IpLiteral
+        } else {
+            HeloClass::Malformed
+        };
+    }
+    
+    let name = arg.trim_end_matches('.').to_ascii_lowercase();
+    if name == "localhost" || name.starts_with("localhost.") || name.ends_with(".localdomain") {
+        return HeloClass::Localhost;
+    }
+    
+    let labels: Vec<&str> = name.split('.').collect();
+    let labels_ok = labels.iter().all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    });
+    // Une IP sans crochets ou un nom sans point n'est pas un FQDN
+    let tld_alpha = labels.last().is_some_and(|tld| tld.chars().any(|c| c.is_ascii_alphabetic()));
+    
+    if labels.len() >= 2 && labels_ok && tld_alpha && name.len() <= 253 {
+        HeloClass::ValidFqdn
+    } else {
+        HeloClass::Malformed
+    }
+}
+
+/// Décode une réponse SASL en base64 ("=" signifie une réponse vide), None si invalide
+pub fn decode_sasl_response(input: &str) -> Option<String> {
+    if input == "=" {
+        return Some(String::new());
+    }
+    openssl::base64::decode_block(input)
+        .ok()
+        .map(|bytes| safe_log_string(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Chemin et paramètres ESMTP de l'argument de MAIL FROM ou RCPT TO, après le deux-points
+///
+/// Le chemin peut être entre chevrons ou nu, précédé d'espaces ; suivent les paramètres
+/// `CLÉ=valeur` (ou `CLÉ` seul), clés en majuscules.
+pub fn parse_path_params(arg: &str) -> (String, Vec<(String, String)>) {
+    let arg = arg.trim_start();
+    let (path, rest) = match arg.strip_prefix('<') {
+        Some(inner) => match inner.find('>') {
+            Some(end) => (&inner[..end], &inner[end + 1..]),
+            None => inner.split_once(char::is_whitespace).unwrap_or((inner, "")),
+        },
+        None => arg.split_once(char::is_whitespace).unwrap_or((arg, "")),
+    };
+    let params = rest.split_whitespace()
+        .map(|param| match param.split_once('=') {
+            Some((key, value)) => (key.to_uppercase(), value.to_string()),
+            None => (param.to_uppercase(), String::new()),
+        })
+        .collect();
+    (path.to_string(), params)
+}
+
+/// Décode une valeur xtext (RFC 3461) : `+XX` représente l'octet XX en hexadécimal
+pub fn decode_xtext(input: &str) -> String {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = tail.get(..2)
+            .filter(|_| byte == b'+')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(value) => {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Nom pleinement qualifié de la machine : nom d'hôte, puis résolution inverse de ses adresses
+pub fn system_fqdn() -> Option<String> {
+    let hostname = dns_lookup::get_hostname().ok()?;
+    if hostname.contains('.') {
+        return Some(hostname);
+    }
+    
+    let fqdn = dns_lookup::lookup_host(&hostname).ok()
+        .into_iter()
+        .flatten()
+        .filter(|ip| !ip.is_loopback())
+        .filter_map(|ip| dns_lookup::lookup_addr(&ip).ok())
+        .find(|name| name.contains('.'));
+    Some(fqdn.unwrap_or(hostname))
+}
+
+/// Analyse une durée : secondes seules ou suffixe s, m, h, d (ex. 90, 30m, 12h)
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c),
+        _ => (s, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return Err(format!("invalid duration unit in '{}' (use s, m, h or d)", s)),
+    };
+    number.parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration '{}'", s))
+}
+
+/// Analyse une probabilité entre 0.0 et 1.0
+pub fn parse_probability(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=1.0).contains(p))
+        .ok_or_else(|| format!("invalid probability '{}' (expected 0.0 to 1.0)", s))
+}
+
+/// Adresse ou plage CIDR (`192.0.2.10`, `198.51.100.0/24`, `2001:db8::/32`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpPrefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl IpPrefix {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.len as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.len as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for IpPrefix {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = s.split_once('/').unwrap_or((s, ""));
+        let addr: IpAddr = addr.trim().parse()
+            .map_err(|_| format!("invalid address '{}' (expected an IP or CIDR range)", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let len = if len.is_empty() {
+            max
+        } else {
+            len.trim().parse::<u8>().ok().filter(|len| *len <= max)
+                .ok_or_else(|| format!("invalid prefix length in '{}' (expected 0 to {})", s, max))?
+        };
+        Ok(Self { addr, len })
+    }
+}
+
+/// Tirage reproductible dans [0, 1) : n-ième sortie de SplitMix64 pour la graine donnée
+pub fn seeded_unit(seed: u64, n: u64) -> f64 {
+    let mut z = seed.wrapping_add(n.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Analyse un mode de permissions octal (ex. 0600)
+pub fn parse_octal_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("invalid octal mode '{}'", s))
+}
+
+/// Permissions appliquées aux fichiers et répertoires créés (Unix uniquement)
+#[derive(Debug, Clone, Copy)]
+pub struct FileModes {
+    pub file: u32,
+    pub dir: u32,
+}
+
+impl FileModes {
+    pub fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(self.dir);
+        }
+        builder.create(path)
+    }
+    
+    pub fn open_options(&self) -> OpenOptions {
+        #[allow(unused_mut)]
+        let mut options = OpenOptions::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(self.file);
+        }
+        options
+    }
+    
+    /// Crée (ou tronque) un fichier en écriture avec le mode configuré
+    pub async fn create_file(&self, path: &Path) -> std::io::Result<tokio::fs::File> {
+        #[allow(unused_mut)]
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(self.file);
+        options.open(path).await
+    }
+}
+
+/// Encodage hexadécimal en minuscules
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Longueur de ligne d'en-tête recommandée, et maximale hors CRLF (RFC 5322 2.1.1)
+const HEADER_FOLD_WIDTH: usize = 78;
+const HEADER_MAX_LINE: usize = 998;
+
+// Marque de fin d'un jeton tronqué
+const HEADER_TRUNCATED: &str = "...";
+
+/// Ligne d'en-tête `Nom: valeur` terminée par CRLF, pliée aux espaces (RFC 5322 2.2.3)
+///
+/// Le pli se fait avant un espace dès que la ligne dépasserait 78 caractères. Un mot sans
+/// espace plus long reste entier (le couper ajouterait un espace une fois la ligne dépliée),
+/// sauf au-delà de 998 octets : il est alors tronqué et terminé par `...`.
+pub fn fold_header(name: &str, value: &str) -> String {
+    let mut folded = String::new();
+    let mut line = String::new();
+    for (i, word) in format!("{}: {}", name, value).split(' ').enumerate() {
+        if i > 0 && line.len() + 1 + word.len() > HEADER_FOLD_WIDTH && !line.trim().is_empty() {
+            folded.push_str(&line);
+            folded.push_str("\r\n");
+            line.clear();
+        }
+        if i > 0 {
+            line.push(' ');
+        }
+        let room = HEADER_MAX_LINE.saturating_sub(line.len());
+        if word.len() > room {
+            let mut cut = room.saturating_sub(HEADER_TRUNCATED.len());
+            while !word.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            line.push_str(&word[..cut]);
+            line.push_str(HEADER_TRUNCATED);
+        } else {
+            line.push_str(word);
+        }
+    }
+    folded.push_str(&line);
+    folded.push_str("\r\n");
+    folded
+}
+
+enum LogCommand {
+    Write(String),
+    Flush(oneshot::Sender<()>),
+}
+
+// Taille du tampon d'écriture et intervalle maximal entre deux vidages
+const LOG_BUFFER_SIZE: usize = 64 * 1024;
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Lignes en attente d'écriture ; au-delà, elles sont abandonnées et comptées
+const LOG_QUEUE_SIZE: usize = 16 * 1024;
+
+// Intervalle minimal entre deux avertissements d'échec d'écriture
+const LOG_ERROR_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Échecs d'écriture du journal (disque plein...) : lignes perdues comptées, avertissement limité
+struct WriteFailures {
+    lost: Arc<AtomicU64>,
+    last_warning: Option<std::time::Instant>,
+}
+
+impl WriteFailures {
+    fn record(&mut self, error: &std::io::Error, lost_lines: u64) {
+        let total = self.lost.fetch_add(lost_lines, Ordering::Relaxed) + lost_lines;
+        if self.last_warning.is_some_and(|at| at.elapsed() < LOG_ERROR_WARNING_INTERVAL) {
+            return;
+        }
+        self.last_warning = Some(std::time::Instant::now());
+        eprintln!("[WARNING] Log file write failed: {} ({} line(s) lost so far)", error, total);
+    }
+}
+
+/// Tampon du fichier journal, vidé sur intervalle ou quand il est plein
+struct LogBuffer {
+    file: tokio::fs::File,
+    data: Vec<u8>,
+    lines: u64,
+    failures: WriteFailures,
+}
+
+impl LogBuffer {
+    async fn push(&mut self, text: &str) {
+        self.data.extend_from_slice(text.as_bytes());
+        self.lines += 1;
+        if self.data.len() >= LOG_BUFFER_SIZE {
+            self.flush().await;
+        }
+    }
+    
+    /// En cas d'échec, les lignes en attente sont abandonnées et comptées
+    async fn flush(&mut self) {
+        if self.data.is_empty() {
+            return;
+        }
+        let mut result = self.file.write_all(&self.data).await;
+        if result.is_ok() {
+            result = self.file.flush().await;
+        }
+        if let Err(e) = result {
+            self.failures.record(&e, self.lines);
+        }
+        self.data.clear();
+        self.lines = 0;
+    }
+}
+
+/// Tâche dédiée à l'écriture du fichier journal : les sessions ne font qu'empiler
+fn spawn_log_writer(file: std::fs::File, lost: Arc<AtomicU64>) -> mpsc::Sender<LogCommand> {
+    let (tx, mut rx) = mpsc::channel(LOG_QUEUE_SIZE);
+    let mut buffer = LogBuffer {
+        file: tokio::fs::File::from_std(file),
+        data: Vec::with_capacity(LOG_BUFFER_SIZE),
+        lines: 0,
+        failures: WriteFailures { lost, last_warning: None },
+    };
+    
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LOG_FLUSH_INTERVAL);
+        loop {
+            tokio::select! {
+                command = rx.recv() => match command {
+                    Some(LogCommand::Write(text)) => buffer.push(&text).await,
+                    Some(LogCommand::Flush(ack)) => {
+                        buffer.flush().await;
+                        let _ = ack.send(());
+                    }
+                    None => {
+                        buffer.flush().await;
+                        break;
+                    }
+                },
+                _ = interval.tick() => buffer.flush().await,
+            }
+        }
+    });
+    
+    tx
+}
+
+/// Format d'une sortie du journal : texte lisible, NDJSON (un objet JSON par ligne)
+/// ou compact (une ligne à champs séparés par des tabulations par connexion terminée)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Ndjson,
+    Compact,
+}
+
+/// Champs de la ligne compacte, après l'horodatage et l'IP, pris dans l'événement de fin de connexion
+const COMPACT_FIELDS: [&str; 8] = ["port", "helo", "mail_from", "rcpt_count", "data_bytes", "tls", "auth_attempts",
+                                   "disposition"];
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "ndjson" | "json" => Ok(LogFormat::Ndjson),
+            "compact" => Ok(LogFormat::Compact),
+            _ => Err(format!("invalid log format '{}' (expected text, ndjson or compact)", s)),
+        }
+    }
+}
+
+/// Chaîne JSON, guillemets compris
+pub fn json_string(input: &str) -> String {
+    let mut result = String::with_capacity(input.len() + 2);
+    result.push('"');
+    for c in input.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7f' => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Type et champs nommés d'un événement, pour le format NDJSON
+fn event_fields(event: &Event) -> Vec<(&'static str, String)> {
+    let mut fields = vec![("type", event.kind.as_str().to_string())];
+    fields.extend(event.fields.iter().cloned());
+    fields
+}
+
+fn ndjson_line(timestamp: &str, client_addr: &SocketAddr, message: &str, fields: &[(&str, String)]) -> String {
+    let mut line = format!("{{\"timestamp\":{},\"client\":{},\"message\":{}",
+                           json_string(timestamp), json_string(&client_addr.to_string()), json_string(message));
+    for (key, value) in fields {
+        line.push_str(&format!(",{}:{}", json_string(key), json_string(value)));
+    }
+    line.push('}');
+    line
+}
+
+/// Ligne compacte d'un événement de fin de connexion (champ `disposition`) ; aucune pour les autres
+fn compact_line(timestamp: &str, client_addr: &SocketAddr, event: Option<&Event>) -> Option<String> {
+    let event = event.filter(|event| event.fields.iter().any(|(key, _)| *key == "disposition"))?;
+    let mut columns = vec![timestamp.to_string(), client_addr.ip().to_string()];
+    for name in COMPACT_FIELDS {
+        let value = event.fields.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or("");
+        columns.push(compact_escape(value));
+    }
+    Some(columns.join("\t"))
+}
+
+/// Valeur d'une colonne compacte : ni tabulation ni fin de ligne, `-` si vide
+fn compact_escape(value: &str) -> String {
+    if value.is_empty() {
+        return "-".to_string();
+    }
+    safe_log_string(&value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r"))
+}
+
+fn verbose_block(timestamp: &str, client_addr: &SocketAddr, title: &str, details: &str) -> String {
+    let separator = "─".repeat(60);
+    format!(
+        "{}\n{} VERBOSE: {} {}\n{}\n{}\n{}\n\n",
+        separator,
+        timestamp,
+        client_addr,
+        title,
+        separator,
+        details,
+        separator
+    )
+}
+
+#[derive(Clone)]
 pub struct Logger {
-    writer: Option<Arc<Mutex<BufWriter<File>>>>,
+    writer: Option<mpsc::Sender<LogCommand>>,
     raw_display: bool,
+    stdout_format: LogFormat,
+    file_format: LogFormat,
+    sampler: Option<Arc<LogSampler>>,
+    coalescer: Option<Arc<AlertCoalescer>>,
+    sinks: Vec<(SinkName, Arc<dyn EventSink>)>,
+    /// Sorties par type d'événement (--route) ; type absent : toutes les sorties
+    routes: Arc<HashMap<EventKind, Vec<SinkName>>>,
+    /// Lignes du fichier journal perdues sur erreur d'écriture ou file d'attente pleine
+    lost_lines: Arc<AtomicU64>,
+    /// Connexions dont les événements de routine sont tus (--quiet-allowlist), et leur drapeau,
+    /// levé par la session au premier signal d'anomalie
+    quiet: Arc<std::sync::Mutex<HashMap<SocketAddr, Arc<AtomicBool>>>>,
+    /// Événements de routine tus depuis le dernier résumé, et depuis le démarrage
+    quiet_suppressed: Arc<AtomicU64>,
+    quiet_suppressed_total: Arc<AtomicU64>,
 }
 
 impl Logger {
-    pub fn new(log_file: Option<PathBuf>, raw_display: bool) -> anyhow::Result<Self> {
+    pub fn new(log_file: Option<PathBuf>, raw_display: bool, log_sample: u64, modes: FileModes) -> anyhow::Result<Self> {
+        let lost_lines = Arc::new(AtomicU64::new(0));
         let writer = if let Some(path) = log_file {
             if let Some(parent) = path.parent() {
                 if !parent.exists() {
-                    std::fs::create_dir_all(parent)?;
+                    modes.create_dir_all(parent)?;
                 }
             }
             
-            let file = OpenOptions::new()
+            let file = modes.open_options()
                 .create(true)
                 .append(true)
                 .open(path)?;
             
-            Some(Arc::new(Mutex::new(BufWriter::new(file))))
+            Some(spawn_log_writer(file, lost_lines.clone()))
         } else {
             None
         };
         
-        Ok(Self { writer, raw_display })
+        let sampler = (log_sample > 1).then(|| Arc::new(LogSampler::new(log_sample)));
+        
+        Ok(Self { writer, raw_display, stdout_format: LogFormat::Text, file_format: LogFormat::Text, sampler, coalescer: None, sinks: Vec::new(), routes: Arc::new(HashMap::new()), lost_lines,
+                  quiet: Arc::new(std::sync::Mutex::new(HashMap::new())), quiet_suppressed: Arc::new(AtomicU64::new(0)),
+                  quiet_suppressed_total: Arc::new(AtomicU64::new(0)) })
     }
     
-    pub async fn log(&self, client_addr: &SocketAddr, message: &str) {
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        
-        let display_message = if self.raw_display {
-            message.to_string()
+    /// Vide le fichier journal sur disque et attend la fin de l'écriture
+    pub async fn flush(&self) {
+        if let Some(writer) = &self.writer {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            if writer.send(LogCommand::Flush(ack_tx)).await.is_ok() {
+                let _ = ack_rx.await;
+            }
+        }
+    }
+    
+    /// Nombre de lignes du fichier journal perdues depuis le démarrage
+    pub fn lost_lines(&self) -> u64 {
+        self.lost_lines.load(Ordering::Relaxed)
+    }
+    
+    /// Formats de la sortie standard et du fichier journal, indépendants l'un de l'autre
+    pub fn set_formats(&mut self, stdout_format: LogFormat, file_format: LogFormat) {
+        self.stdout_format = stdout_format;
+        self.file_format = file_format;
+    }
+    
+    /// Regroupe les alertes identiques d'une même IP sur cette fenêtre
+    pub fn set_alert_window(&mut self, window: Duration) {
+        self.coalescer = Some(Arc::new(AlertCoalescer::new(window, AlertCoalescer::MAX_TRACKED_ALERTS)));
+    }
+    
+    pub fn add_sink(&mut self, name: SinkName, sink: Arc<dyn EventSink>) {
+        self.sinks.push((name, sink));
+    }
+    
+    /// Installe les routes --route, après l'ajout des sorties : chaque sortie citée doit être active
+    pub fn set_routes(&mut self, routes: &[Route]) -> anyhow::Result<()> {
+        let mut table: HashMap<EventKind, Vec<SinkName>> = HashMap::new();
+        for route in routes {
+            for sink in &route.sinks {
+                let enabled = match sink {
+                    SinkName::Stdout => true,
+                    SinkName::File => self.writer.is_some(),
+                    _ => self.sinks.iter().any(|(name, _)| name == sink),
+                };
+                if !enabled {
+                    return Err(anyhow::anyhow!("--route {}: sink '{}' is not enabled",
+                                               route.kind.as_str(), sink.as_str()));
+                }
+            }
+            // Plusieurs routes pour un même type s'additionnent
+            let targets = table.entry(route.kind).or_default();
+            for sink in &route.sinks {
+                if !targets.contains(sink) {
+                    targets.push(*sink);
+                }
+            }
+        }
+        self.routes = Arc::new(table);
+        Ok(())
+    }
+    
+    fn routed(&self, kind: EventKind, sink: SinkName) -> bool {
+        self.routes.get(&kind).is_none_or(|targets| targets.contains(&sink))
+    }
+    
+    /// Tait (ou non) les événements de routine d'une connexion : lignes du journal et
+    /// ouverture/fermeture ; les événements structurés (refus, AUTH, alertes, captures...) restent émis
+    pub fn set_quiet(&self, client_addr: SocketAddr, quiet: bool) {
+        let mut connections = self.quiet.lock().unwrap();
+        if quiet {
+            connections.insert(client_addr, Arc::new(AtomicBool::new(true)));
         } else {
-            filter_printable_chars(message)
-        };
-        
-        let log_line = format!("{} {} {}\n", timestamp, client_addr, display_message);
+            connections.remove(&client_addr);
+        }
+    }
+    
+    /// Drapeau d'une connexion tue, à lever pour journaliser de nouveau toute la suite de la session
+    pub fn quiet_flag(&self, client_addr: &SocketAddr) -> Option<Arc<AtomicBool>> {
+        self.quiet.lock().unwrap().get(client_addr).cloned()
+    }
+    
+    /// Événements de routine tus depuis le démarrage
+    pub fn quiet_suppressed(&self) -> u64 {
+        self.quiet_suppressed_total.load(Ordering::Relaxed)
+    }
+    
+    /// Événement de routine d'une connexion tue : compté, pas journalisé
+    fn quieted(&self, client_addr: &SocketAddr, kind: Option<EventKind>) -> bool {
+        let quiet = self.quiet.lock().unwrap().get(client_addr).is_some_and(|flag| flag.load(Ordering::Relaxed));
+        if !quiet || !matches!(kind, None | Some(EventKind::Connection)) {
+            return false;
+        }
+        self.quiet_suppressed.fetch_add(1, Ordering::Relaxed);
+        self.quiet_suppressed_total.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+    
+    fn sampled_in(&self, client_addr: &SocketAddr, severity: Severity, closing: bool) -> bool {
+        match (&self.sampler, severity) {
+            (Some(sampler), Severity::Low) => sampler.should_log(client_addr, closing),
+            _ => true,
+        }
+    }
+    
+    /// Journalise un événement en tenant compte de sa gravité
+    pub async fn log_severity(&self, client_addr: &SocketAddr, severity: Severity, message: &str) {
+        if !self.quieted(client_addr, None) && self.sampled_in(client_addr, severity, false) {
+            self.write_line(client_addr, message, None, true, true).await;
+        }
+    }
+    
+    /// Journalise un événement structuré et le transmet aux sorties configurées
+    pub async fn event(&self, event: Event) {
+        if let (EventKind::Alert, Some(coalescer)) = (event.kind, &self.coalescer) {
+            let (admitted, summaries) = coalescer.admit(&event);
+            for summary in summaries {
+                self.emit(summary).await;
+            }
+            if !admitted {
+                return;
+            }
+        }
+        self.emit(event).await;
+    }
+    
+    /// Émet les résumés des alertes regroupées dont la fenêtre a expiré
+    pub async fn flush_coalesced_alerts(&self) {
+        if let Some(coalescer) = &self.coalescer {
+            for summary in coalescer.flush() {
+                self.emit(summary).await;
+            }
+        }
+    }
+    
+    /// L'échantillonnage ne concerne que la sortie standard et le fichier journal :
+    /// les sorties structurées reçoivent tous les événements
+    async fn emit(&self, event: Event) {
+        if self.quieted(&event.client_addr, Some(event.kind)) {
+            return;
+        }
+        let closing = event.kind == EventKind::Connection && event.message == "Connection closed";
+        if self.sampled_in(&event.client_addr, event.kind.severity(), closing) {
+            self.write_line(&event.client_addr, &event.message, Some(&event),
+                            self.routed(event.kind, SinkName::Stdout),
+                            self.routed(event.kind, SinkName::File)).await;
+        }
+        self.dispatch(&event);
+    }
+    
+    /// Transmet un événement aux sorties structurées sans l'écrire dans le journal texte
+    pub fn dispatch(&self, event: &Event) {
+        for (name, sink) in &self.sinks {
+            if self.routed(event.kind, *name) {
+                sink.send(event);
+            }
+        }
+    }
+    
+    /// Journalise le nombre d'événements supprimés depuis le dernier appel
+    pub async fn log_suppressed_summary(&self) {
+        let quieted = self.quiet_suppressed.swap(0, Ordering::Relaxed);
+        if quieted > 0 {
+            self.log(&SocketAddr::from(([0,0,0,0], 0)),
+                     &format!("Suppressed {} routine events from allowlisted sources (--quiet-allowlist)", quieted)).await;
+        }
+        if let Some(sampler) = &self.sampler {
+            let suppressed = sampler.suppressed.swap(0, Ordering::Relaxed);
+            if suppressed > 0 {
+                self.log(&SocketAddr::from(([0,0,0,0], 0)),
+                         &format!("Suppressed {} low-severity events (sampling 1/{})", suppressed, sampler.rate)).await;
+            }
+        }
+    }
+    
+    pub async fn log(&self, client_addr: &SocketAddr, message: &str) {
+        self.log_severity(client_addr, Severity::Normal, message).await;
+    }
+    
+    async fn write_line(&self, client_addr: &SocketAddr, message: &str, event: Option<&Event>, to_stdout: bool, to_file: bool) {
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        let fields = event.map(event_fields).unwrap_or_default();
         
-        if self.raw_display {
-            print!("{}", log_line);
-        } else {
-            print!("{}", filter_printable_chars(&log_line));
+        if to_stdout {
+            let display_message = if self.raw_display {
+                message.to_string()
+            } else {
+                filter_printable_chars(message)
+            };
+            match self.stdout_format {
+                LogFormat::Text => println!("{} {} {}", timestamp, client_addr, display_message),
+                LogFormat::Ndjson => println!("{}", ndjson_line(&timestamp, client_addr, &display_message, &fields)),
+                LogFormat::Compact => {
+                    if let Some(line) = compact_line(&timestamp, client_addr, event) {
+                        println!("{}", line);
+                    }
+                }
+            }
         }
         
-        if let Some(writer) = &self.writer {
-            let mut writer = writer.lock().await;
-            let file_line = format!("{} {} {}\n", timestamp, client_addr, message);
-            let _ = writer.write_all(file_line.as_bytes());
-            let _ = writer.flush();
+        if let Some(writer) = self.writer.as_ref().filter(|_| to_file) {
+            let file_line = match self.file_format {
+                LogFormat::Text => Some(format!("{} {} {}\n", timestamp, client_addr, message)),
+                LogFormat::Ndjson => Some(format!("{}\n", ndjson_line(&timestamp, client_addr, message, &fields))),
+                LogFormat::Compact => compact_line(&timestamp, client_addr, event).map(|line| format!("{}\n", line)),
+            };
+            if let Some(file_line) = file_line {
+                self.queue_line(writer, file_line);
+            }
         }
     }
     
     pub async fn log_verbose(&self, client_addr: &SocketAddr, title: &str, details: &str) {
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let separator = "─".repeat(60);
+        if self.quieted(client_addr, None) {
+            return;
+        }
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
         
         let display_details = if self.raw_display {
             details.to_string()
@@ -114,38 +1000,180 @@ impl Logger {
             safe_log_string(details)
         };
         
-        let verbose_log = format!(
-            "{}\n{} VERBOSE: {} {}\n{}\n{}\n{}\n\n",
-            separator,
-            timestamp,
-            client_addr,
-            title,
-            separator,
-            display_details,
-            separator
-        );
-        
-        if self.raw_display {
-            print!("{}", verbose_log);
-        } else {
-            print!("{}", filter_printable_chars(&verbose_log));
+        match self.stdout_format {
+            LogFormat::Text => {
+                let verbose_log = verbose_block(&timestamp, client_addr, title, &display_details);
+                if self.raw_display {
+                    print!("{}", verbose_log);
+                } else {
+                    print!("{}", filter_printable_chars(&verbose_log));
+                }
+            }
+            LogFormat::Ndjson => {
+                let fields = [("type", "verbose".to_string()), ("details", display_details)];
+                println!("{}", ndjson_line(&timestamp, client_addr, title, &fields));
+            }
+            // Une ligne par connexion seulement : pas de bloc détaillé
+            LogFormat::Compact => {}
         }
         
         if let Some(writer) = &self.writer {
-            let mut writer = writer.lock().await;
-            let file_log = format!(
-                "{}\n{} VERBOSE: {} {}\n{}\n{}\n{}\n\n",
-                separator,
-                timestamp,
-                client_addr,
-                title,
-                separator,
-                safe_log_string(details),
-                separator
-            );
-            let _ = writer.write_all(file_log.as_bytes());
-            let _ = writer.flush();
+            let file_log = match self.file_format {
+                LogFormat::Text => verbose_block(&timestamp, client_addr, title, &safe_log_string(details)),
+                LogFormat::Ndjson => {
+                    let fields = [("type", "verbose".to_string()), ("details", details.to_string())];
+                    format!("{}\n", ndjson_line(&timestamp, client_addr, title, &fields))
+                }
+                LogFormat::Compact => return,
+            };
+            self.queue_line(writer, file_log);
+        }
+    }
+    
+    /// Confie une ligne à la tâche d'écriture sans attendre : file pleine, la ligne est perdue
+    fn queue_line(&self, writer: &mpsc::Sender<LogCommand>, line: String) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = writer.try_send(LogCommand::Write(line)) {
+            self.lost_lines.fetch_add(1, Ordering::Relaxed);
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn sampling_keeps_open_and_close_together() {
+        let sampler = LogSampler::new(2);
+        let mut kept = Vec::new();
+        for port in 1000..1006 {
+            let addr = SocketAddr::from(([192, 0, 2, 1], port));
+            let opened = sampler.should_log(&addr, false);
+            let closed = sampler.should_log(&addr, true);
+            assert_eq!(opened, closed, "connection {}", port);
+            kept.push(opened);
+        }
+        assert_eq!(kept, [true, false, true, false, true, false]);
+        assert!(sampler.connections.lock().unwrap().is_empty());
+        assert_eq!(sampler.suppressed.load(Ordering::Relaxed), 6);
+    }
+    
+    /// Dépliage RFC 5322 : CRLF suivi d'un blanc supprimé
+    fn unfold(header: &str) -> String {
+        header.trim_end_matches("\r\n").replace("\r\n ", " ")
+    }
+    
+    #[test]
+    fn fold_header_breaks_only_at_spaces() {
+        let value = (0..40).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ");
+        let folded = fold_header("X-Test", &value);
+        assert!(folded.split("\r\n").all(|line| line.len() <= HEADER_FOLD_WIDTH), "{}", folded);
+        assert_eq!(unfold(&folded), format!("X-Test: {}", value));
+        
+        // Jeton sans espace plus long que la largeur de pli : gardé entier
+        let token = "x".repeat(500);
+        let folded = fold_header("X-Test", &format!("a {} b", token));
+        assert_eq!(unfold(&folded), format!("X-Test: a {} b", token));
+        assert!(folded.contains(&format!(" {}\r\n", token)), "{}", folded);
+    }
+    
+    #[test]
+    fn fold_header_truncates_unbreakable_helo() {
+        let helo = "h".repeat(2000);
+        let folded = fold_header("X-Honeypot-HELO", &helo);
+        assert!(folded.split("\r\n").all(|line| line.len() <= HEADER_MAX_LINE), "line over 998 octets");
+        assert!(folded.ends_with("...\r\n"), "{}", folded);
+        
+        // Coupure sur une frontière de caractère
+        let folded = fold_header("X-Honeypot-HELO", &"é".repeat(1000));
+        assert!(folded.split("\r\n").all(|line| line.len() <= HEADER_MAX_LINE), "line over 998 octets");
+    }
+    
+    #[test]
+    fn compact_line_escapes_tabs_and_newlines() {
+        let addr: SocketAddr = "192.0.2.1:4000".parse().unwrap();
+        let event = Event::new(EventKind::Connection, addr, "Connection closed")
+            .with("port", 25)
+            .with("helo", "evil\thost\r\nX-Injected: 1")
+            .with("rcpt_count", 0)
+            .with("disposition", "closed");
+        let line = compact_line("2024-01-01 00:00:00.000", &addr, Some(&event)).unwrap();
+        
+        assert_eq!(line.split('\t').collect::<Vec<_>>(),
+                   ["2024-01-01 00:00:00.000", "192.0.2.1", "25", "evil\\thost\\r\\nX-Injected: 1", "-", "0", "-", "-", "-", "closed"]);
+        assert!(!line.contains('\n') && !line.contains('\r'));
+        assert_eq!(compact_escape("a\\b"), "a\\\\b");
+        
+        // Seule la fin de connexion donne une ligne
+        assert!(compact_line("", &addr, Some(&Event::new(EventKind::Connection, addr, "New connection"))).is_none());
+    }
+    
+    #[test]
+    fn ip_prefix_boundaries() {
+        let prefix = |s: &str| s.parse::<IpPrefix>().unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        
+        assert!(prefix("0.0.0.0/0").contains(ip("203.0.113.9")));
+        assert!(prefix("::/0").contains(ip("2001:db8::1")));
+        assert!(prefix("192.0.2.7/32").contains(ip("192.0.2.7")));
+        assert!(!prefix("192.0.2.7/32").contains(ip("192.0.2.8")));
+        assert!(prefix("192.0.2.7").contains(ip("192.0.2.7")));
+        assert!(prefix("2001:db8::7/128").contains(ip("2001:db8::7")));
+        assert!(!prefix("2001:db8::7/128").contains(ip("2001:db8::6")));
+        assert!(prefix("192.0.2.0/24").contains(ip("192.0.2.255")));
+        assert!(!prefix("192.0.2.0/24").contains(ip("192.0.3.0")));
+        
+        // Familles différentes : jamais de correspondance, même pour ::/0 ou ::ffff:0:0/96
+        assert!(!prefix("::/0").contains(ip("192.0.2.1")));
+        assert!(!prefix("::ffff:0:0/96").contains(ip("192.0.2.1")));
+        assert!(!prefix("0.0.0.0/0").contains(ip("::ffff:192.0.2.1")));
+        
+        for invalid in ["192.0.2.0/33", "2001:db8::/129", "192.0.2.0/-1", "192.0.2.0/x", "192.0.2/24"] {
+            assert!(invalid.parse::<IpPrefix>().is_err(), "{} accepted", invalid);
+        }
+    }
+    
+    #[test]
+    fn classify_helo_table() {
+        let cases = [
+            (Some("192.0.2.1"), HeloClass::Malformed),
+            (Some("[192.0.2.1]"), HeloClass::IpLiteral),
+            (Some("[IPv6:2001:db8::1]"), HeloClass::IpLiteral),
+            (Some("[not-an-ip]"), HeloClass::Malformed),
+            (Some("mail.example.com"), HeloClass::ValidFqdn),
+            (Some("Mail.Example.COM."), HeloClass::ValidFqdn),
+            (Some("bad_host.example.com"), HeloClass::Malformed),
+            (Some("-mail.example.com"), HeloClass::Malformed),
+            (Some("WIN-8F2K1"), HeloClass::Malformed),
+            (Some("localhost"), HeloClass::Localhost),
+            (Some("box.localdomain"), HeloClass::Localhost),
+            (Some(""), HeloClass::Missing),
+            (None, HeloClass::Missing),
+        ];
+        for (helo, expected) in cases {
+            assert_eq!(classify_helo(helo), expected, "{:?}", helo);
+        }
+    }
+    
+    #[test]
+    fn alert_coalescer_summarises_evicted_entries() {
+        let coalescer = AlertCoalescer::new(Duration::from_secs(3600), 2);
+        let alert = |last: u8| Event::new(EventKind::Alert, SocketAddr::from(([192, 0, 2, last], 25)), "ALERT")
+            .with("alert", "relay");
+        
+        let (admitted, summaries) = coalescer.admit(&alert(1));
+        assert!(admitted && summaries.is_empty());
+        assert!(!coalescer.admit(&alert(1)).0);
+        assert!(!coalescer.admit(&alert(1)).0);
+        coalescer.admit(&alert(2));
+        
+        // Troisième IP : la plus ancienne entrée cède sa place et ses 2 alertes regroupées sont résumées
+        let (admitted, summaries) = coalescer.admit(&alert(3));
+        assert!(admitted);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].client_addr.ip(), IpAddr::from([192, 0, 2, 1]));
+        assert!(summaries[0].fields.contains(&("coalesced", "2".to_string())), "{:?}", summaries[0].fields);
+        assert_eq!(coalescer.recent.lock().unwrap().len(), 2);
+    }
+}
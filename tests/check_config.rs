@@ -0,0 +1,78 @@
+mod common;
+
+use std::process::Command;
+
+#[test]
+fn check_config_has_no_side_effects() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-check-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let grpc_port = common::free_port();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+        .args(["--check-config", "-a", "127.0.0.1", "-p", &common::free_port().to_string(), "--domain", "example.com",
+               "--grpc-port", &grpc_port.to_string(), "--data"])
+        .arg(dir.join("data"))
+        .arg("--logs")
+        .arg(dir.join("honeypot.log"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let created: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    let grpc_bound = std::net::TcpListener::bind(("127.0.0.1", grpc_port)).is_err();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Configuration check: OK"), "{}", stdout);
+    assert!(created.is_empty(), "--check-config created {:?}", created);
+    assert!(!grpc_bound);
+}
+
+#[test]
+fn check_config_reports_invalid_options() {
+    let output = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+        .args(["--check-config", "-a", "127.0.0.1", "-p", &common::free_port().to_string(), "--domain", "example.com",
+               "--save-workers", "0"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("[FAIL] Options: --save-workers must be at least 1"), "{}", stdout);
+}
+
+#[test]
+fn zero_accept_rate_is_rejected_at_startup() {
+    let output = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+        .args(["-a", "127.0.0.1", "-p", &common::free_port().to_string(), "--domain", "example.com",
+               "--max-accepts-per-second", "0"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("--max-accepts-per-second must be at least 1"), "{}", stderr);
+}
+
+#[test]
+fn reject_message_variant_without_code_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+        .args(["-a", "127.0.0.1", "-p", &common::free_port().to_string(), "--domain", "example.com",
+               "--reject-message", "5.1.1 User unknown|Mailbox unavailable"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("--reject-message variants after '|' must start with a 4xx/5xx code: 'Mailbox unavailable'"),
+            "{}", stderr);
+}
+
+#[test]
+fn accept_loops_without_reuseport_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+        .args(["--check-config", "-a", "127.0.0.1", "-p", &common::free_port().to_string(), "--domain", "example.com",
+               "--accept-loops", "2"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("[FAIL] Options: --accept-loops requires --reuseport"), "{}", stdout);
+}
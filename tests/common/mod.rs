@@ -0,0 +1,232 @@
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// Honeypot lancé pour un test : port libre, répertoire de travail propre, journal dans un fichier
+pub struct Honeypot {
+    child: Child,
+    pub port: u16,
+    pub dir: PathBuf,
+}
+
+impl Honeypot {
+    /// Lance le binaire avec --domain example.com, --data <dir>/data et les options données
+    pub fn start(args: &[&str]) -> Self {
+        Self::start_on(free_port(), args)
+    }
+    
+    /// Idem sur un port choisi d'avance, pour les options qui le citent
+    pub fn start_on(port: u16, args: &[&str]) -> Self {
+        let dir = std::env::temp_dir().join(format!("smtp-honeypot-test-{}-{}", std::process::id(),
+                                                    NEXT_DIR.fetch_add(1, Ordering::Relaxed)));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        
+        let stdout = std::fs::File::create(dir.join("stdout.log")).unwrap();
+        let stderr = std::fs::File::create(dir.join("stderr.log")).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+            .args(["-a", "127.0.0.1", "-p", &port.to_string(), "--domain", "example.com", "--data"])
+            .arg(dir.join("data"))
+            .args(args)
+            .stdout(Stdio::from(stdout))
+            .stderr(Stdio::from(stderr))
+            .spawn()
+            .expect("failed to start smtp-honeypot");
+        
+        let honeypot = Self { child, port, dir };
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(Instant::now() < deadline, "honeypot did not start:\n{}", honeypot.stderr());
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        honeypot
+    }
+    
+    /// Connexion SMTP, bannière 220 lue
+    pub fn connect(&self) -> Client {
+        let mut client = Client::open(self.port);
+        let banner = client.reply();
+        assert!(banner[0].starts_with("220"), "unexpected banner {:?}", banner);
+        client
+    }
+    
+    /// Journal écrit sur la sortie standard jusqu'ici
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&std::fs::read(self.dir.join("stdout.log")).unwrap_or_default()).into_owned()
+    }
+    
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&std::fs::read(self.dir.join("stderr.log")).unwrap_or_default()).into_owned()
+    }
+    
+    /// Attend qu'une ligne du journal contienne `needle`
+    pub fn wait_for_output(&self, needle: &str) -> String {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            let output = self.output();
+            if output.contains(needle) {
+                return output;
+            }
+            assert!(Instant::now() < deadline, "'{}' never logged:\n{}", needle, output);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+    
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+    
+    /// Code de sortie du processus s'il se termine dans le délai
+    pub fn exit_code_within(&mut self, timeout: Duration) -> Option<i32> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.code();
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        None
+    }
+    
+    /// Captures .eml enregistrées dans le répertoire de données
+    pub fn captures(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(self.dir.join("data")).unwrap()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "eml"))
+            .collect();
+        files.sort();
+        files
+    }
+    
+    /// Attend `count` captures, écrites après la réponse au point final
+    pub fn wait_for_captures(&self, count: usize) -> Vec<PathBuf> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            let captures = self.captures();
+            if captures.len() >= count || Instant::now() >= deadline {
+                return captures;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Honeypot {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Port libre au moment de l'appel
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Certificat autosigné et sa clé dans un même fichier PEM, pour --tls-pem
+pub fn self_signed_pem(dir: &std::path::Path) -> PathBuf {
+    use openssl::{asn1::Asn1Time, hash::MessageDigest, pkey::PKey, rsa::Rsa, x509::{X509, X509NameBuilder}};
+    
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "mx.example.com").unwrap();
+    let name = name.build();
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    
+    let path = dir.join("cert.pem");
+    let mut pem = cert.build().to_pem().unwrap();
+    pem.extend(key.private_key_to_pem_pkcs8().unwrap());
+    std::fs::write(&path, pem).unwrap();
+    path
+}
+
+/// Client SMTP minimal : lignes envoyées telles quelles, réponses multilignes regroupées
+pub struct Client {
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    pub fn open(port: u16) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(REPLY_TIMEOUT)).unwrap();
+        Self { reader: BufReader::new(stream) }
+    }
+    
+    pub fn send(&mut self, bytes: &[u8]) {
+        self.reader.get_mut().write_all(bytes).unwrap();
+    }
+    
+    /// Une réponse complète (lignes `NNN-` puis `NNN `), sans les fins de ligne
+    pub fn reply(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.line().unwrap_or_else(|| panic!("connection closed after {:?}", lines));
+            let last = line.as_bytes().get(3) != Some(&b'-');
+            lines.push(line);
+            if last {
+                return lines;
+            }
+        }
+    }
+    
+    /// Envoie une commande et renvoie sa réponse
+    pub fn command(&mut self, line: &str) -> Vec<String> {
+        self.send(format!("{}\r\n", line).as_bytes());
+        self.reply()
+    }
+    
+    /// Ligne suivante, ou None si le serveur a fermé la connexion
+    pub fn line(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+    
+    /// Vrai si le serveur ferme la connexion avant `timeout` (lignes reçues entre-temps ignorées)
+    pub fn closed_within(&mut self, timeout: Duration) -> bool {
+        self.reader.get_ref().set_read_timeout(Some(timeout)).unwrap();
+        let mut rest = Vec::new();
+        let closed = loop {
+            match self.reader.read_until(b'\n', &mut rest) {
+                Ok(0) => break true,
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => break true,
+                Err(_) => break false,
+            }
+        };
+        self.reader.get_ref().set_read_timeout(Some(REPLY_TIMEOUT)).unwrap();
+        closed
+    }
+    
+    /// Transaction complète jusqu'à la réponse au point final
+    pub fn send_message(&mut self, from: &str, rcpts: &[&str], body: &str) -> Vec<String> {
+        assert!(self.command(&format!("MAIL FROM:<{}>", from))[0].starts_with("250"));
+        for rcpt in rcpts {
+            let reply = self.command(&format!("RCPT TO:<{}>", rcpt));
+            assert!(reply[0].starts_with("250"), "RCPT {} refused: {:?}", rcpt, reply);
+        }
+        assert!(self.command("DATA")[0].starts_with("354"));
+        self.send(format!("{}\r\n.\r\n", body).as_bytes());
+        self.reply()
+    }
+}
@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+
+use common::Honeypot;
+
+#[test]
+fn control_socket_created_with_file_mode() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-control-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("control.sock");
+    
+    let honeypot = Honeypot::start(&["--control-socket", socket.to_str().unwrap(), "--file-mode", "0640"]);
+    honeypot.connect();
+    let metadata = std::fs::symlink_metadata(&socket).unwrap();
+    let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    stream.write_all(b"stats\n").unwrap();
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+    assert_eq!(entries, ["control.sock"], "staging directory left behind");
+    assert!(reply.starts_with("OK "), "{}", reply);
+}
+
+#[test]
+fn extra_control_arguments_are_rejected() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-control-args-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("control.sock");
+    
+    let honeypot = Honeypot::start(&["--control-socket", socket.to_str().unwrap(), "--recent-events", "10"]);
+    honeypot.connect();
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+    let mut command = |line: &str| {
+        stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+        replies.next().unwrap().unwrap()
+    };
+    
+    assert!(command("recent 10").starts_with("OK "));
+    for line in ["recent 10 junk", "stats now", "ban 192.0.2.1 192.0.2.2"] {
+        assert_eq!(command(line), format!("ERR unknown command '{}' (expected stats, ban <ip>, unban <ip>, reload, drain or recent [n])", line));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,58 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn daemon_mode_detaches_and_serves() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-daemon-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let port = common::free_port();
+    let pid_file = dir.join("honeypot.pid");
+    
+    // Le processus lancé rend la main une fois le démon détaché
+    let status = Command::new(env!("CARGO_BIN_EXE_smtp-honeypot"))
+        .args(["--daemon", "-a", "127.0.0.1", "-p", &port.to_string(), "--domain", "example.com", "--pid-file"])
+        .arg(&pid_file)
+        .arg("--logs")
+        .arg(dir.join("honeypot.log"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    
+    let deadline = Instant::now() + Duration::from_secs(15);
+    let stream = loop {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            break stream;
+        }
+        assert!(Instant::now() < deadline, "daemon never listened:\n{}",
+                std::fs::read_to_string(dir.join("honeypot.log")).unwrap_or_default());
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut banner = String::new();
+    BufReader::new(&stream).read_line(&mut banner).unwrap();
+    
+    let tmp_test_file = std::path::Path::new("/tmp").join(format!("smtp-honeypot-server-test-{}", port));
+    let wrote_tmp = tmp_test_file.exists();
+    let pid = std::fs::read_to_string(&pid_file).unwrap_or_default().trim().to_string();
+    let mut created: Vec<String> = std::fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    created.sort();
+    if !pid.is_empty() {
+        let _ = Command::new("kill").arg(&pid).status();
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(banner.starts_with("220"), "{:?}", banner);
+    assert!(pid.parse::<u32>().is_ok(), "no PID written: {:?}", pid);
+    // Ni journal de débogage ni fichier de test sans --daemon-debug-log
+    assert_eq!(created, ["honeypot.log", "honeypot.pid"]);
+    assert!(!wrote_tmp, "{} written", tmp_test_file.display());
+}
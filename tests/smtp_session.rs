@@ -0,0 +1,478 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{Client, Honeypot};
+
+#[test]
+fn invalid_utf8_does_not_end_session() {
+    let honeypot = Honeypot::start(&[]);
+    let mut client = honeypot.connect();
+    
+    client.send(b"EHLO \xff\xfe\xc3(bad\r\n");
+    assert!(client.reply()[0].starts_with("250"));
+    assert_eq!(client.command("NOOP"), ["250 OK"]);
+    
+    let output = honeypot.wait_for_output(">> NOOP");
+    assert!(output.contains(r">> EHLO \xff\xfe\xc3(bad"), "bytes not escaped:\n{}", output);
+}
+
+#[test]
+fn long_body_line_is_captured() {
+    // En mémoire, puis avec débordement sur disque dès le premier kilo-octet
+    for args in [&[][..], &["--spill-threshold", "1000"][..]] {
+        let honeypot = Honeypot::start(args);
+        let mut client = honeypot.connect();
+        client.command("EHLO client.example.org");
+        
+        // Ligne de 20 000 octets, puis une de 8192 octets dont le CR tombe en fin de morceau
+        let long = "x".repeat(20_000);
+        let boundary = "y".repeat(8192);
+        let body = format!("Subject: html\r\n\r\n{}\r\n{}\r\nend", long, boundary);
+        let reply = client.send_message("a@b.example", &["user@example.com"], &body);
+        assert!(reply[0].starts_with("250"), "message refused: {:?}", reply);
+        assert_eq!(client.command("NOOP"), ["250 OK"]);
+        
+        let captures = honeypot.wait_for_captures(1);
+        assert_eq!(captures.len(), 1, "{:?}", args);
+        let saved = std::fs::read(&captures[0]).unwrap();
+        let saved_body = &saved[saved.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4..];
+        assert!(saved_body == body.as_bytes(), "body altered with {:?}", args);
+    }
+}
+
+#[test]
+fn starttls_offered_only_on_starttls_ports() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-cert-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pem = common::self_signed_pem(&dir);
+    let pem = pem.to_str().unwrap();
+    
+    let honeypot = Honeypot::start(&["--starttls", "--tls-pem", pem]);
+    let ehlo = honeypot.connect().command("EHLO client.example.org");
+    assert!(!ehlo.iter().any(|line| line.contains("STARTTLS")), "STARTTLS offered outside --starttls-port: {:?}", ehlo);
+    let mut client = honeypot.connect();
+    assert!(client.command("STARTTLS")[0].starts_with("454"));
+    
+    let port = common::free_port();
+    let honeypot = Honeypot::start_on(port, &["--starttls", "--tls-pem", pem, "--starttls-port", &port.to_string()]);
+    let ehlo = honeypot.connect().command("EHLO client.example.org");
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(ehlo.iter().any(|line| line.contains("STARTTLS")), "STARTTLS not offered: {:?}", ehlo);
+}
+
+#[test]
+fn quiet_allowlist_still_logs_anomalies() {
+    let honeypot = Honeypot::start(&["--allowlist", "127.0.0.0/8", "--quiet-allowlist"]);
+    
+    // Contrôle de supervision : rien dans le journal
+    let mut client = honeypot.connect();
+    client.command("EHLO monitor.example.org");
+    client.command("QUIT");
+    
+    // Même source, EHLO et NOOP envoyés ensemble : violation de pipelining
+    let mut client = honeypot.connect();
+    client.send(b"EHLO client.example.org\r\nNOOP\r\n");
+    client.reply();
+    client.reply();
+    client.command("QUIT");
+    
+    let output = honeypot.wait_for_output("Pipelining violation");
+    assert!(!output.contains("monitor.example.org"), "routine session logged:\n{}", output);
+    assert!(output.contains(">> NOOP"), "session not logged after the anomaly:\n{}", output);
+    let closed = honeypot.wait_for_output("Connection closed");
+    assert_eq!(closed.matches("Connection closed").count(), 1, "{}", closed);
+}
+
+#[test]
+fn auth_exchange_completes_before_relay_alert() {
+    let honeypot = Honeypot::start(&[]);
+    
+    // AUTH PLAIN sans réponse initiale puis annulé : pas authentifié
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    assert_eq!(client.command("AUTH PLAIN"), ["334 "]);
+    assert!(client.command("*")[0].starts_with("501"));
+    client.command("MAIL FROM:<a@b.example>");
+    client.command("RCPT TO:<victim@elsewhere.example>");
+    client.command("QUIT");
+    
+    // AUTH LOGIN mené à terme : nom d'utilisateur, mot de passe, puis relais
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    assert_eq!(client.command("AUTH LOGIN"), ["334 VXNlcm5hbWU6"]);
+    assert_eq!(client.command("dXNlcg=="), ["334 UGFzc3dvcmQ6"]);
+    assert!(client.command("c2VjcmV0")[0].starts_with("235"));
+    client.command("MAIL FROM:<a@b.example>");
+    client.command("RCPT TO:<victim@elsewhere.example>");
+    client.command("QUIT");
+    
+    let output = honeypot.wait_for_output("authenticated session attempted relay");
+    assert_eq!(output.matches("authenticated session attempted relay").count(), 1, "{}", output);
+}
+
+#[test]
+fn max_uptime_lets_active_sessions_finish() {
+    let mut honeypot = Honeypot::start(&["--max-uptime", "1s"]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    
+    // Échéance passée en pleine session : la transaction se termine normalement
+    honeypot.wait_for_output("Max uptime: draining");
+    let reply = client.send_message("a@b.example", &["user@example.com"], "Subject: late\r\n\r\nbody");
+    assert!(reply[0].starts_with("250"), "{:?}", reply);
+    assert_eq!(honeypot.exit_code_within(Duration::from_millis(300)), None, "exited with a session still active");
+    client.command("QUIT");
+    
+    assert_eq!(honeypot.exit_code_within(Duration::from_secs(5)), Some(75));
+    assert_eq!(honeypot.captures().len(), 1);
+}
+
+#[test]
+fn persona_size_limit_is_enforced() {
+    // Graine 3 : profil postfix, SIZE 10240000
+    let honeypot = Honeypot::start(&["--randomize-persona", "--persona-seed", "3"]);
+    let mut client = honeypot.connect();
+    let ehlo = client.command("EHLO client.example.org");
+    assert!(ehlo.iter().any(|line| line.ends_with("SIZE 10240000")), "{:?}", ehlo);
+    assert!(!ehlo.iter().any(|line| line.contains("ENHANCEDSTATUSCODES")), "{:?}", ehlo);
+    
+    assert!(client.command("MAIL FROM:<a@b.example> SIZE=10240001")[0].starts_with("552"));
+    
+    let line = "x".repeat(998);
+    let body = format!("Subject: big\r\n\r\n{}", vec![line.as_str(); 10_300].join("\r\n"));
+    let reply = client.send_message("a@b.example", &["user@example.com"], &body);
+    assert!(reply[0].starts_with("552"), "{:?}", reply);
+    assert_eq!(client.command("NOOP"), ["250 OK"]);
+    assert!(honeypot.captures().is_empty());
+}
+
+#[test]
+fn lookups_carry_the_session_id() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-asn-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = dir.join("ip2asn.tsv");
+    std::fs::write(&db, "127.0.0.0\t127.255.255.255\t64500\tZZ\tLOOPBACK-TEST\n").unwrap();
+    
+    let honeypot = Honeypot::start(&["--asn-db", db.to_str().unwrap(), "--log-lookups"]);
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    client.send_message("a@b.example", &["user@example.com"], "Subject: asn\r\n\r\nbody");
+    
+    let captures = honeypot.wait_for_captures(1);
+    let name = captures[0].file_name().unwrap().to_str().unwrap().to_string();
+    let session = name.split('_').find(|part| part.starts_with('s')).unwrap();
+    let output = honeypot.wait_for_output("Lookup");
+    assert!(output.contains(&format!("Lookup {} asn: 127.0.0.1 -> AS64500 (LOOPBACK-TEST)", session)), "{}\n{}", name, output);
+}
+
+#[test]
+fn capture_with_50_recipients_parses() {
+    let honeypot = Honeypot::start(&[]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    let rcpts: Vec<String> = (0..50).map(|i| format!("user{}@example.com", i)).collect();
+    let rcpt_refs: Vec<&str> = rcpts.iter().map(String::as_str).collect();
+    let reply = client.send_message("a@b.example", &rcpt_refs, "Subject: many\r\n\r\nbody");
+    assert!(reply[0].starts_with("250"), "{:?}", reply);
+    
+    let captures = honeypot.wait_for_captures(1);
+    let raw = std::fs::read(&captures[0]).unwrap();
+    assert!(raw.split(|b| *b == b'\n').all(|line| line.len() <= 1000), "line over 998 octets");
+    let message = mail_parser::MessageParser::default().parse(&raw[..]).expect("capture does not parse");
+    let parsed: Vec<String> = message.header_values("X-Honeypot-RcptTo")
+        .map(|value| value.as_text().unwrap_or_default().trim().to_string())
+        .collect();
+    assert_eq!(parsed, rcpts);
+    // Le message du client, en-têtes compris, forme le corps de la capture
+    assert_eq!(message.body_text(0).as_deref().map(str::trim_end), Some("Subject: many\r\n\r\nbody"));
+}
+
+#[test]
+fn lmtp_replies_once_per_recipient() {
+    let honeypot = Honeypot::start(&["--lmtp"]);
+    let mut client = honeypot.connect();
+    client.command("LHLO client.example.org");
+    
+    let first = client.send_message("a@b.example", &["one@example.com", "two@example.com"], "Subject: lmtp\r\n\r\nbody");
+    let second = client.reply();
+    assert_eq!(first, ["250 OK: Message accepted for <one@example.com>"]);
+    assert_eq!(second, ["250 OK: Message accepted for <two@example.com>"]);
+    // Rien de plus après les deux lignes : la réponse suivante est celle du NOOP
+    assert_eq!(client.command("NOOP"), ["250 OK"]);
+}
+
+#[test]
+fn too_many_data_lines_are_discarded() {
+    let honeypot = Honeypot::start(&["--max-data-lines", "100"]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    
+    let body = format!("Subject: lines\r\n\r\n{}", vec!["x"; 5000].join("\r\n"));
+    let reply = client.send_message("a@b.example", &["user@example.com"], &body);
+    assert!(reply[0].starts_with("552"), "{:?}", reply);
+    
+    // Session toujours utilisable, message suivant dans la limite enregistré
+    let reply = client.send_message("a@b.example", &["user@example.com"], "Subject: short\r\n\r\nbody");
+    assert!(reply[0].starts_with("250"), "{:?}", reply);
+    let captures = honeypot.wait_for_captures(1);
+    assert_eq!(captures.len(), 1);
+    assert!(String::from_utf8_lossy(&std::fs::read(&captures[0]).unwrap()).contains("Subject: short"));
+}
+
+#[test]
+fn noop_flood_is_disconnected() {
+    let honeypot = Honeypot::start(&["--max-commands", "20"]);
+    let mut client = honeypot.connect();
+    
+    let mut last = Vec::new();
+    for _ in 0..20 {
+        last = client.command("NOOP");
+    }
+    assert_eq!(last, ["250 OK"]);
+    assert_eq!(client.command("NOOP"), ["421 Too many commands"]);
+    assert!(client.closed_within(Duration::from_secs(2)));
+}
+
+#[test]
+fn two_messages_on_one_connection_give_two_files() {
+    let honeypot = Honeypot::start(&[]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    
+    for subject in ["first", "second"] {
+        let reply = client.send_message("a@b.example", &["user@example.com"], &format!("Subject: {}\r\n\r\nbody", subject));
+        assert!(reply[0].starts_with("250"), "{:?}", reply);
+    }
+    
+    let captures = honeypot.wait_for_captures(2);
+    assert_eq!(captures.len(), 2, "{:?}", captures);
+    let names: Vec<String> = captures.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    assert!(names[0].ends_with("_m1.eml") && names[1].ends_with("_m2.eml"), "{:?}", names);
+    let contents: Vec<String> = captures.iter().map(|path| String::from_utf8_lossy(&std::fs::read(path).unwrap()).into_owned()).collect();
+    assert!(contents[0].contains("Subject: first") && contents[1].contains("Subject: second"));
+}
+
+#[test]
+fn helo_gets_a_single_line() {
+    let honeypot = Honeypot::start(&[]);
+    let mut client = honeypot.connect();
+    
+    let helo = client.command("HELO client.example.org");
+    assert_eq!(helo.len(), 1, "{:?}", helo);
+    assert!(helo[0].starts_with("250 ") && helo[0].ends_with("Hello client.example.org"), "{:?}", helo);
+    // La ligne suivante est bien la réponse au NOOP, pas une extension en attente
+    assert_eq!(client.command("NOOP"), ["250 OK"]);
+    
+    let ehlo = client.command("EHLO client.example.org");
+    assert!(ehlo.len() > 1 && ehlo[0].starts_with("250-"), "{:?}", ehlo);
+}
+
+#[test]
+fn overlong_rcpt_is_rejected_and_logged() {
+    let honeypot = Honeypot::start(&[]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    client.command("MAIL FROM:<a@b.example>");
+    
+    // Au-delà de 512 octets : 501, la session continue
+    assert_eq!(client.command(&format!("RCPT TO:<{}@example.com>", "A".repeat(1500))), ["501 Command line too long"]);
+    assert!(client.command("RCPT TO:<user@example.com>")[0].starts_with("250"));
+    let output = honeypot.wait_for_output("Oversized command argument: RCPT line of");
+    assert!(output.contains("first bytes: RCPT TO:<AAAA"), "{}", output);
+    
+    // Au-delà de la limite de ligne : 500 et fermeture, toujours classé par verbe
+    client.send(format!("RCPT TO:<{}@example.com>\r\n", "B".repeat(10_000)).as_bytes());
+    assert_eq!(client.reply(), ["500 Line too long"]);
+    assert!(client.closed_within(Duration::from_secs(2)));
+    let output = honeypot.wait_for_output("first bytes: RCPT TO:<BBBB");
+    assert_eq!(output.matches("Oversized command argument: RCPT").count(), 2, "{}", output);
+}
+
+#[test]
+fn plaintext_on_implicit_tls_port_is_logged_on_one_line() {
+    let dir = std::env::temp_dir().join(format!("smtp-honeypot-implicit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pem = common::self_signed_pem(&dir);
+    
+    let port = common::free_port();
+    // Liste séparée par des virgules, comme --starttls-port
+    let honeypot = Honeypot::start_on(port, &["--tls-pem", pem.to_str().unwrap(), "--implicit-tls-port", &format!("465,{}", port)]);
+    let mut client = common::Client::open(port);
+    client.send(b"EHLO probe.example.org\r\nMAIL FROM:<\xffx@example.org>\r\n");
+    assert!(client.closed_within(Duration::from_secs(5)));
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    let output = honeypot.wait_for_output("Plaintext on implicit-TLS port");
+    let line = output.lines().find(|line| line.contains("Plaintext on implicit-TLS port")).unwrap();
+    assert!(line.contains("EHLO probe.example.org\\r\\nMAIL FROM:<\\xffx@example.org>\\r\\n"), "{}", line);
+}
+
+#[test]
+fn body_signature_past_spill_threshold_is_matched() {
+    let honeypot = Honeypot::start(&["--spill-threshold", "1024", "--body-signature", "NEEDLE-[0-9]{4}"]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    
+    let filler = "filler line of the message body\r\n".repeat(3000);
+    let body = format!("Subject: large\r\n\r\n{}NEEDLE-1234\r\n", filler);
+    let reply = client.send_message("a@b.example", &["user@example.com"], &body);
+    assert!(reply[0].starts_with("250"), "{:?}", reply);
+    honeypot.wait_for_output("body signature matched: custom-1");
+}
+
+#[test]
+fn long_helo_keeps_capture_headers_under_998_octets() {
+    let honeypot = Honeypot::start(&[]);
+    let mut client = honeypot.connect();
+    client.command(&format!("EHLO {}", "h".repeat(2000)));
+    let reply = client.send_message("a@b.example", &["user@example.com"], "Subject: helo\r\n\r\nbody");
+    assert!(reply[0].starts_with("250"), "{:?}", reply);
+    
+    let captures = honeypot.wait_for_captures(1);
+    let raw = std::fs::read(&captures[0]).unwrap();
+    assert!(raw.split(|b| *b == b'\n').all(|line| line.len() <= 1000), "line over 998 octets");
+    let message = mail_parser::MessageParser::default().parse(&raw[..]).expect("capture does not parse");
+    let helo = message.header("X-Honeypot-HELO").and_then(|value| value.as_text()).unwrap_or_default();
+    assert!(helo.starts_with("hhhh") && helo.ends_with("..."), "{}", helo);
+}
+
+#[test]
+fn storage_recovers_after_a_failed_save() {
+    let honeypot = Honeypot::start(&[]);
+    let data = honeypot.dir.join("data");
+    
+    // Un fichier à la place du répertoire : l'écriture échoue, même pour root
+    std::fs::remove_dir_all(&data).unwrap();
+    std::fs::write(&data, b"not a directory").unwrap();
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    client.send_message("a@b.example", &["user@example.com"], "Subject: lost\r\n\r\nbody");
+    honeypot.wait_for_output("Storage unwritable");
+    
+    std::fs::remove_file(&data).unwrap();
+    std::fs::create_dir(&data).unwrap();
+    client.send_message("a@b.example", &["user@example.com"], "Subject: saved\r\n\r\nbody");
+    honeypot.wait_for_output("Storage writable again");
+    assert_eq!(honeypot.wait_for_captures(1).len(), 1);
+}
+
+#[test]
+fn throttled_mail_emits_a_rejection_event() {
+    let honeypot = Honeypot::start(&["--adaptive-throttle", "0", "--stdout-format", "ndjson"]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    assert!(client.command("MAIL FROM:<a@b.example>")[0].starts_with("450"));
+    let output = honeypot.wait_for_output("\"reason\":\"throttled\"");
+    assert!(output.lines().any(|line| line.contains("\"reason\":\"throttled\"") && line.contains("rejection")), "{}", output);
+}
+
+#[test]
+fn throttling_disengage_is_logged_without_new_commands() {
+    let honeypot = Honeypot::start(&["--adaptive-throttle", "0"]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    assert!(client.command("MAIL FROM:<a@b.example>")[0].starts_with("450"));
+    honeypot.wait_for_output("Adaptive throttling engaged");
+    client.command("QUIT");
+    drop(client);
+    honeypot.wait_for_output("Adaptive throttling disengaged");
+}
+
+#[test]
+fn persona_is_chosen_from_the_ehlo_domain() {
+    let honeypot = Honeypot::start(&["--domain-persona", "acme.example:helo=mx.acme.example;reject=550 5.1.1 acme says no",
+                                     "--domain-persona", "other.example:helo=mx.other.example"]);
+    let mut client = honeypot.connect();
+    let ehlo = client.command("EHLO relay.acme.example");
+    assert!(ehlo[0].starts_with("250-mx.acme.example "), "{:?}", ehlo);
+    assert!(client.command("MAIL FROM:<a@b.example>")[0].starts_with("250"));
+    assert_eq!(client.command("RCPT TO:<nobody@unknown.example>"), ["550 5.1.1 acme says no"]);
+    
+    // Domaine inconnu : réglages globaux
+    let mut client = honeypot.connect();
+    let ehlo = client.command("EHLO client.example.org");
+    assert!(!ehlo[0].contains("acme") && !ehlo[0].contains("other"), "{:?}", ehlo);
+}
+
+#[cfg(unix)]
+#[test]
+fn sigusr1_dumps_state_to_the_log() {
+    // La sonde de démarrage du banc compte pour une connexion
+    let honeypot = Honeypot::start(&["--max-connections", "2"]);
+    drop(honeypot.connect());
+    let mut refused = Client::open(honeypot.port);
+    refused.closed_within(Duration::from_secs(5));
+    
+    let status = std::process::Command::new("kill").args(["-USR1", &honeypot.pid().to_string()]).status().unwrap();
+    assert!(status.success());
+    let output = honeypot.wait_for_output("State dump:");
+    let dump = output.lines().find(|line| line.contains("State dump:")).unwrap();
+    assert!(dump.contains("active_sessions=") && dump.contains("rate_limit_top=127.0.0.1=1"), "{}", dump);
+}
+
+#[cfg(unix)]
+#[test]
+fn reuseport_runs_several_accept_loops_and_shares_the_port() {
+    let honeypot = Honeypot::start(&["--reuseport", "--accept-loops", "2"]);
+    honeypot.wait_for_output("(SO_REUSEPORT, 2 accept loop(s))");
+    
+    // Deuxième instance sur le même port : le noyau répartit les connexions entre les deux
+    let second = Honeypot::start_on(honeypot.port, &["--reuseport"]);
+    second.wait_for_output("(SO_REUSEPORT, 1 accept loop(s))");
+    let mut client = second.connect();
+    assert!(client.command("QUIT")[0].starts_with("221"));
+}
+
+#[test]
+fn compact_stdout_holds_only_connection_lines() {
+    let honeypot = Honeypot::start(&["--stdout-format", "compact"]);
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    client.command("QUIT");
+    drop(client);
+    
+    let output = honeypot.wait_for_output("\tclient.example.org\t");
+    assert!(output.lines().all(|line| line.split('\t').count() == 10), "{}", output);
+    assert!(honeypot.stderr().contains("[INFO] Waiting for connections..."));
+}
+
+#[test]
+fn retry_after_a_failed_save_is_stored() {
+    let honeypot = Honeypot::start(&["--dedup-retries", "1h"]);
+    let data = honeypot.dir.join("data");
+    let body = "Subject: retried\r\n\r\nsame body";
+    
+    std::fs::remove_dir_all(&data).unwrap();
+    std::fs::write(&data, b"not a directory").unwrap();
+    let mut client = honeypot.connect();
+    client.command("EHLO client.example.org");
+    client.send_message("a@b.example", &["user@example.com"], body);
+    honeypot.wait_for_output("Storage unwritable");
+    
+    // Le renvoi du message perdu est enregistré, seul un renvoi après succès est un doublon
+    std::fs::remove_file(&data).unwrap();
+    std::fs::create_dir(&data).unwrap();
+    client.send_message("a@b.example", &["user@example.com"], body);
+    assert_eq!(honeypot.wait_for_captures(1).len(), 1);
+    client.send_message("a@b.example", &["user@example.com"], body);
+    let output = honeypot.wait_for_output("duplicate not stored");
+    assert_eq!(output.matches("duplicate not stored").count(), 1, "{}", output);
+}
+
+#[test]
+fn advertised_size_is_enforced_without_capture() {
+    // Graine 0 : profil annonçant SIZE 10240000
+    for policy in ["discard", "hash-only"] {
+        let honeypot = Honeypot::start(&["--randomize-persona", "--persona-seed", "0", "--data-policy", policy]);
+        let mut client = honeypot.connect();
+        let ehlo = client.command("EHLO client.example.org");
+        assert!(ehlo.iter().any(|line| line.ends_with("SIZE 10240000")), "{:?}", ehlo);
+        
+        let body = format!("Subject: large\r\n\r\n{}", format!("{}\r\n", "x".repeat(998)).repeat(11_000));
+        let reply = client.send_message("a@b.example", &["user@example.com"], &body);
+        assert_eq!(reply, ["552 Message size exceeds fixed maximum message size"], "policy {}", policy);
+        honeypot.wait_for_output("exceed the advertised SIZE of 10240000");
+    }
+}